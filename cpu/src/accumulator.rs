@@ -0,0 +1,459 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use rayon::prelude::*;
+
+use matrix_base::{RowPartition, CSR};
+
+use crate::context::chunks_per_thread;
+use crate::semiring::{PlusTimes, Semiring};
+
+
+// Accumulators for one row of C = A*B in the Gustavson (row-by-row) SpGEMM,
+// see section 5 of "A Systematic Survey of General Sparse Matrix-Matrix Multiplication", Gao et al.
+// https://doi.org/10.1145/3571157
+//
+// flops(i) = \sum_{k \in I_i(A)} nnz(B_{k*}) is the number of products of row i.
+//
+// Dense: dense array of length n plus a list of touched columns (SPA).
+//        O(flops + nnz log nnz) per row, n memory per thread.
+// Hash:  open addressing hash table sized to flops(i), sorted at the end.
+//        O(flops + nnz log nnz) per row, memory independent of n.
+// Esc:   expand all products into a list, (stable) sort by column, compress.
+//        O(flops log flops) per row, good for rows with very few products.
+// Heap:  k-way merge of the rows B_{k*}, k \in I_i(A), with a binary heap.
+//        O(flops log nnz(A_{i*})) per row, good for short rows of A.
+// Auto:  pick one of the above per row from flops(i), nnz(A_{i*}) and n.
+//        Heap only if the rows B_{k*}, k \in I_i(A), are sorted.
+//
+// All accumulators add up the products of a column in the same order
+// (increasing position in A_{i*}), so they agree up to the sign of zeros.
+// Heap needs sorted rows of B, the others do not.
+//
+// The row loop is generic over the semiring (see semiring.rs), "add" and
+// "products" above are its add and mul. A column takes the value of its
+// first product, so the identity of add is never needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Accumulator {
+    Dense,
+    Hash,
+    Esc,
+    Heap,
+    Auto
+}
+
+
+
+// Thresholds for Accumulator::Auto
+const AUTO_ESC_MAX_FLOPS: usize = 32;
+const AUTO_HEAP_MAX_ROW_NNZ: usize = 4;
+// Dense wins once the row touches a noticeable fraction of all n columns
+const AUTO_DENSE_FLOPS_FACTOR: usize = 16;
+
+
+
+// Number of products for the row i of A*B, given the column indices of A_{i*}
+pub fn row_flops(a_cols: &[usize], b: &CSR) -> usize {
+    a_cols.iter().map(|k| b.get_row_nnz(*k)).sum()
+}
+
+
+
+// Flop-balanced partition of the rows of A (see matrix_base::partition),
+// drives the parallel kernels for C = A*B. The number of chunks follows
+// the pool and granularity of the current ExecContext (see context).
+pub fn spgemm_partition(a: &CSR, b: &CSR) -> RowPartition {
+    RowPartition::for_product(a, b, rayon::current_num_threads() * chunks_per_thread())
+}
+
+
+
+// row_pos of C = A*B: parallel count of nnz(C_{i*}) and a prefix sum.
+// With it, all rows of C can be written in parallel into disjoint
+// slices of a preallocated col_pos / values.
+pub(crate) fn product_row_pos_par(a: &CSR, b: &CSR, partition: &RowPartition) -> Vec<usize> {
+    let n = b.shape.1;
+
+    let counts: Vec<usize> = a.par_rows_by(partition)
+        .map_init(|| SpGemmWorkspace::new(n), |ws, (_, a_cols, _)| ws.row_nnz(a_cols, b, |_| true))
+        .collect();
+
+    row_pos_from_counts(&counts)
+}
+
+
+
+// Prefix sum over the row counts
+pub(crate) fn row_pos_from_counts(counts: &[usize]) -> Vec<usize> {
+    let mut row_pos = Vec::with_capacity(counts.len()+1);
+    row_pos.push(0);
+    for c in counts {
+        row_pos.push(row_pos.last().unwrap() + c);
+    }
+    row_pos
+}
+
+
+
+impl Accumulator {
+    // Resolve Auto for one row, all other variants are returned unchanged
+    pub fn choose(self, a_cols: &[usize], b: &CSR) -> Accumulator {
+        if self != Accumulator::Auto {
+            return self;
+        }
+
+        let flops = row_flops(a_cols, b);
+        let n = b.shape.1;
+
+        if flops <= AUTO_ESC_MAX_FLOPS {
+            Accumulator::Esc
+        } else if flops * AUTO_DENSE_FLOPS_FACTOR >= n {
+            Accumulator::Dense
+        } else if a_cols.len() <= AUTO_HEAP_MAX_ROW_NNZ && a_cols.iter().all(|k| row_sorted(b, *k)) {
+            Accumulator::Heap
+        } else {
+            Accumulator::Hash
+        }
+    }
+}
+
+
+
+// The check for Heap reads the same O(flops) column indices as the merge
+fn row_sorted(b: &CSR, k: usize) -> bool {
+    b.row_slices(k).0.windows(2).all(|w| w[0] <= w[1])
+}
+
+
+
+// Per-thread workspace holding all accumulators.
+// Buffers are allocated on first use and reused for all following rows,
+// so a thread allocates O(n) (Dense) or O(max flops) (others) once.
+pub(crate) struct SpGemmWorkspace {
+    n: usize,
+    dense: Option<DenseAccumulator>,
+    hash: HashAccumulator,
+    esc: Vec<(usize, f64)>,
+    heap: HeapAccumulator,
+    scratch: Vec<f64>
+}
+
+
+impl SpGemmWorkspace {
+    pub(crate) fn new(n: usize) -> Self {
+        SpGemmWorkspace{n, dense: None, hash: HashAccumulator::new(), esc: vec![], heap: HeapAccumulator::new(), scratch: vec![]}
+    }
+
+
+    // C_{i*} = \sum_{k \in I_i (A)} a_{ik} B_{k*}
+    // where A_{i*} is given by (a_cols, a_vals), sum and product of the semiring S.
+    // The sorted column indices and values of C_{i*} are appended to cols and vals.
+    pub(crate) fn row_product<S: Semiring>(&mut self, acc: Accumulator, a_cols: &[usize], a_vals: &[f64], b: &CSR,
+        cols: &mut Vec<usize>, vals: &mut Vec<f64>) {
+        self.expand_row::<S>(acc, a_cols, b, cols, vals, |l, b_kj| S::mul(a_vals[l], b_kj), |_| true);
+    }
+
+
+    // Same as row_product, but only columns j with keep(j) are accumulated,
+    // all other products are skipped (masked SpGEMM, see mask.rs)
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn row_product_filtered<S: Semiring>(&mut self, acc: Accumulator, a_cols: &[usize], a_vals: &[f64], b: &CSR,
+        cols: &mut Vec<usize>, vals: &mut Vec<f64>, keep: impl Fn(usize) -> bool) {
+        self.expand_row::<S>(acc, a_cols, b, cols, vals, |l, b_kj| S::mul(a_vals[l], b_kj), keep);
+    }
+
+
+    // Only the sorted column indices of C_{i*}, i.e. the symbolic phase
+    pub(crate) fn row_structure(&mut self, acc: Accumulator, a_cols: &[usize], b: &CSR, cols: &mut Vec<usize>) {
+        let mut vals = std::mem::take(&mut self.scratch);
+        self.expand_row::<PlusTimes>(acc, a_cols, b, cols, &mut vals, |_, _| 0., |_| true);
+        vals.clear();
+        self.scratch = vals;
+    }
+
+
+    // nnz(C_{i*}) only, for the row count pass before the results are written.
+    // No sorting needed: dense marker for rows with many products, hash keys otherwise.
+    // Only columns j with keep(j) are counted.
+    pub(crate) fn row_nnz(&mut self, a_cols: &[usize], b: &CSR, keep: impl Fn(usize) -> bool) -> usize {
+        let flops = row_flops(a_cols, b);
+        if flops == 0 {
+            return 0;
+        }
+
+        if flops * AUTO_DENSE_FLOPS_FACTOR >= self.n {
+            let n = self.n;
+            let dense = self.dense.get_or_insert_with(|| DenseAccumulator::new(n));
+            for k in a_cols {
+                for (j, _) in b.row(*k).filter(|(j, _)| keep(*j)) {
+                    dense.add::<PlusTimes>(j, 0.);
+                }
+            }
+            dense.clear()
+        } else {
+            self.hash.reserve(flops);
+            for k in a_cols {
+                for (j, _) in b.row(*k).filter(|(j, _)| keep(*j)) {
+                    self.hash.add::<PlusTimes>(j, 0.);
+                }
+            }
+            self.hash.clear()
+        }
+    }
+
+
+    // Gustavson row loop shared by the numeric and the symbolic phase.
+    // mult(l, b_kj) gives the product for the l-th entry of A_{i*} and b_kj,
+    // products for columns j without keep(j) are skipped.
+    #[allow(clippy::too_many_arguments)]
+    fn expand_row<S: Semiring>(&mut self, acc: Accumulator, a_cols: &[usize], b: &CSR,
+        cols: &mut Vec<usize>, vals: &mut Vec<f64>, mult: impl Fn(usize, f64) -> f64, keep: impl Fn(usize) -> bool) {
+        match acc.choose(a_cols, b) {
+            Accumulator::Dense => {
+                let n = self.n;
+                let dense = self.dense.get_or_insert_with(|| DenseAccumulator::new(n));
+                for (l, k) in a_cols.iter().enumerate() {
+                    for (j, b_kj) in b.row(*k).filter(|(j, _)| keep(*j)) {
+                        dense.add::<S>(j, mult(l, *b_kj));
+                    }
+                }
+                dense.drain(cols, vals);
+            }
+            Accumulator::Hash => {
+                self.hash.reserve(row_flops(a_cols, b));
+                for (l, k) in a_cols.iter().enumerate() {
+                    for (j, b_kj) in b.row(*k).filter(|(j, _)| keep(*j)) {
+                        self.hash.add::<S>(j, mult(l, *b_kj));
+                    }
+                }
+                self.hash.drain(cols, vals);
+            }
+            Accumulator::Esc => {
+                // Expand
+                for (l, k) in a_cols.iter().enumerate() {
+                    for (j, b_kj) in b.row(*k).filter(|(j, _)| keep(*j)) {
+                        self.esc.push((j, mult(l, *b_kj)));
+                    }
+                }
+                // Sort, stable to keep the summation order
+                self.esc.sort_by_key(|&(j, _)| j);
+                // Compress
+                compress_sorted::<S>(&self.esc, cols, vals);
+                self.esc.clear();
+            }
+            Accumulator::Heap => {
+                self.heap.merge::<S>(a_cols, b, cols, vals, mult, keep);
+            }
+            Accumulator::Auto => unreachable!("Auto is resolved by choose()")
+        }
+    }
+}
+
+
+
+// Append sorted (j, x) pairs to cols / vals, summing up equal j
+fn compress_sorted<S: Semiring>(pairs: &[(usize, f64)], cols: &mut Vec<usize>, vals: &mut Vec<f64>) {
+    let start = cols.len();
+    for &(j, x) in pairs {
+        if cols.len() > start && *cols.last().unwrap() == j {
+            let last = vals.last_mut().unwrap();
+            *last = S::add(*last, x);
+        } else {
+            cols.push(j);
+            vals.push(x);
+        }
+    }
+}
+
+
+
+const DENSE_SCAN_FACTOR: usize = 16;
+
+
+// Sparse accumulator (SPA): dense values, marker and the list of touched columns.
+// Only touched columns are reset after a row.
+struct DenseAccumulator {
+    values: Vec<f64>,
+    marker: Vec<bool>,
+    touched: Vec<usize>
+}
+
+
+impl DenseAccumulator {
+    fn new(n: usize) -> Self {
+        DenseAccumulator{values: vec![0.; n], marker: vec![false; n], touched: vec![]}
+    }
+
+    fn add<S: Semiring>(&mut self, j: usize, x: f64) {
+        if !self.marker[j] {
+            self.marker[j] = true;
+            self.touched.push(j);
+            self.values[j] = x;
+        } else {
+            self.values[j] = S::add(self.values[j], x);
+        }
+    }
+
+    // Reset without output, returns the number of touched columns
+    fn clear(&mut self) -> usize {
+        let nnz = self.touched.len();
+        for &j in &self.touched {
+            self.values[j] = 0.;
+            self.marker[j] = false;
+        }
+        self.touched.clear();
+        nnz
+    }
+
+    fn drain(&mut self, cols: &mut Vec<usize>, vals: &mut Vec<f64>) {
+        let n = self.values.len();
+
+        // For (almost) dense rows a scan over all n columns is cheaper than sorting
+        if self.touched.len() * DENSE_SCAN_FACTOR >= n {
+            for j in 0..n {
+                if self.marker[j] {
+                    cols.push(j);
+                    vals.push(self.values[j]);
+                    self.values[j] = 0.;
+                    self.marker[j] = false;
+                }
+            }
+            self.touched.clear();
+        } else {
+            self.touched.sort_unstable();
+            for &j in &self.touched {
+                cols.push(j);
+                vals.push(self.values[j]);
+                self.values[j] = 0.;
+                self.marker[j] = false;
+            }
+            self.touched.clear();
+        }
+    }
+}
+
+
+
+// Open addressing hash table with linear probing, capacity is a power of two
+// and at least twice the number of products of the current row.
+struct HashAccumulator {
+    keys: Vec<usize>,
+    values: Vec<f64>,
+    used: Vec<usize>,
+    sorted: Vec<(usize, f64)>
+}
+
+
+const HASH_EMPTY: usize = usize::MAX;
+// Multiplicative hashing, as in the hash SpGEMM of Nagasaka et al.
+const HASH_SCALE: usize = 107;
+
+
+impl HashAccumulator {
+    fn new() -> Self {
+        HashAccumulator{keys: vec![], values: vec![], used: vec![], sorted: vec![]}
+    }
+
+    fn reserve(&mut self, flops: usize) {
+        let cap = (2*flops).next_power_of_two().max(16);
+        if self.keys.len() < cap {
+            self.keys = vec![HASH_EMPTY; cap];
+            self.values = vec![0.; cap];
+        }
+    }
+
+    fn add<S: Semiring>(&mut self, j: usize, x: f64) {
+        let mask = self.keys.len() - 1;
+        let mut slot = j.wrapping_mul(HASH_SCALE) & mask;
+        loop {
+            if self.keys[slot] == j {
+                self.values[slot] = S::add(self.values[slot], x);
+                return;
+            }
+            if self.keys[slot] == HASH_EMPTY {
+                self.keys[slot] = j;
+                self.values[slot] = x;
+                self.used.push(slot);
+                return;
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    // Reset without output, returns the number of distinct keys
+    fn clear(&mut self) -> usize {
+        let nnz = self.used.len();
+        for &slot in &self.used {
+            self.keys[slot] = HASH_EMPTY;
+        }
+        self.used.clear();
+        nnz
+    }
+
+    fn drain(&mut self, cols: &mut Vec<usize>, vals: &mut Vec<f64>) {
+        for &slot in &self.used {
+            self.sorted.push((self.keys[slot], self.values[slot]));
+            self.keys[slot] = HASH_EMPTY;
+        }
+        self.used.clear();
+
+        self.sorted.sort_unstable_by_key(|&(j, _)| j);
+        for &(j, x) in &self.sorted {
+            cols.push(j);
+            vals.push(x);
+        }
+        self.sorted.clear();
+    }
+}
+
+
+
+// k-way merge of the rows B_{k*}, k \in I_i(A).
+// The heap holds (column, list) of the current head of every list. Ties are
+// broken by the list index, i.e. by the position in A_{i*}.
+struct HeapAccumulator {
+    heap: BinaryHeap<Reverse<(usize, usize)>>,
+    cursor: Vec<usize>
+}
+
+
+impl HeapAccumulator {
+    fn new() -> Self {
+        HeapAccumulator{heap: BinaryHeap::new(), cursor: vec![]}
+    }
+
+    fn merge<S: Semiring>(&mut self, a_cols: &[usize], b: &CSR, cols: &mut Vec<usize>, vals: &mut Vec<f64>,
+        mult: impl Fn(usize, f64) -> f64, keep: impl Fn(usize) -> bool) {
+        self.cursor.clear();
+        for (l, k) in a_cols.iter().enumerate() {
+            let p = b.row_pos[*k];
+            self.cursor.push(p);
+            if p < b.row_pos[*k+1] {
+                self.heap.push(Reverse((b.col_pos[p], l)));
+            }
+        }
+
+        let start = cols.len();
+        while let Some(Reverse((j, l))) = self.heap.pop() {
+            let p = self.cursor[l];
+            if keep(j) {
+                let x = mult(l, b.values[p]);
+
+                if cols.len() > start && *cols.last().unwrap() == j {
+                    let last = vals.last_mut().unwrap();
+                    *last = S::add(*last, x);
+                } else {
+                    cols.push(j);
+                    vals.push(x);
+                }
+            }
+
+            // Advance list l
+            let k = a_cols[l];
+            self.cursor[l] += 1;
+            if self.cursor[l] < b.row_pos[k+1] {
+                self.heap.push(Reverse((b.col_pos[self.cursor[l]], l)));
+            }
+        }
+    }
+}
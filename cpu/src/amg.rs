@@ -0,0 +1,369 @@
+use rayon::prelude::*;
+
+use matrix_base::{LinearOperator, CSR};
+
+use crate::chain::rap_product;
+use crate::direct::Cholesky;
+use crate::eigen::{power_iteration, EigenOptions};
+use crate::elementwise::SparseElementwise;
+use crate::precond::SsorSweep;
+use crate::solvers::{check_dims, norm, residual, Monitor, Preconditioner, SolverOptions, SolverReport, SolverStatus, VEC_MIN_LEN};
+use crate::sparse::SparseProd;
+
+
+// Smoothed aggregation algebraic multigrid for SPD A (Poisson-type problems), see
+// "Algebraic multigrid by smoothed aggregation for second and fourth order
+// elliptic problems", Vaněk, Mandel, Brezina
+// https://doi.org/10.1007/BF02238511
+//
+// Setup, level by level until the matrix has at most max_coarse rows:
+//   strength: j is strongly connected to i if |a_ij| >= theta sqrt(|a_ii a_jj|)
+//   aggregation: the three phases of the paper on the strength graph
+//   tentative prolongator T: one column per aggregate, the constant vector
+//             on the aggregate (the near null space of Poisson), normalized
+//   smoothing: P = (I - omega D^{-1} A) T with omega = 4/3 / rho(D^{-1} A),
+//             rho estimated by a few power iterations, enlarged if they did not converge
+//   Galerkin: A_c = P^T A P with the fused rap_product (chain.rs)
+// The coarsest matrix is factorized with the sparse Cholesky (direct.rs).
+//
+// Cycle: V-cycle with symmetric Gauss-Seidel or damped Jacobi as smoother.
+// With as many pre- as post-smoothing steps the V-cycle is a symmetric
+// operator, so Amg is a preconditioner for CG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoother {
+    // Triangular sweeps level-scheduled as in precond::Ssor, converges
+    // in about half the cycles of Jacobi
+    SymmetricGaussSeidel,
+    // x += omega D^{-1} (b - A x), omega as for the prolongator, fully parallel
+    Jacobi
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmgOptions {
+    // theta of the strength of connection, 0 makes every connection strong
+    pub strength_theta: f64,
+    // Levels including the coarsest one
+    pub max_levels: usize,
+    // Matrices with at most max_coarse rows are solved directly
+    pub max_coarse: usize,
+    pub smoother: Smoother,
+    pub presmooth: usize,
+    pub postsmooth: usize
+}
+
+
+impl Default for AmgOptions {
+    fn default() -> Self {
+        AmgOptions{strength_theta: 0.08, max_levels: 10, max_coarse: 500, smoother: Smoother::SymmetricGaussSeidel, presmooth: 1, postsmooth: 1}
+    }
+}
+
+
+
+const NONE: usize = usize::MAX;
+
+// Power iterations for the estimate of rho(D^{-1} A)
+const RHO_ITERATIONS: usize = 15;
+// Factor on an estimate of rho whose power iteration did not converge
+const RHO_SAFETY: f64 = 1.1;
+
+
+
+// Strongly connected off-diagonal entries of A (with their values), rows stay sorted
+pub fn strength_of_connection(a: &CSR, theta: f64) -> CSR {
+    assert_eq!(a.shape.0, a.shape.1, "Matrix is not square");
+    let diag = a.diagonal();
+
+    let mut row_pos = vec![0];
+    let mut col_pos = vec![];
+    let mut values = vec![];
+    for (i, cols, vals) in a.rows() {
+        for (j, a_ij) in cols.iter().zip(vals) {
+            if *j != i && a_ij.abs() >= theta * (diag[i] * diag[*j]).abs().sqrt() {
+                col_pos.push(*j);
+                values.push(*a_ij);
+            }
+        }
+        row_pos.push(values.len());
+    }
+
+    CSR{row_pos, col_pos, values, shape: a.shape}
+}
+
+
+
+// Aggregate of every node for the (symmetric) strength matrix S, and the number of aggregates.
+// 1. every node whose strong neighbors are all free forms an aggregate with them
+// 2. the remaining nodes join an aggregate of phase 1 they are strongly connected to
+// 3. the rest forms new aggregates with its free strong neighbors
+// Isolated nodes end up as aggregates of their own in phase 1.
+pub fn aggregate(s: &CSR) -> (Vec<usize>, usize) {
+    let n = s.shape.0;
+    let mut agg = vec![NONE; n];
+    let mut n_agg = 0;
+
+    for i in 0..n {
+        let (cols, _) = s.row_slices(i);
+        if agg[i] == NONE && cols.iter().all(|j| agg[*j] == NONE) {
+            agg[i] = n_agg;
+            for j in cols {
+                agg[*j] = n_agg;
+            }
+            n_agg += 1;
+        }
+    }
+
+    let roots = agg.clone();
+    for (i, agg_i) in agg.iter_mut().enumerate() {
+        if *agg_i == NONE {
+            if let Some(j) = s.row_slices(i).0.iter().find(|j| roots[**j] != NONE) {
+                *agg_i = roots[*j];
+            }
+        }
+    }
+
+    for i in 0..n {
+        if agg[i] == NONE {
+            agg[i] = n_agg;
+            for j in s.row_slices(i).0 {
+                if agg[*j] == NONE {
+                    agg[*j] = n_agg;
+                }
+            }
+            n_agg += 1;
+        }
+    }
+
+    (agg, n_agg)
+}
+
+
+
+// rho(D^{-1} A) = rho(D^{-1/2} A D^{-1/2}), the latter is symmetric, so the
+// Rayleigh quotient of the power iteration converges fast. The few iterations
+// may underestimate rho a bit, which the smoothing tolerates: omega lambda
+// stays below 2 for all eigenvalues lambda < 1.5 rho.
+// Without convergence the estimate is enlarged by RHO_SAFETY, but not beyond
+// the Gershgorin bound max_i \sum_j |s_ij|, which holds for every matrix.
+fn spectral_radius(a: &CSR, diag: &[f64]) -> f64 {
+    let scale: Vec<f64> = diag.iter().map(|d| 1. / d.sqrt()).collect();
+    let mut s = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+    s.scale_rows(&scale);
+    s.scale_cols(&scale);
+    let report = power_iteration(&s, &EigenOptions{tol: 1e-3, max_iter: RHO_ITERATIONS, ..Default::default()});
+    let rho = report.values[0].abs();
+    if report.converged() && rho.is_finite() {
+        return rho;
+    }
+    let gershgorin = s.rows().map(|(_, _, vals)| vals.iter().map(|x| x.abs()).sum::<f64>()).fold(0., f64::max);
+    if rho.is_finite() { (RHO_SAFETY * rho).min(gershgorin) } else { gershgorin }
+}
+
+
+
+enum LevelSmoother {
+    SymmetricGaussSeidel(SsorSweep),
+    // omega / a_ii
+    Jacobi(Vec<f64>)
+}
+
+
+// One level of the hierarchy, all but the coarsest one
+struct Level {
+    a: CSR,
+    smoother: LevelSmoother,
+    p: CSR,
+    r: CSR
+}
+
+
+impl Level {
+    // x += M^{-1} (b - A x) with the smoother M, r and z are buffers
+    fn smooth(&self, b: &[f64], x: &mut [f64], r: &mut [f64], z: &mut [f64]) {
+        residual(&self.a, b, x, r);
+        match &self.smoother {
+            LevelSmoother::SymmetricGaussSeidel(sweep) => {
+                sweep.apply(&self.a, r, z, true);
+                x.par_iter_mut().zip(&*z).with_min_len(VEC_MIN_LEN).for_each(|(x_i, z_i)| *x_i += z_i);
+            }
+            LevelSmoother::Jacobi(weights) => {
+                x.par_iter_mut().zip(&*r).zip(weights).with_min_len(VEC_MIN_LEN)
+                    .for_each(|((x_i, r_i), w_i)| *x_i += w_i * r_i);
+            }
+        }
+    }
+}
+
+
+
+pub struct Amg {
+    opts: AmgOptions,
+    levels: Vec<Level>,
+    coarse_a: CSR,
+    coarse: Cholesky
+}
+
+
+impl Amg {
+    // Err if A has a non-positive diagonal entry or the
+    // coarsest matrix is not positive definite
+    pub fn new(a: &CSR, opts: &AmgOptions) -> Result<Self, &'static str> {
+        assert_eq!(a.shape.0, a.shape.1, "Matrix is not square");
+        assert!(opts.max_levels > 0, "AMG needs at least one level");
+        let mut a = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+        let mut levels = vec![];
+
+        while a.shape.0 > opts.max_coarse && levels.len() + 1 < opts.max_levels {
+            let diag = a.diagonal();
+            if diag.iter().any(|d| *d <= 0.) {
+                return Err("AMG needs a positive diagonal");
+            }
+            let omega = 4. / 3. / spectral_radius(&a, &diag);
+            let jacobi: Vec<f64> = diag.iter().map(|d| omega / d).collect();
+
+            let (agg, n_agg) = aggregate(&strength_of_connection(&a, opts.strength_theta));
+            if n_agg == a.shape.0 {
+                // Nothing left to coarsen
+                break;
+            }
+            let p = smoothed_prolongator(&a, &agg, n_agg, &jacobi);
+            let r = p.transpose();
+            let a_c = rap_product(&r, &a, &p);
+            let smoother = match opts.smoother {
+                Smoother::SymmetricGaussSeidel => LevelSmoother::SymmetricGaussSeidel(SsorSweep::new(&a, 1.)?),
+                Smoother::Jacobi => LevelSmoother::Jacobi(jacobi)
+            };
+            levels.push(Level{a, smoother, p, r});
+            a = a_c;
+        }
+
+        let coarse = Cholesky::new(&a)?;
+        Ok(Amg{opts: *opts, levels, coarse_a: a, coarse})
+    }
+
+
+    // Including the coarsest level
+    pub fn n_levels(&self) -> usize {
+        self.levels.len() + 1
+    }
+
+
+    // Rows of the matrix on every level, finest first
+    pub fn level_sizes(&self) -> Vec<usize> {
+        let mut sizes: Vec<usize> = self.levels.iter().map(|l| l.a.shape.0).collect();
+        sizes.push(self.coarse_a.shape.0);
+        sizes
+    }
+
+
+    // The matrix A the hierarchy was set up for (level 0)
+    pub fn matrix(&self) -> &CSR {
+        self.levels.first().map_or(&self.coarse_a, |l| &l.a)
+    }
+
+
+    // \sum_l nnz(A_l) / nnz(A), the memory and work of a cycle relative to A
+    pub fn operator_complexity(&self) -> f64 {
+        let nnz: usize = self.levels.iter().map(|l| l.a.values.len()).sum::<usize>() + self.coarse_a.values.len();
+        nnz as f64 / self.matrix().values.len() as f64
+    }
+
+
+    // One V-cycle for A x = b, x holds the initial guess and is updated
+    pub fn cycle(&self, b: &[f64], x: &mut [f64]) {
+        assert_eq!(b.len(), x.len(), "Matrix and vector dimensions do not match");
+        assert_eq!(b.len(), self.matrix().shape.0, "Matrix and vector dimensions do not match");
+        self.cycle_level(0, b, x);
+    }
+
+
+    fn cycle_level(&self, l: usize, b: &[f64], x: &mut [f64]) {
+        let Some(level) = self.levels.get(l) else {
+            x.copy_from_slice(&self.coarse.solve(b));
+            return;
+        };
+
+        let mut r = vec![0.; b.len()];
+        // Correction of the Gauss-Seidel sweeps, shared by all smoothing steps of the level
+        let mut z = match level.smoother {
+            LevelSmoother::SymmetricGaussSeidel(_) => vec![0.; b.len()],
+            LevelSmoother::Jacobi(_) => vec![]
+        };
+        for _ in 0..self.opts.presmooth {
+            level.smooth(b, x, &mut r, &mut z);
+        }
+
+        // Coarse grid correction x += P A_c^{-1} R (b - A x), A_c^{-1} by recursion
+        residual(&level.a, b, x, &mut r);
+        let mut b_c = vec![0.; level.r.shape.0];
+        level.r.apply(&r, &mut b_c);
+        let mut x_c = vec![0.; b_c.len()];
+        self.cycle_level(l + 1, &b_c, &mut x_c);
+        level.p.apply(&x_c, &mut r);
+        x.par_iter_mut().zip(&r).with_min_len(VEC_MIN_LEN).for_each(|(x_i, e_i)| *x_i += e_i);
+
+        for _ in 0..self.opts.postsmooth {
+            level.smooth(b, x, &mut r, &mut z);
+        }
+    }
+
+
+    // Standalone solver: V-cycles until ||b - A x|| <= max(rtol ||b||, atol)
+    // for the A of the setup, with the SolverReport of the Krylov solvers
+    // (one iteration per cycle)
+    pub fn solve(&self, b: &[f64], x: &mut [f64], opts: &SolverOptions) -> SolverReport {
+        let a = self.matrix();
+        check_dims(a, b, x);
+        let mut mon = Monitor::new(b, opts);
+
+        let mut r = vec![0.; b.len()];
+        residual(a, b, x, &mut r);
+        let mut r_norm = norm(&r);
+        if let Some(status) = mon.check(r_norm) {
+            return mon.report(status, 0, r_norm);
+        }
+
+        for k in 1..=opts.max_iter {
+            self.cycle(b, x);
+            residual(a, b, x, &mut r);
+            r_norm = norm(&r);
+            if let Some(status) = mon.check(r_norm) {
+                return mon.report(status, k, r_norm);
+            }
+        }
+
+        mon.report(SolverStatus::MaxIterations, opts.max_iter, r_norm)
+    }
+}
+
+
+// z = one V-cycle for A z = r from z = 0
+impl Preconditioner for Amg {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        z.fill(0.);
+        self.cycle(r, z);
+    }
+}
+
+
+
+// P = (I - omega D^{-1} A) T, jacobi holds omega / a_ii
+fn smoothed_prolongator(a: &CSR, agg: &[usize], n_agg: usize, jacobi: &[f64]) -> CSR {
+    let n = a.shape.0;
+    let mut size = vec![0usize; n_agg];
+    for g in agg {
+        size[*g] += 1;
+    }
+    let t = CSR{
+        row_pos: (0..=n).collect(),
+        col_pos: agg.to_vec(),
+        values: agg.iter().map(|g| 1. / (size[*g] as f64).sqrt()).collect(),
+        shape: (n, n_agg)
+    };
+
+    let mut at = a.product_sparse_par(&t);
+    at.scale_rows(jacobi);
+    t.sparse_add_par(1., &at, -1.)
+}
@@ -0,0 +1,333 @@
+use std::fmt;
+
+use rayon::prelude::*;
+
+use matrix_base::{product_row_flops, Dense, CSR};
+
+use crate::accumulator::{row_pos_from_counts, spgemm_partition, Accumulator, SpGemmWorkspace};
+use crate::dense::DenseProd;
+use crate::semiring::PlusTimes;
+use crate::sparse::SparseProd;
+
+
+// Products of chains M_0 M_1 ... M_{p-1}, e.g. Galerkin products R A P.
+// The multiplication order is chosen by the classic matrix-chain dynamic
+// program (Cormen et al., "Introduction to Algorithms", section 15.2):
+// cost(i,j) = min_k cost(i,k) + cost(k+1,j) + cost of (M_i..M_k)(M_{k+1}..M_j)
+//
+// Dense: the cost of X*Y is rows(X) * cols(X) * cols(Y), exact.
+// Sparse: the cost is the flops of X*Y, which depend on the (unknown) nnz of
+//         the intermediates. Assuming the entries of Y are spread evenly over its rows,
+//         flops(X*Y) = nnz(X) * nnz(Y) / rows(Y), with nnz estimated by NnzEstimator.
+
+
+
+// Estimates of nnz(M_i ... M_j) for the sparse chain order
+//
+// UpperBound: no two products fall on the same entry, nnz(X*Y) = min(flops, rows * cols).
+//             Exact flops for two input matrices (product_row_flops), longer
+//             chains estimate the flops as above.
+// Sampling(s): s evenly spaced rows of M_i are multiplied through the chain
+//              (structure only) and the row counts are scaled up by rows(M_i) / s.
+//              Exact for s >= rows(M_i), about s / rows(M_i) of the cost of the products otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NnzEstimator {
+    UpperBound,
+    Sampling(usize)
+}
+
+
+// Sampled rows per start of a chain for chain_product
+pub const CHAIN_SAMPLES: usize = 256;
+
+
+
+// Optimal order of a chain of p matrices.
+// split(i, j) = k means M_i..M_j is computed as (M_i..M_k)(M_{k+1}..M_j),
+// cost is the (estimated) number of flops of the whole chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainOrder {
+    split: Vec<Vec<usize>>,
+    pub cost: f64
+}
+
+
+impl ChainOrder {
+    pub fn n_matrices(&self) -> usize {
+        self.split.len()
+    }
+
+
+    pub fn split(&self, i: usize, j: usize) -> usize {
+        assert!(i < j && j < self.n_matrices(), "No product for the range {}..={}", i, j);
+        self.split[i][j]
+    }
+
+
+    // Classic DP, cost(i, k, j) is the cost of (M_i..M_k)(M_{k+1}..M_j)
+    fn optimal(p: usize, cost: impl Fn(usize, usize, usize) -> f64) -> Self {
+        assert!(p > 0, "Empty matrix chain");
+        let mut best = vec![vec![0.; p]; p];
+        let mut split = vec![vec![0; p]; p];
+
+        for len in 2..=p {
+            for i in 0..=p-len {
+                let j = i + len - 1;
+                best[i][j] = f64::INFINITY;
+                for k in i..j {
+                    let c = best[i][k] + best[k+1][j] + cost(i, k, j);
+                    if c < best[i][j] {
+                        best[i][j] = c;
+                        split[i][j] = k;
+                    }
+                }
+            }
+        }
+
+        ChainOrder{cost: best[0][p-1], split}
+    }
+
+
+    fn fmt_range(&self, f: &mut fmt::Formatter, i: usize, j: usize) -> fmt::Result {
+        if i == j {
+            return write!(f, "M{}", i);
+        }
+        let k = self.split(i, j);
+        write!(f, "(")?;
+        self.fmt_range(f, i, k)?;
+        write!(f, " ")?;
+        self.fmt_range(f, k+1, j)?;
+        write!(f, ")")
+    }
+}
+
+
+// Parenthesization, e.g. "(M0 (M1 M2))"
+impl fmt::Display for ChainOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_range(f, 0, self.n_matrices()-1)
+    }
+}
+
+
+
+fn check_chain(shapes: &[(usize, usize)]) {
+    assert!(!shapes.is_empty(), "Empty matrix chain");
+    for w in shapes.windows(2) {
+        assert_eq!(w[0].1, w[1].0, "Matrix dimensions do not match for multiplication");
+    }
+}
+
+
+
+// nnz[i][j] = estimated nnz(M_i ... M_j) for i <= j, exact for i == j
+pub fn estimate_chain_nnz(mats: &[&CSR], est: NnzEstimator) -> Vec<Vec<usize>> {
+    check_chain(&mats.iter().map(|m| m.shape).collect::<Vec<_>>());
+    let p = mats.len();
+    let mut nnz = vec![vec![0; p]; p];
+
+    for i in 0..p {
+        nnz[i][i] = mats[i].values.len();
+        match est {
+            NnzEstimator::UpperBound => {
+                for j in i+1..p {
+                    let flops = if j == i+1 {
+                        product_row_flops(mats[i], mats[j]).iter().sum()
+                    } else {
+                        avg_flops(nnz[i][j-1], mats[j])
+                    };
+                    nnz[i][j] = flops.min(mats[i].shape.0 * mats[j].shape.1);
+                }
+            }
+            NnzEstimator::Sampling(s) => {
+                let counts = sample_chain_nnz(&mats[i..], s);
+                nnz[i][i+1..].copy_from_slice(&counts);
+            }
+        }
+    }
+
+    nnz
+}
+
+
+
+// Flops of X*Y for nnz(X) = nnz_x, entries of Y spread evenly over its rows
+fn avg_flops(nnz_x: usize, y: &CSR) -> usize {
+    if y.shape.0 == 0 {
+        return 0;
+    }
+    (nnz_x as u128 * y.values.len() as u128 / y.shape.0 as u128) as usize
+}
+
+
+
+// Sampling estimate of nnz(M_0 ... M_j) for j = 1..p-1. Every sampled row
+// of M_0 is multiplied through the chain with the symbolic row product.
+fn sample_chain_nnz(mats: &[&CSR], samples: usize) -> Vec<usize> {
+    let p = mats.len();
+    let m = mats[0].shape.0;
+    let s = samples.min(m);
+    if s == 0 || p < 2 {
+        return vec![0; p-1];
+    }
+
+    let sums = (0..s).into_par_iter()
+    .map_init(|| (mats[1..].iter().map(|x| SpGemmWorkspace::new(x.shape.1)).collect::<Vec<_>>(), vec![], vec![]),
+        |(ws, row, next), t| {
+        // Evenly spaced rows, all rows for s == m
+        let i = (2*t + 1) * m / (2*s);
+        row.clear();
+        row.extend_from_slice(mats[0].row_slices(i).0);
+
+        let mut counts = vec![0; p-1];
+        for (j, x) in mats[1..].iter().enumerate() {
+            if row.is_empty() {
+                break;
+            }
+            ws[j].row_structure(Accumulator::Auto, row, x, next);
+            counts[j] = next.len();
+            std::mem::swap(row, next);
+            next.clear();
+        }
+        counts
+    })
+    .reduce(|| vec![0; p-1], |mut a, b| {
+        for (x, y) in a.iter_mut().zip(b) {
+            *x += y;
+        }
+        a
+    });
+
+    sums.iter().map(|c| (*c as u128 * m as u128 / s as u128) as usize).collect()
+}
+
+
+
+// Optimal order of a sparse chain, flops estimated from the nnz estimates
+pub fn sparse_chain_order(mats: &[&CSR], est: NnzEstimator) -> ChainOrder {
+    let nnz = estimate_chain_nnz(mats, est);
+    ChainOrder::optimal(mats.len(), |i, k, j| {
+        let rows_y = mats[k+1].shape.0;
+        if rows_y == 0 { 0. } else { nnz[i][k] as f64 * nnz[k+1][j] as f64 / rows_y as f64 }
+    })
+}
+
+
+
+// Optimal order of a dense chain, exact number of multiplications
+pub fn dense_chain_order(mats: &[&Dense]) -> ChainOrder {
+    let shapes: Vec<(usize, usize)> = mats.iter().map(|m| m.shape).collect();
+    check_chain(&shapes);
+    ChainOrder::optimal(mats.len(), |i, k, j| (shapes[i].0 * shapes[k].1 * shapes[j].1) as f64)
+}
+
+
+
+// M_0 M_1 ... M_{p-1}, in the order of sparse_chain_order with sampled nnz
+pub fn chain_product(mats: &[&CSR]) -> CSR {
+    chain_product_with(mats, NnzEstimator::Sampling(CHAIN_SAMPLES))
+}
+
+
+
+// Every product is a parallel SpGEMM, three input matrices multiplied
+// left to right, ((X Y) Z), use the fused kernel rap_product instead
+pub fn chain_product_with(mats: &[&CSR], est: NnzEstimator) -> CSR {
+    let order = sparse_chain_order(mats, est);
+    match sparse_chain_rec(mats, &order, 0, mats.len()-1) {
+        Some(c) => c,
+        None => {
+            let m = mats[0];
+            CSR{row_pos: m.row_pos.clone(), col_pos: m.col_pos.clone(), values: m.values.clone(), shape: m.shape}
+        }
+    }
+}
+
+
+// M_i ... M_j, None for a single matrix (i == j)
+fn sparse_chain_rec(mats: &[&CSR], order: &ChainOrder, i: usize, j: usize) -> Option<CSR> {
+    if i == j {
+        return None;
+    }
+    let k = order.split(i, j);
+    if j == i+2 && k == i+1 {
+        return Some(rap_product(mats[i], mats[i+1], mats[j]));
+    }
+
+    let left = sparse_chain_rec(mats, order, i, k);
+    let right = sparse_chain_rec(mats, order, k+1, j);
+    let x = left.as_ref().unwrap_or(mats[i]);
+    let y = right.as_ref().unwrap_or(mats[k+1]);
+    Some(x.product_sparse_par(y))
+}
+
+
+
+// M_0 M_1 ... M_{p-1}, in the order of dense_chain_order
+pub fn dense_chain_product(mats: &[&Dense]) -> Dense {
+    let order = dense_chain_order(mats);
+    match dense_chain_rec(mats, &order, 0, mats.len()-1) {
+        Some(c) => c,
+        None => Dense{data: mats[0].data.clone(), shape: mats[0].shape}
+    }
+}
+
+
+fn dense_chain_rec(mats: &[&Dense], order: &ChainOrder, i: usize, j: usize) -> Option<Dense> {
+    if i == j {
+        return None;
+    }
+    let k = order.split(i, j);
+    let left = dense_chain_rec(mats, order, i, k);
+    let right = dense_chain_rec(mats, order, k+1, j);
+    let x = left.as_ref().unwrap_or(mats[i]);
+    let y = right.as_ref().unwrap_or(mats[k+1]);
+    Some(x.product_dense_par(y))
+}
+
+
+
+// Fused triple product C = R*A*P, e.g. the Galerkin coarse operator in AMG.
+// For every row i: (RA)_{i*} = \sum_k r_ik A_{k*} in one accumulator,
+// then C_{i*} = \sum_l (RA)_{il} P_{l*} in a second one. RA is never stored,
+// only one of its rows per thread. Two passes as in the parallel SpGEMM
+// (see sparse.rs), the count pass uses the symbolic row of RA.
+pub fn rap_product(r: &CSR, a: &CSR, p: &CSR) -> CSR {
+    assert_eq!(r.shape.1, a.shape.0, "Matrix dimensions do not match for multiplication");
+    assert_eq!(a.shape.1, p.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = r.shape.0;
+    let k = a.shape.1;
+    let n = p.shape.1;
+
+    let partition = spgemm_partition(r, a);
+
+    let counts: Vec<usize> = r.par_rows_by(&partition)
+    .map_init(|| (SpGemmWorkspace::new(k), SpGemmWorkspace::new(n), vec![]), |(ws_ra, ws_c, ra_cols), (_, r_cols, _)| {
+        ws_ra.row_structure(Accumulator::Auto, r_cols, a, ra_cols);
+        let count = ws_c.row_nnz(ra_cols, p, |_| true);
+        ra_cols.clear();
+        count
+    })
+    .collect();
+
+    let row_pos = row_pos_from_counts(&counts);
+    let nnz = row_pos[m];
+    let mut res = CSR{row_pos, col_pos: vec![0; nnz], values: vec![0.; nnz], shape: (m,n)};
+
+    res.par_rows_pattern_mut_by(&partition)
+    .for_each_init(|| (SpGemmWorkspace::new(k), SpGemmWorkspace::new(n), vec![], vec![], vec![], vec![]),
+        |(ws_ra, ws_c, ra_cols, ra_vals, cols, vals), (i, res_cols, res_vals)| {
+        let (r_cols, r_vals) = r.row_slices(i);
+        ws_ra.row_product::<PlusTimes>(Accumulator::Auto, r_cols, r_vals, a, ra_cols, ra_vals);
+        ws_c.row_product::<PlusTimes>(Accumulator::Auto, ra_cols, ra_vals, p, cols, vals);
+
+        res_cols.copy_from_slice(cols);
+        res_vals.copy_from_slice(vals);
+        ra_cols.clear();
+        ra_vals.clear();
+        cols.clear();
+        vals.clear();
+    });
+
+    res
+}
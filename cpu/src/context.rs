@@ -0,0 +1,155 @@
+use std::cell::Cell;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+
+// Execution contexts for the parallel kernels. By default every kernel runs
+// on rayon's global pool, sized by RAYON_NUM_THREADS at the first use.
+// An ExecContext owns a pool of its own, every kernel called inside
+// ctx.install(|| ...) runs on it, so scaling studies can sweep the number
+// of threads in one process:
+//
+//     let ctx = ExecContext::new(ExecOptions{threads: 4, ..Default::default()})?;
+//     let c = ctx.install(|| a.product_sparse_par(&b));
+//
+// threads:           size of the pool, 0 for rayon's default (RAYON_NUM_THREADS
+//                    or one thread per available core)
+// pin:               worker t is pinned to the t-th core of the process'
+//                    affinity mask (round robin), Linux only, new fails if
+//                    a worker cannot be pinned
+// chunks_per_thread: granularity of the flop-balanced row partitions of the
+//                    SpGEMM kernels, more chunks give rayon more to steal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExecOptions {
+    pub threads: usize,
+    pub pin: bool,
+    pub chunks_per_thread: usize
+}
+
+
+impl Default for ExecOptions {
+    fn default() -> Self {
+        ExecOptions{threads: 0, pin: false, chunks_per_thread: DEFAULT_CHUNKS_PER_THREAD}
+    }
+}
+
+
+
+// Chunks per thread of the flop-balanced row partition, the slack
+// lets rayon steal work if the flops do not match the actual cost
+const DEFAULT_CHUNKS_PER_THREAD: usize = 4;
+
+
+thread_local! {
+    // Set by the start handler of the workers of an ExecContext
+    static CHUNKS_PER_THREAD: Cell<usize> = const { Cell::new(DEFAULT_CHUNKS_PER_THREAD) };
+}
+
+
+// Granularity for the current thread, the default outside of an ExecContext
+pub(crate) fn chunks_per_thread() -> usize {
+    CHUNKS_PER_THREAD.with(|c| c.get())
+}
+
+
+
+pub struct ExecContext {
+    pool: ThreadPool,
+    opts: ExecOptions
+}
+
+
+impl ExecContext {
+    pub fn new(opts: ExecOptions) -> Result<Self, &'static str> {
+        if opts.chunks_per_thread == 0 {
+            return Err("At least one chunk per thread is needed");
+        }
+        let cores = if opts.pin { Some(affinity::allowed_cores()?) } else { None };
+        let chunks = opts.chunks_per_thread;
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(opts.threads)
+            .thread_name(|t| format!("fakscpu-{}", t))
+            .start_handler(move |_| CHUNKS_PER_THREAD.with(|c| c.set(chunks)))
+            .build()
+            .map_err(|_| "Failed to build the thread pool")?;
+
+        // Pinned on the workers themselves, broadcast returns once every
+        // worker has run, so a failure on any of them is seen here
+        if let Some(cores) = &cores {
+            pool.broadcast(|w| affinity::pin_current(cores[w.index() % cores.len()]))
+                .into_iter()
+                .collect::<Result<Vec<()>, _>>()?;
+        }
+        Ok(ExecContext{pool, opts})
+    }
+
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+
+    pub fn options(&self) -> &ExecOptions {
+        &self.opts
+    }
+
+
+    // Runs f on the pool of the context, the parallel kernels called by f
+    // (rayon::current_num_threads, par_iter, join) use its workers
+    pub fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        self.pool.install(f)
+    }
+}
+
+
+
+// Cores in the affinity mask of the calling thread, a single one on the
+// workers of a pinned ExecContext. Linux only
+pub fn current_cores() -> Result<Vec<usize>, &'static str> {
+    affinity::allowed_cores()
+}
+
+
+
+#[cfg(target_os = "linux")]
+mod affinity {
+    use std::mem;
+
+    // Cores in the affinity mask of the calling thread
+    pub(super) fn allowed_cores() -> Result<Vec<usize>, &'static str> {
+        // SAFETY: cpu_set_t is a plain bit mask, zeroed it is the empty set
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        if unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+            return Err("Failed to read the CPU affinity mask");
+        }
+        let cores: Vec<usize> = (0..libc::CPU_SETSIZE as usize).filter(|c| unsafe { libc::CPU_ISSET(*c, &set) }).collect();
+        if cores.is_empty() {
+            return Err("Empty CPU affinity mask");
+        }
+        Ok(cores)
+    }
+
+
+    pub(super) fn pin_current(core: usize) -> Result<(), &'static str> {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        unsafe { libc::CPU_SET(core, &mut set) };
+        if unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+            return Err("Failed to pin the thread");
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(not(target_os = "linux"))]
+mod affinity {
+    pub(super) fn allowed_cores() -> Result<Vec<usize>, &'static str> {
+        Err("Reading the CPU affinity is only supported on Linux")
+    }
+
+
+    pub(super) fn pin_current(_core: usize) -> Result<(), &'static str> {
+        Err("Pinning threads to cores is only supported on Linux")
+    }
+}
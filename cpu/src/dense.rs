@@ -1,36 +1,204 @@
-use rayon::prelude::*;
 use matrix_base::Dense;
 
+use crate::gemm::{gemm, MatMut, MatRef};
+
 pub trait DenseProd {
     fn product_dense_par(&self, other: &Dense) -> Dense;
+    fn product_strassen(&self, other: &Dense, cutoff: usize) -> Dense;
+    fn product_winograd(&self, other: &Dense, cutoff: usize) -> Dense;
 }
 
+
+// Default cutoff for product_strassen / product_winograd: blocks with a
+// dimension at or below it are multiplied by the blocked kernel
+pub const STRASSEN_CUTOFF: usize = 256;
+
+
 impl DenseProd for Dense {
+    // Cache-blocked, packed GEMM, parallel over macro tiles of C (see gemm.rs)
     fn product_dense_par(&self, other: &Dense) -> Dense {
         let m = self.shape.0;
         let n = other.shape.1;
         let p = self.shape.1;
         assert_eq!(p, other.shape.0, "Matrix dimensions do not match for multiplication");
 
-        let result: Vec<Vec<f64>> = (0..m).into_par_iter().map(|i| {
-            let mut row = vec![0.0; n];
-            for j in 0..n {
-                let mut sum = 0.0;
-                for k in 0..p {
-                    sum += self.get(i, k) * other.get(k, j);
-                }
-                row[j] = sum;
-            }
-            row
-        }).collect();
-
-        let mut final_result = Dense::new_zeros((m, n));
-        for (i, row) in result.into_iter().enumerate() {
-            for (j, value) in row.into_iter().enumerate() {
-                final_result.set(i, j, value);
-            }
+        let mut result = Dense::new_zeros((m, n));
+        gemm(MatRef::from_dense(self), MatRef::from_dense(other), &mut MatMut::from_dense(&mut result));
+
+        result
+    }
+
+
+    // Strassen, "Gaussian elimination is not optimal", https://doi.org/10.1007/BF02165411
+    // 7 products and 18 additions per level
+    fn product_strassen(&self, other: &Dense, cutoff: usize) -> Dense {
+        assert_eq!(self.shape.1, other.shape.0, "Matrix dimensions do not match for multiplication");
+        fast_product(MatRef::from_dense(self), MatRef::from_dense(other), cutoff, FastVariant::Strassen)
+    }
+
+
+    // Strassen-Winograd variant, 7 products and 15 additions per level,
+    // see Boyer et al., "Memory efficient scheduling of Strassen-Winograd's
+    // matrix multiplication algorithm", https://doi.org/10.1145/1576702.1576713
+    // Slightly larger error than Strassen.
+    fn product_winograd(&self, other: &Dense, cutoff: usize) -> Dense {
+        assert_eq!(self.shape.1, other.shape.0, "Matrix dimensions do not match for multiplication");
+        fast_product(MatRef::from_dense(self), MatRef::from_dense(other), cutoff, FastVariant::Winograd)
+    }
+}
+
+
+
+// Relative error ||C - A*B||_F / ||A*B||_F of a product C of a fast algorithm,
+// A*B computed by the classic (blocked) algorithm
+pub fn product_error(a: &Dense, b: &Dense, c: &Dense) -> f64 {
+    let c_ref = a.product_dense_par(b);
+    assert_eq!(c.shape, c_ref.shape, "Product has the wrong shape");
+
+    let diff: f64 = c.data.iter().zip(&c_ref.data).map(|(x, y)| (x - y) * (x - y)).sum();
+    let norm: f64 = c_ref.data.iter().map(|x| x * x).sum();
+    if norm == 0. {
+        diff.sqrt()
+    } else {
+        (diff / norm).sqrt()
+    }
+}
+
+
+
+#[derive(Clone, Copy, PartialEq)]
+enum FastVariant {
+    Strassen,
+    Winograd
+}
+
+
+
+// C = A*B, recursively on 2x2 blocks. Odd dimensions are handled by dynamic
+// peeling: the even (m-1, k-1, n-1) part goes into the recursion, the
+// last row / column are added with the blocked kernel afterwards.
+fn fast_product(a: MatRef, b: MatRef, cutoff: usize, variant: FastVariant) -> Dense {
+    let (m, k, n) = (a.rows, a.cols, b.cols);
+    let mut c = Dense::new_zeros((m, n));
+
+    if m.min(k).min(n) <= cutoff.max(1) {
+        gemm(a, b, &mut MatMut::from_dense(&mut c));
+        return c;
+    }
+
+    let (me, ke, ne) = (m & !1, k & !1, n & !1);
+    if (me, ke, ne) != (m, k, n) {
+        let c_even = fast_product(a.sub(0, 0, me, ke), b.sub(0, 0, ke, ne), cutoff, variant);
+        for (i, row) in c_even.rows() {
+            c.row_mut(i)[..ne].copy_from_slice(row);
+        }
+
+        let mut c_view = MatMut::from_dense(&mut c);
+        // C11 += A12 B21 (last column of A times last row of B)
+        gemm(a.sub(0, ke, me, k - ke), b.sub(ke, 0, k - ke, ne), &mut c_view.sub(0, 0, me, ne));
+        // Last column of C
+        gemm(a.sub(0, 0, me, k), b.sub(0, ne, k, n - ne), &mut c_view.sub(0, ne, me, n - ne));
+        // Last row of C
+        gemm(a.sub(me, 0, m - me, k), b.sub(0, 0, k, n), &mut c_view.sub(me, 0, m - me, n));
+        return c;
+    }
+
+    let (mh, kh, nh) = (m / 2, k / 2, n / 2);
+    let a11 = a.sub(0, 0, mh, kh);
+    let a12 = a.sub(0, kh, mh, kh);
+    let a21 = a.sub(mh, 0, mh, kh);
+    let a22 = a.sub(mh, kh, mh, kh);
+    let b11 = b.sub(0, 0, kh, nh);
+    let b12 = b.sub(0, nh, kh, nh);
+    let b21 = b.sub(kh, 0, kh, nh);
+    let b22 = b.sub(kh, nh, kh, nh);
+
+    let (c11, c12, c21, c22) = match variant {
+        FastVariant::Strassen => {
+            let m1 = fast_product(view(&lin(a11, a22, 1.)), view(&lin(b11, b22, 1.)), cutoff, variant);
+            let m2 = fast_product(view(&lin(a21, a22, 1.)), b11, cutoff, variant);
+            let m3 = fast_product(a11, view(&lin(b12, b22, -1.)), cutoff, variant);
+            let m4 = fast_product(a22, view(&lin(b21, b11, -1.)), cutoff, variant);
+            let m5 = fast_product(view(&lin(a11, a12, 1.)), b22, cutoff, variant);
+            let m6 = fast_product(view(&lin(a21, a11, -1.)), view(&lin(b11, b12, 1.)), cutoff, variant);
+            let m7 = fast_product(view(&lin(a12, a22, -1.)), view(&lin(b21, b22, 1.)), cutoff, variant);
+
+            // C11 = M1 + M4 - M5 + M7, C12 = M3 + M5, C21 = M2 + M4, C22 = M1 - M2 + M3 + M6
+            (combine(&[(&m1, 1.), (&m4, 1.), (&m5, -1.), (&m7, 1.)]),
+             combine(&[(&m3, 1.), (&m5, 1.)]),
+             combine(&[(&m2, 1.), (&m4, 1.)]),
+             combine(&[(&m1, 1.), (&m2, -1.), (&m3, 1.), (&m6, 1.)]))
         }
+        FastVariant::Winograd => {
+            let s1 = lin(a21, a22, 1.);
+            let s2 = lin(view(&s1), a11, -1.);
+            let s3 = lin(a11, a21, -1.);
+            let s4 = lin(a12, view(&s2), -1.);
+            let t1 = lin(b12, b11, -1.);
+            let t2 = lin(b22, view(&t1), -1.);
+            let t3 = lin(b22, b12, -1.);
+            let t4 = lin(view(&t2), b21, -1.);
 
-        final_result
+            let p1 = fast_product(a11, b11, cutoff, variant);
+            let p2 = fast_product(a12, b21, cutoff, variant);
+            let p3 = fast_product(view(&s4), b22, cutoff, variant);
+            let p4 = fast_product(a22, view(&t4), cutoff, variant);
+            let p5 = fast_product(view(&s1), view(&t1), cutoff, variant);
+            let p6 = fast_product(view(&s2), view(&t2), cutoff, variant);
+            let p7 = fast_product(view(&s3), view(&t3), cutoff, variant);
+
+            // U2 = P1 + P6, U3 = U2 + P7, U4 = U2 + P5
+            // C11 = P1 + P2, C12 = U4 + P3, C21 = U3 - P4, C22 = U3 + P5
+            let u2 = combine(&[(&p1, 1.), (&p6, 1.)]);
+            let u3 = combine(&[(&u2, 1.), (&p7, 1.)]);
+            let u4 = combine(&[(&u2, 1.), (&p5, 1.)]);
+            (combine(&[(&p1, 1.), (&p2, 1.)]),
+             combine(&[(&u4, 1.), (&p3, 1.)]),
+             combine(&[(&u3, 1.), (&p4, -1.)]),
+             combine(&[(&u3, 1.), (&p5, 1.)]))
+        }
+    };
+
+    for i in 0..mh {
+        c.row_mut(i)[..nh].copy_from_slice(c11.row(i));
+        c.row_mut(i)[nh..].copy_from_slice(c12.row(i));
+        c.row_mut(mh + i)[..nh].copy_from_slice(c21.row(i));
+        c.row_mut(mh + i)[nh..].copy_from_slice(c22.row(i));
     }
-}
\ No newline at end of file
+    c
+}
+
+
+
+// Whole matrix as a block, for the temporaries of fast_product
+fn view(x: &Dense) -> MatRef<'_> {
+    MatRef::from_dense(x)
+}
+
+
+
+// X + alpha*Y for two blocks of the same size
+fn lin(x: MatRef, y: MatRef, alpha: f64) -> Dense {
+    let mut z = Dense::new_zeros((x.rows, x.cols));
+    for (i, row) in z.rows_mut() {
+        let x_row = &x.data[i*x.stride..i*x.stride + x.cols];
+        let y_row = &y.data[i*y.stride..i*y.stride + y.cols];
+        for ((z_ij, x_ij), y_ij) in row.iter_mut().zip(x_row).zip(y_row) {
+            *z_ij = x_ij + alpha * y_ij;
+        }
+    }
+    z
+}
+
+
+
+// \sum alpha_i X_i for matrices of the same shape
+fn combine(terms: &[(&Dense, f64)]) -> Dense {
+    let mut z = Dense::new_zeros(terms[0].0.shape);
+    for (x, alpha) in terms {
+        for (z_ij, x_ij) in z.data.iter_mut().zip(&x.data) {
+            *z_ij += alpha * x_ij;
+        }
+    }
+    z
+}
@@ -0,0 +1,474 @@
+use matrix_base::{CSC, CSR};
+
+use crate::triangular::{Diagonal, Triangle, TriangularAnalysis};
+
+
+// Sparse direct solvers, both in a symbolic and a numeric phase, see
+// "Direct Methods for Sparse Linear Systems", Davis
+// https://doi.org/10.1137/1.9780898718881
+//
+// Cholesky A = L L^T for SPD A (chapter 4):
+//   symbolic: elimination tree (Liu) and the pattern of L, column j of L is
+//             the lower part of A_{*j} plus the columns of L of its children in the tree.
+//             Only the lower triangle of A is read, so A can be given in full or
+//             as its lower triangle, like a symmetric .mtx from COO::read_mtx.
+//   numeric:  left-looking, column j of L is updated by all columns k < j
+//             with l_jk != 0. These are found with one linked list per row:
+//             column k waits in the list of the row of its next unused entry.
+//   solve:    L y = b and L^T x = y with the triangular solves of triangular,
+//             serial or level-scheduled (solve_par)
+//
+// LU P A Q = L U for general A (chapter 6, Gilbert-Peierls):
+//   symbolic: column ordering Q (identity or given, e.g. a fill-reducing one)
+//   numeric:  left-looking, column j solves L x = A_{*j} with a sparse triangular
+//             solve, whose pattern is the reach of A_{*j} in the graph of L (DFS).
+//             Threshold partial pivoting: the diagonal is kept as pivot if
+//             |x_jj| >= threshold * max |x_ij|, threshold = 1 is partial pivoting.
+//   refactor: new values with the same pattern, reuses the pivots and the patterns of L and U.
+//   solve:    L y = P b and U z = y with the triangular solves of triangular
+//
+// The symbolic phase only depends on the pattern, it can be reused for every
+// matrix with the same pattern. Failures (not positive definite, singular,
+// wrong pattern) are returned as Err, not as a panic.
+// No supernodes, every column is processed on its own.
+
+const NONE: usize = usize::MAX;
+
+
+
+pub struct CholeskySymbolic {
+    // Elimination tree, NONE for the roots
+    pub parent: Vec<usize>,
+    // Pattern of L in CSC, rows sorted, diagonal first
+    l_col_pos: Vec<usize>,
+    l_row_pos: Vec<usize>
+}
+
+
+impl CholeskySymbolic {
+    // Only the entries a_ij with i >= j are read, entries above the diagonal are ignored.
+    // The etree needs the rows of the lower triangle, the pattern of L its columns.
+    pub fn new(a: &CSR) -> Self {
+        assert_eq!(a.shape.0, a.shape.1, "Matrix is not square");
+        let n = a.shape.0;
+
+        // Etree from the rows of the lower triangle, with path compression
+        let mut parent = vec![NONE; n];
+        let mut ancestor = vec![NONE; n];
+        for i in 0..n {
+            for &k in a.row_slices(i).0.iter().filter(|&&k| k < i) {
+                let mut r = k;
+                while ancestor[r] != NONE && ancestor[r] != i {
+                    let next = ancestor[r];
+                    ancestor[r] = i;
+                    r = next;
+                }
+                if ancestor[r] == NONE {
+                    ancestor[r] = i;
+                    parent[r] = i;
+                }
+            }
+        }
+
+        let mut children = vec![vec![]; n];
+        for (j, p) in parent.iter().enumerate() {
+            if *p != NONE {
+                children[*p].push(j);
+            }
+        }
+
+        // L_{*j} = {j} + lower part of A_{*j} + (L_{*c} \ {c}) for the children c
+        let a_cols = CSC::from_csr(a);
+        let mut marker = vec![NONE; n];
+        let mut l_col_pos = vec![0];
+        let mut l_row_pos = vec![];
+        for j in 0..n {
+            let start = l_row_pos.len();
+            marker[j] = j;
+            l_row_pos.push(j);
+            for &i in a_cols.col_slices(j).0.iter().filter(|&&i| i > j) {
+                if marker[i] != j {
+                    marker[i] = j;
+                    l_row_pos.push(i);
+                }
+            }
+            for &c in &children[j] {
+                for q in l_col_pos[c]+1..l_col_pos[c+1] {
+                    let i = l_row_pos[q];
+                    if marker[i] != j {
+                        marker[i] = j;
+                        l_row_pos.push(i);
+                    }
+                }
+            }
+            l_row_pos[start..].sort_unstable();
+            l_col_pos.push(l_row_pos.len());
+        }
+
+        CholeskySymbolic{parent, l_col_pos, l_row_pos}
+    }
+
+
+    pub fn nnz_l(&self) -> usize {
+        self.l_row_pos.len()
+    }
+
+
+    // Numeric phase for a matrix with the pattern of the symbolic phase
+    pub fn factorize(&self, a: &CSR) -> Result<Cholesky, &'static str> {
+        let n = self.parent.len();
+        assert_eq!(a.shape, (n, n), "Matrix does not match the symbolic factorization");
+        let (col_pos, rows) = (&self.l_col_pos, &self.l_row_pos);
+        let a_cols = CSC::from_csr(a);
+
+        let mut values = vec![0.; rows.len()];
+        let mut x = vec![0.; n];
+        let mut in_pattern = vec![NONE; n];
+        // next[k]: position of the next unused entry of column k
+        let mut next = vec![0; n];
+        let mut lists: Vec<Vec<usize>> = vec![vec![]; n];
+
+        for j in 0..n {
+            let (start, end) = (col_pos[j], col_pos[j+1]);
+            for i in &rows[start..end] {
+                in_pattern[*i] = j;
+            }
+
+            for (i, a_ij) in a_cols.col(j).filter(|(i, _)| *i >= j) {
+                if in_pattern[i] != j {
+                    return Err("Pattern of the matrix does not match the symbolic factorization");
+                }
+                x[i] += a_ij;
+            }
+
+            // x -= l_jk L_{*k} for all columns k with l_jk != 0
+            for k in std::mem::take(&mut lists[j]) {
+                let p = next[k];
+                let l_jk = values[p];
+                for q in p..col_pos[k+1] {
+                    x[rows[q]] -= values[q] * l_jk;
+                }
+                next[k] = p + 1;
+                if next[k] < col_pos[k+1] {
+                    lists[rows[next[k]]].push(k);
+                }
+            }
+
+            let d = x[j];
+            if d <= 0. || !d.is_finite() {
+                return Err("Matrix is not positive definite");
+            }
+            let l_jj = d.sqrt();
+            for q in start..end {
+                values[q] = if q == start { l_jj } else { x[rows[q]] / l_jj };
+                x[rows[q]] = 0.;
+            }
+
+            next[j] = start + 1;
+            if next[j] < end {
+                lists[rows[next[j]]].push(j);
+            }
+        }
+
+        let l = CSC{col_pos: col_pos.clone(), row_pos: rows.clone(), values, shape: (n, n)};
+        let forward = TriangularAnalysis::new_csc(&l, Triangle::Lower, false, Diagonal::NonUnit)?;
+        let backward = TriangularAnalysis::new_csc(&l, Triangle::Lower, true, Diagonal::NonUnit)?;
+        Ok(Cholesky{l, forward, backward})
+    }
+}
+
+
+
+// The triangular solves read L and L^T from L in CSC
+pub struct Cholesky {
+    l: CSC,
+    forward: TriangularAnalysis,
+    backward: TriangularAnalysis
+}
+
+
+impl Cholesky {
+    // Symbolic and numeric phase
+    pub fn new(a: &CSR) -> Result<Self, &'static str> {
+        CholeskySymbolic::new(a).factorize(a)
+    }
+
+
+    pub fn l(&self) -> &CSC {
+        &self.l
+    }
+
+
+    // L y = b, then L^T x = y
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        assert_eq!(b.len(), self.l.shape.0, "Matrix and vector dimensions do not match");
+        let mut x = b.to_vec();
+        self.forward.solve_csc_owned(&self.l, &mut x, false);
+        self.backward.solve_csc_owned(&self.l, &mut x, false);
+        x
+    }
+
+
+    // Same as solve, with the level-scheduled parallel triangular solves
+    pub fn solve_par(&self, b: &[f64]) -> Vec<f64> {
+        assert_eq!(b.len(), self.l.shape.0, "Matrix and vector dimensions do not match");
+        let mut x = b.to_vec();
+        self.forward.solve_csc_owned(&self.l, &mut x, true);
+        self.backward.solve_csc_owned(&self.l, &mut x, true);
+        x
+    }
+}
+
+
+
+pub struct LuSymbolic {
+    col_perm: Vec<usize>
+}
+
+
+impl LuSymbolic {
+    // col_perm[j] is the column of A eliminated in step j, None for the identity
+    pub fn new(a: &CSC, col_perm: Option<&[usize]>) -> Self {
+        assert_eq!(a.shape.0, a.shape.1, "Matrix is not square");
+        let n = a.shape.0;
+        let col_perm = match col_perm {
+            Some(p) => {
+                assert_eq!(p.len(), n, "Permutation does not match the matrix");
+                let mut seen = vec![false; n];
+                for &j in p {
+                    assert!(j < n && !seen[j], "Not a permutation");
+                    seen[j] = true;
+                }
+                p.to_vec()
+            }
+            None => (0..n).collect()
+        };
+        LuSymbolic{col_perm}
+    }
+
+
+    pub fn factorize(&self, a: &CSC, threshold: f64) -> Result<Lu, &'static str> {
+        let n = self.col_perm.len();
+        assert_eq!(a.shape, (n, n), "Matrix does not match the symbolic factorization");
+        assert!(threshold > 0. && threshold <= 1., "Pivot threshold must be in (0, 1]");
+
+        // L: original row indices, pivot row first with l = 1,
+        //    renumbered to steps at the end
+        // U: row indices are steps, the diagonal last
+        let mut l = CSC{col_pos: vec![0], row_pos: vec![], values: vec![], shape: (n, n)};
+        let mut u = CSC{col_pos: vec![0], row_pos: vec![], values: vec![], shape: (n, n)};
+        let mut pinv = vec![NONE; n];
+        let mut pivot_row = Vec::with_capacity(n);
+
+        let mut x = vec![0.; n];
+        let mut marker = vec![NONE; n];
+        let mut stack: Vec<(usize, usize)> = vec![];
+        let mut post = vec![];
+
+        for j in 0..n {
+            let col = self.col_perm[j];
+
+            // Reach of A_{*col} in the graph of L, in postorder
+            post.clear();
+            for &i in a.col_slices(col).0 {
+                if marker[i] == j {
+                    continue;
+                }
+                marker[i] = j;
+                stack.push((i, 0));
+                while let Some(&(node, pos)) = stack.last() {
+                    let k = pinv[node];
+                    let (start, end) = if k == NONE { (0, 0) } else { (l.col_pos[k] + 1, l.col_pos[k+1]) };
+                    match (start + pos..end).find(|q| marker[l.row_pos[*q]] != j) {
+                        Some(q) => {
+                            stack.last_mut().unwrap().1 = q + 1 - start;
+                            let r = l.row_pos[q];
+                            marker[r] = j;
+                            stack.push((r, 0));
+                        }
+                        None => {
+                            stack.pop();
+                            post.push(node);
+                        }
+                    }
+                }
+            }
+
+            // x = L \ A_{*col}, in topological order (reverse postorder)
+            for (i, a_ij) in a.col(col) {
+                x[i] += a_ij;
+            }
+            for &i in post.iter().rev() {
+                let k = pinv[i];
+                if k == NONE {
+                    continue;
+                }
+                let u_kj = x[i];
+                for q in l.col_pos[k]+1..l.col_pos[k+1] {
+                    x[l.row_pos[q]] -= l.values[q] * u_kj;
+                }
+            }
+
+            // Pivot among the rows that are not pivotal yet
+            let mut best = NONE;
+            let mut max = 0.;
+            for &i in &post {
+                if pinv[i] == NONE && (best == NONE || x[i].abs() > max) {
+                    best = i;
+                    max = x[i].abs();
+                }
+            }
+            if best == NONE || max == 0. || !max.is_finite() {
+                return Err("Matrix is singular");
+            }
+            let piv = if pinv[col] == NONE && marker[col] == j && x[col].abs() >= threshold * max { col } else { best };
+            let pivot = x[piv];
+
+            for &i in post.iter().rev() {
+                if pinv[i] != NONE {
+                    u.row_pos.push(pinv[i]);
+                    u.values.push(x[i]);
+                    x[i] = 0.;
+                }
+            }
+            u.row_pos.push(j);
+            u.values.push(pivot);
+            u.col_pos.push(u.values.len());
+
+            l.row_pos.push(piv);
+            l.values.push(1.);
+            x[piv] = 0.;
+            for &i in post.iter().rev() {
+                if pinv[i] == NONE && i != piv {
+                    l.row_pos.push(i);
+                    l.values.push(x[i] / pivot);
+                    x[i] = 0.;
+                }
+            }
+            l.col_pos.push(l.values.len());
+
+            pinv[piv] = j;
+            pivot_row.push(piv);
+        }
+
+        // Row k of L and U is step k, both are triangular
+        for r in l.row_pos.iter_mut() {
+            *r = pinv[*r];
+        }
+        let forward = TriangularAnalysis::new_csc(&l, Triangle::Lower, false, Diagonal::Unit)?;
+        let backward = TriangularAnalysis::new_csc(&u, Triangle::Upper, false, Diagonal::NonUnit)?;
+        Ok(Lu{l, u, pivot_row, col_perm: self.col_perm.clone(), forward, backward})
+    }
+}
+
+
+
+// L (unit diagonal) and U in CSC with the steps as row indices,
+// solved with the triangular solves of triangular
+pub struct Lu {
+    l: CSC,
+    u: CSC,
+    pivot_row: Vec<usize>,
+    col_perm: Vec<usize>,
+    forward: TriangularAnalysis,
+    backward: TriangularAnalysis
+}
+
+
+impl Lu {
+    // Symbolic (identity ordering) and numeric phase
+    pub fn new(a: &CSC, threshold: f64) -> Result<Self, &'static str> {
+        LuSymbolic::new(a, None).factorize(a, threshold)
+    }
+
+
+    pub fn nnz(&self) -> usize {
+        self.l.values.len() + self.u.values.len()
+    }
+
+
+    // Row of A that was pivotal in step k
+    pub fn pivot_rows(&self) -> &[usize] {
+        &self.pivot_row
+    }
+
+
+    // Numeric factorization of a matrix with the same pattern, without pivoting:
+    // the pivots and the patterns of L and U are reused.
+    // Fails if a pivot becomes zero, then a new factorize is needed. The new
+    // values are computed aside, so after an Err the old factors are still valid.
+    pub fn refactor(&mut self, a: &CSC) -> Result<(), &'static str> {
+        let n = self.pivot_row.len();
+        assert_eq!(a.shape, (n, n), "Matrix does not match the factorization");
+        let (l, u) = (&self.l, &self.u);
+        let mut l_values = vec![0.; l.values.len()];
+        let mut u_values = vec![0.; u.values.len()];
+
+        let mut x = vec![0.; n];
+        let mut in_pattern = vec![NONE; n];
+
+        for j in 0..n {
+            let col = self.col_perm[j];
+            let (u_start, u_end) = (u.col_pos[j], u.col_pos[j+1]);
+            let (l_start, l_end) = (l.col_pos[j], l.col_pos[j+1]);
+            for q in u_start..u_end {
+                in_pattern[self.pivot_row[u.row_pos[q]]] = j;
+            }
+            for q in l_start..l_end {
+                in_pattern[self.pivot_row[l.row_pos[q]]] = j;
+            }
+
+            for (i, a_ij) in a.col(col) {
+                if in_pattern[i] != j {
+                    return Err("Pattern of the matrix does not match the factorization");
+                }
+                x[i] += a_ij;
+            }
+
+            // Same order as in factorize, the diagonal is the last entry of U_{*j}
+            for (&k, u_val) in u.row_pos[u_start..u_end-1].iter().zip(&mut u_values[u_start..u_end-1]) {
+                let u_kj = x[self.pivot_row[k]];
+                *u_val = u_kj;
+                for p in l.col_pos[k]+1..l.col_pos[k+1] {
+                    x[self.pivot_row[l.row_pos[p]]] -= l_values[p] * u_kj;
+                }
+            }
+
+            let pivot = x[self.pivot_row[j]];
+            if pivot == 0. || !pivot.is_finite() {
+                return Err("Zero pivot in refactorization");
+            }
+            u_values[u_end-1] = pivot;
+            for q in u_start..u_end {
+                x[self.pivot_row[u.row_pos[q]]] = 0.;
+            }
+            l_values[l_start] = 1.;
+            for (&k, l_val) in l.row_pos[l_start+1..l_end].iter().zip(&mut l_values[l_start+1..l_end]) {
+                let i = self.pivot_row[k];
+                *l_val = x[i] / pivot;
+                x[i] = 0.;
+            }
+        }
+
+        self.l.values = l_values;
+        self.u.values = u_values;
+        Ok(())
+    }
+
+
+    // A x = b: L y = P b, U z = y, x = Q z
+    pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.pivot_row.len();
+        assert_eq!(b.len(), n, "Matrix and vector dimensions do not match");
+
+        let mut y: Vec<f64> = self.pivot_row.iter().map(|piv| b[*piv]).collect();
+        self.forward.solve_csc_owned(&self.l, &mut y, false);
+        self.backward.solve_csc_owned(&self.u, &mut y, false);
+
+        let mut x = vec![0.; n];
+        for (j, y_j) in y.iter().enumerate() {
+            x[self.col_perm[j]] = *y_j;
+        }
+        x
+    }
+}
@@ -0,0 +1,105 @@
+use std::cmp::Ordering;
+
+
+// SpGEMM with numerical dropping, C = drop(A*B), as needed for the
+// coarse operators of algebraic multigrid and sparse approximate inverses.
+// Every row C_{i*} is dropped right after its accumulator is drained
+// (row compaction), so the full product is never stored.
+//
+// abs_tol:     entries with |c_ij| < abs_tol are dropped
+// rel_tol:     entries with |c_ij| < rel_tol max_j |c_ij| are dropped
+// top_k:       of the remaining entries the top_k largest in magnitude are
+//              kept, ties are broken by the smaller column index.
+//              NaN ranks above every number, like the thresholds keep it.
+// prune_zeros: entries c_ij == 0.0 are dropped, e.g. exact cancellations.
+//              The accumulators keep them, since they only track structure.
+//
+// The thresholds are applied before top_k. The default drops nothing,
+// i.e. gives the same result as product_sparse.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DropOptions {
+    pub abs_tol: f64,
+    pub rel_tol: f64,
+    pub top_k: Option<usize>,
+    pub prune_zeros: bool
+}
+
+
+
+// Compaction of one row of the result, with a scratch buffer
+// for the top-k selection that is reused over the rows
+pub(crate) struct RowDropper {
+    opts: DropOptions,
+    scratch: Vec<usize>
+}
+
+
+impl RowDropper {
+    pub(crate) fn new(opts: DropOptions) -> Self {
+        assert!(opts.abs_tol >= 0. && opts.rel_tol >= 0., "Drop tolerances must be non-negative");
+        RowDropper{opts, scratch: vec![]}
+    }
+
+
+    // Drops from the row cols[start..] / vals[start..] in place,
+    // the kept entries stay in column order
+    pub(crate) fn compact(&mut self, cols: &mut Vec<usize>, vals: &mut Vec<f64>, start: usize) {
+        let row_max = vals[start..].iter().fold(0., |acc: f64, x| acc.max(x.abs()));
+        let tol = self.opts.abs_tol.max(self.opts.rel_tol * row_max);
+        let prune_zeros = self.opts.prune_zeros;
+        retain_row(cols, vals, start, |x| (x.is_nan() || x.abs() >= tol) && !(prune_zeros && x == 0.));
+
+        let len = vals.len() - start;
+        match self.opts.top_k {
+            Some(0) => {
+                cols.truncate(start);
+                vals.truncate(start);
+            }
+            Some(k) if k < len => {
+                // Positions in the row ranked by magnitude (descending), then
+                // by position, i.e. column. Exactly the first k are kept.
+                let row = &vals[start..];
+                self.scratch.clear();
+                self.scratch.extend(0..len);
+                self.scratch.select_nth_unstable_by(k - 1, |p, q| magnitude_cmp(row[*q], row[*p]).then(p.cmp(q)));
+                let kept = &mut self.scratch[..k];
+                kept.sort_unstable();
+                for (end, p) in kept.iter().enumerate() {
+                    cols[start + end] = cols[start + p];
+                    vals[start + end] = vals[start + p];
+                }
+                cols.truncate(start + k);
+                vals.truncate(start + k);
+            }
+            _ => ()
+        }
+    }
+}
+
+
+
+// Order of |x|, NaN above every number
+fn magnitude_cmp(x: f64, y: f64) -> Ordering {
+    match (x.is_nan(), y.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => x.abs().total_cmp(&y.abs())
+    }
+}
+
+
+
+// Keeps the entries of cols[start..] / vals[start..] with keep(value), in order
+fn retain_row(cols: &mut Vec<usize>, vals: &mut Vec<f64>, start: usize, mut keep: impl FnMut(f64) -> bool) {
+    let mut end = start;
+    for p in start..vals.len() {
+        if keep(vals[p]) {
+            cols[end] = cols[p];
+            vals[end] = vals[p];
+            end += 1;
+        }
+    }
+    cols.truncate(end);
+    vals.truncate(end);
+}
@@ -0,0 +1,631 @@
+use rayon::prelude::*;
+
+use matrix_base::{Dense, LinearOperator};
+
+use crate::solvers::{axpy, dot, norm, Preconditioner, SolverStatus, VEC_MIN_LEN};
+
+
+// Extreme eigenpairs of sparse symmetric matrices
+//
+// power_iteration: the eigenvalue of largest magnitude
+// lanczos:         k eigenvalues at one end of the spectrum, thick-restart Lanczos
+// lobpcg:          the same with a block method, optionally preconditioned
+//
+// A is any LinearOperator (a matrix, a composition or matrix-free) and is
+// assumed to be symmetric, this is not checked.
+// An eigenpair counts as converged once ||A v - lambda v|| <= tol ||A||,
+// with ||A|| estimated by the largest Ritz value in magnitude.
+// Like for the linear solvers, not converging is reported, not a panic.
+
+
+
+// End of the spectrum (algebraically largest or smallest eigenvalues)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Which {
+    Largest,
+    Smallest
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EigenOptions {
+    pub tol: f64,
+    // Iterations of power_iteration and lobpcg, restarts of lanczos
+    pub max_iter: usize,
+    // Size of the Lanczos basis before a restart, at least k + 2
+    pub max_dim: usize
+}
+
+
+impl Default for EigenOptions {
+    fn default() -> Self {
+        EigenOptions{tol: 1e-8, max_iter: 1000, max_dim: 40}
+    }
+}
+
+
+
+// values[j] with the normalized eigenvector in column j of vectors
+// (descending for Which::Largest, ascending for Which::Smallest),
+// residual_norms[j] = ||A v_j - values[j] v_j||
+pub struct EigenReport {
+    pub status: SolverStatus,
+    pub iterations: usize,
+    pub matvecs: usize,
+    pub values: Vec<f64>,
+    pub vectors: Dense,
+    pub residual_norms: Vec<f64>
+}
+
+
+impl EigenReport {
+    pub fn converged(&self) -> bool {
+        self.status == SolverStatus::Converged
+    }
+}
+
+
+
+// Sweeps of the cyclic Jacobi method, it converges quadratically
+// and usually needs less than 10
+const JACOBI_MAX_SWEEPS: usize = 50;
+
+// A Lanczos vector is considered to be in the span of the basis if
+// orthogonalization leaves less than this fraction of its norm
+const BREAKDOWN_TOL: f64 = 1e-10;
+
+// Directions of the LOBPCG basis with a Gram eigenvalue below this
+// fraction of the largest one are dropped as linearly dependent
+const RR_DROP_TOL: f64 = 1e-12;
+
+
+
+// Eigenvalues (ascending) and eigenvectors (columns) of a small dense
+// symmetric matrix by the cyclic Jacobi method, see algorithm 8.5.3 of
+// "Matrix Computations", Golub, Van Loan, 4th edition
+pub fn jacobi_eigen(a: &Dense) -> (Vec<f64>, Dense) {
+    assert_eq!(a.shape.0, a.shape.1, "Matrix is not square");
+    let n = a.shape.0;
+    let mut a = Dense{data: a.data.clone(), shape: a.shape};
+    let mut v = Dense::new_zeros((n, n));
+    for i in 0..n {
+        v.set(i, i, 1.);
+    }
+
+    let frob: f64 = a.data.iter().map(|x| x * x).sum();
+    for _ in 0..JACOBI_MAX_SWEEPS {
+        let off: f64 = a.iter().filter(|(i, j, _)| i != j).map(|(_, _, x)| x * x).sum();
+        if off <= f64::EPSILON * f64::EPSILON * frob {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p+1..n {
+                let a_pq = a.get(p, q);
+                if a_pq == 0. {
+                    continue;
+                }
+                // Rotation J with J^T A J zeroing a_pq
+                let tau = (a.get(q, q) - a.get(p, p)) / (2. * a_pq);
+                let t = tau.signum() / (tau.abs() + (1. + tau * tau).sqrt());
+                let c = 1. / (1. + t * t).sqrt();
+                let s = t * c;
+
+                for k in 0..n {
+                    let (x_p, x_q) = (a.get(k, p), a.get(k, q));
+                    a.set(k, p, c * x_p - s * x_q);
+                    a.set(k, q, s * x_p + c * x_q);
+                }
+                for k in 0..n {
+                    let (x_p, x_q) = (a.get(p, k), a.get(q, k));
+                    a.set(p, k, c * x_p - s * x_q);
+                    a.set(q, k, s * x_p + c * x_q);
+                }
+                for k in 0..n {
+                    let (x_p, x_q) = (v.get(k, p), v.get(k, q));
+                    v.set(k, p, c * x_p - s * x_q);
+                    v.set(k, q, s * x_p + c * x_q);
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| a.get(i, i).total_cmp(&a.get(j, j)));
+    let values = order.iter().map(|&i| a.get(i, i)).collect();
+    let mut vectors = Dense::new_zeros((n, n));
+    for (r, row) in vectors.rows_mut() {
+        for (c, &i) in order.iter().enumerate() {
+            row[c] = v.get(r, i);
+        }
+    }
+    (values, vectors)
+}
+
+
+
+// Power iteration v <- A v / ||A v|| with the Rayleigh quotient v^T A v.
+// Converges with the rate |lambda_2 / lambda_1|, not at all if -lambda_1
+// is an eigenvalue as well.
+pub fn power_iteration(a: &dyn LinearOperator, opts: &EigenOptions) -> EigenReport {
+    check_dims(a, 1, opts);
+    let n = a.shape().0;
+    let mut v = start_vector(n, 0);
+    let v_norm = norm(&v);
+    v.iter_mut().for_each(|v_i| *v_i /= v_norm);
+    let mut av = vec![0.; n];
+    let mut r = vec![0.; n];
+
+    for k in 1..=opts.max_iter {
+        a.apply(&v, &mut av);
+        let lambda = dot(&v, &av);
+        r.copy_from_slice(&av);
+        axpy(-lambda, &v, &mut r);
+        let r_norm = norm(&r);
+
+        let status = if !r_norm.is_finite() {
+            Some(SolverStatus::Breakdown)
+        } else if r_norm <= opts.tol * lambda.abs() {
+            Some(SolverStatus::Converged)
+        } else if k == opts.max_iter {
+            Some(SolverStatus::MaxIterations)
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            let vectors = Dense{data: v, shape: (n, 1)};
+            return EigenReport{status, iterations: k, matvecs: k, values: vec![lambda], vectors, residual_norms: vec![r_norm]};
+        }
+
+        let av_norm = norm(&av);
+        v.par_iter_mut().zip(&av).with_min_len(VEC_MIN_LEN).for_each(|(v_i, x)| *v_i = x / av_norm);
+    }
+
+    unreachable!()
+}
+
+
+
+// Thick-restart Lanczos, see
+// "Thick-Restart Lanczos Method for Large Symmetric Eigenvalue Problems", Wu, Simon
+// https://doi.org/10.1137/S0895479898334605
+//
+// The basis V is extended to m = opts.max_dim vectors with full
+// reorthogonalization (classical Gram-Schmidt, twice), so T = V^T A V is
+// computed explicitly and the arrowhead structure after a restart needs no
+// special treatment. The residual of the Ritz pair (theta_i, V y_i) is
+// |beta_m y_{m,i}|. At a restart the k + (m - k) / 2 Ritz vectors at the
+// wanted end and the last Lanczos vector are kept.
+pub fn lanczos(a: &dyn LinearOperator, k: usize, which: Which, opts: &EigenOptions) -> EigenReport {
+    check_dims(a, k, opts);
+    let n = a.shape().0;
+    let m = opts.max_dim.max(k + 2).min(n);
+
+    let mut basis: Vec<Vec<f64>> = Vec::with_capacity(m);
+    let mut t = Dense::new_zeros((m, m));
+    let mut w = vec![0.; n];
+    let mut matvecs = 0;
+    let mut a_norm: f64 = 0.;
+
+    let mut v = start_vector(n, 0);
+    let v_norm = norm(&v);
+    v.iter_mut().for_each(|v_i| *v_i /= v_norm);
+    basis.push(v);
+
+    for restart in 1..=opts.max_iter {
+        // Lanczos steps up to m vectors, w = (I - V V^T) A v_m at the end
+        let mut beta;
+        let mut j = basis.len() - 1;
+        loop {
+            a.apply(&basis[j], &mut w);
+            matvecs += 1;
+            let av_norm = norm(&w);
+            let h = orthogonalize(&basis, &mut w);
+            for (i, h_i) in h.iter().enumerate() {
+                t.set(i, j, *h_i);
+                t.set(j, i, *h_i);
+            }
+            beta = norm(&w);
+            if basis.len() == m {
+                break;
+            }
+
+            if beta > BREAKDOWN_TOL * av_norm {
+                t.set(j+1, j, beta);
+                t.set(j, j+1, beta);
+                basis.push(w.iter().map(|w_i| w_i / beta).collect());
+            } else {
+                // Invariant subspace: continue with a new direction, T_{j+1,j} = 0.
+                // If there is none, the basis spans the whole space.
+                beta = 0.;
+                let mut r = start_vector(n, basis.len() as u64);
+                let r_start = norm(&r);
+                orthogonalize(&basis, &mut r);
+                let r_norm = norm(&r);
+                if r_norm <= BREAKDOWN_TOL * r_start {
+                    break;
+                }
+                basis.push(r.iter().map(|r_i| r_i / r_norm).collect());
+            }
+            j += 1;
+        }
+
+        // Ritz pairs, wanted end first
+        let s = basis.len();
+        let (theta, y) = jacobi_eigen(&leading(&t, s));
+        let order: Vec<usize> = match which {
+            Which::Largest => (0..s).rev().collect(),
+            Which::Smallest => (0..s).collect()
+        };
+        a_norm = a_norm.max(theta[0].abs()).max(theta[s-1].abs());
+        let converged = order[..k].iter().all(|&i| (beta * y.get(s-1, i)).abs() <= opts.tol * a_norm);
+
+        if converged || restart == opts.max_iter {
+            let values: Vec<f64> = order[..k].iter().map(|&i| theta[i]).collect();
+            let vectors = ritz_vectors(&basis, &y, &order[..k]);
+            let residual_norms = residual_norms(a, &values, &vectors);
+            matvecs += k;
+            let status = if converged { SolverStatus::Converged } else { SolverStatus::MaxIterations };
+            return EigenReport{status, iterations: restart, matvecs, values, vectors, residual_norms};
+        }
+
+        // Thick restart: V = [Ritz vectors, w / beta], T = diag(theta)
+        let l = (k + (s - k) / 2).min(s - 1);
+        let kept = ritz_vectors(&basis, &y, &order[..l]);
+        basis.clear();
+        basis.extend((0..l).map(|c| column(&kept, c)));
+        basis.push(w.iter().map(|w_i| w_i / beta).collect());
+        t.data.iter_mut().for_each(|x| *x = 0.);
+        for (i, &o) in order[..l].iter().enumerate() {
+            t.set(i, i, theta[o]);
+        }
+    }
+
+    unreachable!()
+}
+
+
+
+// Locally optimal block preconditioned conjugate gradients, see
+// "Toward the Optimal Preconditioned Eigensolver: Locally Optimal Block
+// Preconditioned Conjugate Gradient Method", Knyazev
+// https://doi.org/10.1137/S1064827500366124
+//
+// Every iteration does a Rayleigh-Ritz step on span[X, W, P] with the block X
+// of k Ritz vectors, the preconditioned residuals W = M^{-1} (A X - X Theta)
+// and the previous update P. Products with A are one SpMM with W per iteration,
+// A X and A P are updated with the same coefficients as X and P.
+// The Rayleigh-Ritz step drops (nearly) linearly dependent directions,
+// so the basis needs no explicit orthogonalization. If fewer than k
+// directions are left, the status is Breakdown with the current iterates.
+pub fn lobpcg(a: &dyn LinearOperator, k: usize, which: Which, precond: &dyn Preconditioner, opts: &EigenOptions) -> EigenReport {
+    check_dims(a, k, opts);
+    lobpcg_start(a, &start_block(a.shape().0, k), which, precond, opts)
+}
+
+
+// lobpcg with the start block X (n x k) instead of a pseudo-random one
+pub fn lobpcg_start(a: &dyn LinearOperator, x: &Dense, which: Which, precond: &dyn Preconditioner, opts: &EigenOptions) -> EigenReport {
+    let k = x.shape.1;
+    check_dims(a, k, opts);
+    let n = a.shape().0;
+    assert_eq!(x.shape.0, n, "Operator and start block dimensions do not match");
+
+    let mut ax = Dense::new_zeros((n, k));
+    a.apply_block(x, &mut ax);
+    let mut matvecs = k;
+    let Some((coef, mut theta)) = rayleigh_ritz(&[x], &[&ax], k, which) else {
+        // Degenerate start block, the Rayleigh quotients of its columns
+        let values = rayleigh_quotients(x, &ax);
+        let residual_norms = residual_norms(a, &values, x);
+        return EigenReport{status: SolverStatus::Breakdown, iterations: 0, matvecs: matvecs + k, values, vectors: Dense{data: x.data.clone(), shape: x.shape}, residual_norms};
+    };
+    let mut ax = combine(&[&ax], &coef);
+    let mut x = combine(&[x], &coef);
+    let mut p: Option<(Dense, Dense)> = None;
+    let mut a_norm: f64 = 0.;
+
+    for iteration in 0..=opts.max_iter {
+        // R = A X - X Theta
+        let mut r = Dense::new_zeros((n, k));
+        r.par_rows_mut().for_each(|(i, row)| {
+            for (c, r_ic) in row.iter_mut().enumerate() {
+                *r_ic = ax.get(i, c) - theta[c] * x.get(i, c);
+            }
+        });
+        let residual_norms = column_norms(&r);
+        a_norm = theta.iter().fold(a_norm, |acc, t| acc.max(t.abs()));
+
+        let converged = residual_norms.iter().all(|r_c| *r_c <= opts.tol * a_norm);
+        let finite = residual_norms.iter().all(|r_c| r_c.is_finite());
+        if converged || !finite || iteration == opts.max_iter {
+            let status = if !finite {
+                SolverStatus::Breakdown
+            } else if converged {
+                SolverStatus::Converged
+            } else {
+                SolverStatus::MaxIterations
+            };
+            return EigenReport{status, iterations: iteration, matvecs, values: theta, vectors: x, residual_norms};
+        }
+
+        // W = M^{-1} R, column by column
+        let mut w = Dense::new_zeros((n, k));
+        let mut r_c = vec![0.; n];
+        let mut w_c = vec![0.; n];
+        for c in 0..k {
+            for (i, row) in r.rows() {
+                r_c[i] = row[c];
+            }
+            precond.apply(&r_c, &mut w_c);
+            for (i, row) in w.rows_mut() {
+                row[c] = w_c[i];
+            }
+        }
+        let mut aw = Dense::new_zeros((n, k));
+        a.apply_block(&w, &mut aw);
+        matvecs += k;
+
+        let rr = match &p {
+            Some((p, ap)) => rayleigh_ritz(&[&x, &w, p], &[&ax, &aw, ap], k, which),
+            None => rayleigh_ritz(&[&x, &w], &[&ax, &aw], k, which)
+        };
+        let Some((coef, new_theta)) = rr else {
+            return EigenReport{status: SolverStatus::Breakdown, iterations: iteration, matvecs, values: theta, vectors: x, residual_norms};
+        };
+
+        // P = W C_W + P C_P, the new X = X C_X + P
+        let coef_wp = Dense{data: coef.data[k*k..].to_vec(), shape: (coef.shape.0 - k, k)};
+        let coef_x = Dense{data: coef.data[..k*k].to_vec(), shape: (k, k)};
+        let (new_p, new_ap) = match &p {
+            Some((p, ap)) => (combine(&[&w, p], &coef_wp), combine(&[&aw, ap], &coef_wp)),
+            None => (combine(&[&w], &coef_wp), combine(&[&aw], &coef_wp))
+        };
+        x = combine(&[&x, &new_p], &stack_identity(&coef_x));
+        ax = combine(&[&ax, &new_ap], &stack_identity(&coef_x));
+        p = Some((new_p, new_ap));
+        theta = new_theta;
+    }
+
+    unreachable!()
+}
+
+
+
+fn check_dims(a: &dyn LinearOperator, k: usize, opts: &EigenOptions) {
+    let (m, n) = a.shape();
+    assert_eq!(m, n, "Matrix is not square");
+    assert!(k > 0 && k <= n, "Number of eigenpairs must be in 1..=n");
+    assert!(opts.max_iter > 0, "Eigensolvers need at least one iteration");
+}
+
+
+// Deterministic pseudo-random vector with entries in [-1, 1) (xorshift64*),
+// different seeds give different vectors
+fn start_vector(n: usize, seed: u64) -> Vec<f64> {
+    let mut state = 0x9E37_79B9_7F4A_7C15u64 ^ seed.wrapping_mul(0xD1B5_4A32_D192_ED03);
+    (0..n).map(|_| {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let r = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (r >> 11) as f64 / (1u64 << 52) as f64 - 1.
+    }).collect()
+}
+
+
+fn start_block(n: usize, k: usize) -> Dense {
+    let mut x = Dense::new_zeros((n, k));
+    for c in 0..k {
+        for (i, x_i) in start_vector(n, c as u64).into_iter().enumerate() {
+            x.set(i, c, x_i);
+        }
+    }
+    x
+}
+
+
+// w -= V (V^T w) twice, returns the sum of the coefficients V^T w
+fn orthogonalize(basis: &[Vec<f64>], w: &mut [f64]) -> Vec<f64> {
+    let mut h = vec![0.; basis.len()];
+    for _ in 0..2 {
+        let coef: Vec<f64> = basis.iter().map(|v| dot(v, w)).collect();
+        for (v, c) in basis.iter().zip(&coef) {
+            axpy(-c, v, w);
+        }
+        h.iter_mut().zip(&coef).for_each(|(h_i, c)| *h_i += c);
+    }
+    h
+}
+
+
+// Leading s x s block of t
+fn leading(t: &Dense, s: usize) -> Dense {
+    let mut sub = Dense::new_zeros((s, s));
+    for (i, row) in sub.rows_mut() {
+        row.copy_from_slice(&t.row(i)[..s]);
+    }
+    sub
+}
+
+
+fn column(x: &Dense, c: usize) -> Vec<f64> {
+    x.rows().map(|(_, row)| row[c]).collect()
+}
+
+
+fn column_norms(x: &Dense) -> Vec<f64> {
+    let sq = x.par_rows()
+        .fold(|| vec![0.; x.shape.1], |mut acc, (_, row)| {
+            acc.iter_mut().zip(row).for_each(|(a, r)| *a += r * r);
+            acc
+        })
+        .reduce(|| vec![0.; x.shape.1], |mut a, b| {
+            a.iter_mut().zip(&b).for_each(|(a, b)| *a += b);
+            a
+        });
+    sq.into_iter().map(f64::sqrt).collect()
+}
+
+
+// Columns idx of V Y, as an n x idx.len() block
+fn ritz_vectors(basis: &[Vec<f64>], y: &Dense, idx: &[usize]) -> Dense {
+    let n = basis[0].len();
+    let mut x = Dense::new_zeros((n, idx.len()));
+    x.par_rows_mut().for_each(|(r, row)| {
+        for (c, &i) in idx.iter().enumerate() {
+            row[c] = basis.iter().enumerate().map(|(j, v)| v[r] * y.get(j, i)).sum();
+        }
+    });
+    x
+}
+
+
+// x_c^T A x_c / x_c^T x_c for the columns of X, 0 for zero columns
+fn rayleigh_quotients(x: &Dense, ax: &Dense) -> Vec<f64> {
+    let xx = gram(&[x], &[x]);
+    let xax = gram(&[x], &[ax]);
+    (0..x.shape.1).map(|c| if xx.get(c, c) > 0. { xax.get(c, c) / xx.get(c, c) } else { 0. }).collect()
+}
+
+
+// ||A v_c - lambda_c v_c|| for the columns of vectors
+fn residual_norms(a: &dyn LinearOperator, values: &[f64], vectors: &Dense) -> Vec<f64> {
+    let mut av = vec![0.; a.shape().0];
+    values.iter().enumerate().map(|(c, lambda)| {
+        let v = column(vectors, c);
+        a.apply(&v, &mut av);
+        axpy(-lambda, &v, &mut av);
+        norm(&av)
+    }).collect()
+}
+
+
+// X^T Y for the column blocks [X_1, X_2, ...] and [Y_1, Y_2, ...] of the same height
+fn gram(xs: &[&Dense], ys: &[&Dense]) -> Dense {
+    let p: usize = xs.iter().map(|x| x.shape.1).sum();
+    let q: usize = ys.iter().map(|y| y.shape.1).sum();
+    let n = xs[0].shape.0;
+
+    let data = (0..n).into_par_iter()
+        .fold(|| (vec![0.; p * q], vec![0.; p], vec![0.; q]), |(mut acc, mut x_row, mut y_row), i| {
+            concat_row(xs, i, &mut x_row);
+            concat_row(ys, i, &mut y_row);
+            for (a, x) in x_row.iter().enumerate() {
+                for (b, y) in y_row.iter().enumerate() {
+                    acc[a * q + b] += x * y;
+                }
+            }
+            (acc, x_row, y_row)
+        })
+        .map(|(acc, _, _)| acc)
+        .reduce(|| vec![0.; p * q], |mut a, b| {
+            a.iter_mut().zip(&b).for_each(|(a, b)| *a += b);
+            a
+        });
+    Dense{data, shape: (p, q)}
+}
+
+
+fn concat_row(blocks: &[&Dense], i: usize, row: &mut [f64]) {
+    let mut start = 0;
+    for b in blocks {
+        row[start..start + b.shape.1].copy_from_slice(b.row(i));
+        start += b.shape.1;
+    }
+}
+
+
+// [X_1, X_2, ...] C
+fn combine(blocks: &[&Dense], c: &Dense) -> Dense {
+    let n = blocks[0].shape.0;
+    let p: usize = blocks.iter().map(|b| b.shape.1).sum();
+    assert_eq!(p, c.shape.0);
+    let mut y = Dense::new_zeros((n, c.shape.1));
+    y.par_rows_mut().for_each_init(|| vec![0.; p], |x_row, (i, y_row)| {
+        concat_row(blocks, i, x_row);
+        for (a, x) in x_row.iter().enumerate() {
+            for (y_ic, c_ac) in y_row.iter_mut().zip(c.row(a)) {
+                *y_ic += x * c_ac;
+            }
+        }
+    });
+    y
+}
+
+
+// [C; I], so that [X, P] [C; I] = X C + P
+fn stack_identity(c: &Dense) -> Dense {
+    let k = c.shape.1;
+    let mut s = Dense::new_zeros((c.shape.0 + k, k));
+    s.data[..c.data.len()].copy_from_slice(&c.data);
+    for i in 0..k {
+        s.set(c.shape.0 + i, i, 1.);
+    }
+    s
+}
+
+
+// Rayleigh-Ritz on span S = [X_1, X_2, ...] with A S = [AX_1, AX_2, ...].
+// The Gram matrix G = S^T S is diagonalized after scaling its diagonal to 1,
+// directions with tiny eigenvalues are dropped and the rest is orthonormalized
+// by Z = D U diag(mu)^{-1/2}. The Ritz pairs come from Z^T (S^T A S) Z.
+// Returns the coefficients of the k wanted Ritz vectors in S and their values,
+// None if S has rank < k.
+fn rayleigh_ritz(s: &[&Dense], a_s: &[&Dense], k: usize, which: Which) -> Option<(Dense, Vec<f64>)> {
+    let g_b = gram(s, s);
+    let g_a = gram(s, a_s);
+    let p = g_b.shape.0;
+
+    let d: Vec<f64> = (0..p).map(|i| {
+        let g = g_b.get(i, i);
+        if g > 0. { 1. / g.sqrt() } else { 0. }
+    }).collect();
+    let mut scaled = Dense::new_zeros((p, p));
+    for (i, j, x) in scaled.iter_mut() {
+        *x = d[i] * g_b.get(i, j) * d[j];
+    }
+    let (mu, u) = jacobi_eigen(&scaled);
+    let mu_max = mu.last().copied().unwrap_or(0.);
+    let kept: Vec<usize> = (0..p).filter(|&i| mu[i] > RR_DROP_TOL * mu_max).collect();
+    let r = kept.len();
+    if r < k {
+        return None;
+    }
+
+    let mut z = Dense::new_zeros((p, r));
+    for (i, row) in z.rows_mut() {
+        for (c, &q) in kept.iter().enumerate() {
+            row[c] = d[i] * u.get(i, q) / mu[q].sqrt();
+        }
+    }
+
+    // C = Z^T sym(G_A) Z
+    let mut gz = Dense::new_zeros((p, r));
+    for (i, row) in gz.rows_mut() {
+        for j in 0..p {
+            let g_ij = 0.5 * (g_a.get(i, j) + g_a.get(j, i));
+            for (gz_ic, z_jc) in row.iter_mut().zip(z.row(j)) {
+                *gz_ic += g_ij * z_jc;
+            }
+        }
+    }
+    let mut c = Dense::new_zeros((r, r));
+    for (a, b, c_ab) in c.iter_mut() {
+        *c_ab = (0..p).map(|i| z.get(i, a) * gz.get(i, b)).sum();
+    }
+    let (theta, y) = jacobi_eigen(&c);
+    let picked: Vec<usize> = match which {
+        Which::Largest => (0..r).rev().take(k).collect(),
+        Which::Smallest => (0..k).collect()
+    };
+
+    let mut coef = Dense::new_zeros((p, k));
+    for (i, row) in coef.rows_mut() {
+        for (col, &q) in picked.iter().enumerate() {
+            row[col] = (0..r).map(|a| z.get(i, a) * y.get(a, q)).sum();
+        }
+    }
+    Some((coef, picked.iter().map(|&q| theta[q]).collect()))
+}
@@ -0,0 +1,246 @@
+use rayon::prelude::*;
+
+use matrix_base::CSR;
+
+use crate::accumulator::row_pos_from_counts;
+use crate::sparse::SparseProd;
+
+
+// Element-wise operations on CSR matrices of the same shape.
+//
+// sparse_add: C = alpha*A + beta*B, pattern of C is the union of the patterns
+// hadamard:   C = A∘B, c_ij = a_ij b_ij, pattern is the intersection
+//
+// Rows are combined by merging the sorted column indices, O(nnz(A_{i*}) + nnz(B_{i*})).
+// Rows with unsorted column indices are sorted into a buffer first, so any
+// pattern works. Explicit zeros (also alpha = 0 or cancellation) stay in C.
+//
+// The parallel versions use the two passes of the parallel SpGEMM
+// (see sparse.rs): count nnz(C_{i*}), prefix sum, fill disjoint rows.
+pub trait SparseElementwise {
+    fn sparse_add(&self, alpha: f64, other: &CSR, beta: f64) -> CSR;
+    fn sparse_add_par(&self, alpha: f64, other: &CSR, beta: f64) -> CSR;
+    fn hadamard(&self, other: &CSR) -> CSR;
+    fn hadamard_par(&self, other: &CSR) -> CSR;
+    fn scale_rows(&mut self, d: &[f64]);
+    fn scale_cols(&mut self, d: &[f64]);
+    fn map_values(&mut self, f: impl Fn(f64) -> f64 + Sync);
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MergeOp {
+    Union,
+    Intersection
+}
+
+
+
+impl SparseElementwise for CSR {
+    fn sparse_add(&self, alpha: f64, other: &CSR, beta: f64) -> CSR {
+        merge(self, other, MergeOp::Union, |x, y| alpha * x + beta * y)
+    }
+
+
+    fn sparse_add_par(&self, alpha: f64, other: &CSR, beta: f64) -> CSR {
+        merge_par(self, other, MergeOp::Union, |x, y| alpha * x + beta * y)
+    }
+
+
+    fn hadamard(&self, other: &CSR) -> CSR {
+        merge(self, other, MergeOp::Intersection, |x, y| x * y)
+    }
+
+
+    fn hadamard_par(&self, other: &CSR) -> CSR {
+        merge_par(self, other, MergeOp::Intersection, |x, y| x * y)
+    }
+
+
+    // A = diag(d) A, in place and in parallel
+    fn scale_rows(&mut self, d: &[f64]) {
+        assert_eq!(d.len(), self.shape.0, "Scaling vector does not match the number of rows");
+        self.par_rows_mut().for_each(|(i, _, vals)| {
+            for x in vals {
+                *x *= d[i];
+            }
+        });
+    }
+
+
+    // A = A diag(d), in place and in parallel
+    fn scale_cols(&mut self, d: &[f64]) {
+        assert_eq!(d.len(), self.shape.1, "Scaling vector does not match the number of columns");
+        self.par_rows_mut().for_each(|(_, cols, vals)| {
+            for (j, x) in cols.iter().zip(vals) {
+                *x *= d[*j];
+            }
+        });
+    }
+
+
+    // a_ij = f(a_ij) for all stored entries, the pattern is not changed
+    fn map_values(&mut self, f: impl Fn(f64) -> f64 + Sync) {
+        self.values.par_iter_mut().for_each(|x| *x = f(*x));
+    }
+}
+
+
+
+// C = alpha*A*B + beta*C, e.g. the update of a BLAS-like sparse GEMM
+pub fn sparse_gemm(alpha: f64, a: &CSR, b: &CSR, beta: f64, c: &CSR) -> CSR {
+    a.product_sparse_par(b).sparse_add_par(alpha, c, beta)
+}
+
+
+
+fn check_shapes(a: &CSR, b: &CSR) {
+    assert_eq!(a.shape, b.shape, "Matrix dimensions do not match for element-wise operation");
+}
+
+
+
+fn merge(a: &CSR, b: &CSR, op: MergeOp, f: impl Fn(f64, f64) -> f64) -> CSR {
+    check_shapes(a, b);
+    let mut ws = MergeWorkspace::new();
+
+    let mut row_pos = vec![0];
+    let mut col_pos = vec![];
+    let mut values = vec![];
+
+    for i in 0..a.shape.0 {
+        ws.merge_row(a.row_slices(i), b.row_slices(i), op, &f, &mut col_pos, &mut values);
+        row_pos.push(values.len());
+    }
+
+    CSR{row_pos, col_pos, values, shape: a.shape}
+}
+
+
+
+fn merge_par(a: &CSR, b: &CSR, op: MergeOp, f: impl Fn(f64, f64) -> f64 + Sync) -> CSR {
+    check_shapes(a, b);
+    let m = a.shape.0;
+
+    let counts: Vec<usize> = (0..m).into_par_iter()
+        .map_init(MergeWorkspace::new, |ws, i| ws.merge_count(a.row_slices(i), b.row_slices(i), op))
+        .collect();
+
+    let row_pos = row_pos_from_counts(&counts);
+    let nnz = row_pos[m];
+    let mut res = CSR{row_pos, col_pos: vec![0; nnz], values: vec![0.; nnz], shape: a.shape};
+
+    res.par_rows_pattern_mut()
+    .for_each_init(|| (MergeWorkspace::new(), vec![], vec![]), |(ws, cols, vals), (i, res_cols, res_vals)| {
+        ws.merge_row(a.row_slices(i), b.row_slices(i), op, &f, cols, vals);
+        res_cols.copy_from_slice(cols);
+        res_vals.copy_from_slice(vals);
+        cols.clear();
+        vals.clear();
+    });
+
+    res
+}
+
+
+
+// Buffers for sorting unsorted rows, one per thread
+struct MergeWorkspace {
+    pairs: Vec<(usize, f64)>,
+    a: (Vec<usize>, Vec<f64>),
+    b: (Vec<usize>, Vec<f64>)
+}
+
+
+impl MergeWorkspace {
+    fn new() -> Self {
+        MergeWorkspace{pairs: vec![], a: (vec![], vec![]), b: (vec![], vec![])}
+    }
+
+
+    // Appends the merged row to cols / vals. f gets 0 for a missing entry.
+    fn merge_row(&mut self, a_row: (&[usize], &[f64]), b_row: (&[usize], &[f64]), op: MergeOp,
+        f: impl Fn(f64, f64) -> f64, cols: &mut Vec<usize>, vals: &mut Vec<f64>) {
+        let (a_cols, a_vals) = sorted_row(a_row, &mut self.pairs, &mut self.a);
+        let (b_cols, b_vals) = sorted_row(b_row, &mut self.pairs, &mut self.b);
+
+        let (mut p, mut q) = (0, 0);
+        while p < a_cols.len() && q < b_cols.len() {
+            let (ja, jb) = (a_cols[p], b_cols[q]);
+            if ja == jb {
+                cols.push(ja);
+                vals.push(f(a_vals[p], b_vals[q]));
+                p += 1;
+                q += 1;
+            } else if ja < jb {
+                if op == MergeOp::Union {
+                    cols.push(ja);
+                    vals.push(f(a_vals[p], 0.));
+                }
+                p += 1;
+            } else {
+                if op == MergeOp::Union {
+                    cols.push(jb);
+                    vals.push(f(0., b_vals[q]));
+                }
+                q += 1;
+            }
+        }
+
+        if op == MergeOp::Union {
+            for (j, x) in a_cols[p..].iter().zip(&a_vals[p..]) {
+                cols.push(*j);
+                vals.push(f(*x, 0.));
+            }
+            for (j, y) in b_cols[q..].iter().zip(&b_vals[q..]) {
+                cols.push(*j);
+                vals.push(f(0., *y));
+            }
+        }
+    }
+
+
+    // nnz of the merged row, same merge on the column indices only
+    fn merge_count(&mut self, a_row: (&[usize], &[f64]), b_row: (&[usize], &[f64]), op: MergeOp) -> usize {
+        let (a_cols, _) = sorted_row(a_row, &mut self.pairs, &mut self.a);
+        let (b_cols, _) = sorted_row(b_row, &mut self.pairs, &mut self.b);
+
+        let (mut p, mut q, mut common) = (0, 0, 0);
+        while p < a_cols.len() && q < b_cols.len() {
+            match a_cols[p].cmp(&b_cols[q]) {
+                std::cmp::Ordering::Equal => { common += 1; p += 1; q += 1; }
+                std::cmp::Ordering::Less => p += 1,
+                std::cmp::Ordering::Greater => q += 1
+            }
+        }
+
+        match op {
+            MergeOp::Union => a_cols.len() + b_cols.len() - common,
+            MergeOp::Intersection => common
+        }
+    }
+}
+
+
+
+// The row itself if its column indices are sorted, otherwise a sorted copy in buf
+fn sorted_row<'a>(row: (&'a [usize], &'a [f64]), pairs: &mut Vec<(usize, f64)>,
+    buf: &'a mut (Vec<usize>, Vec<f64>)) -> (&'a [usize], &'a [f64]) {
+    let (cols, vals) = row;
+    if cols.is_sorted() {
+        return (cols, vals);
+    }
+
+    pairs.clear();
+    pairs.extend(cols.iter().copied().zip(vals.iter().copied()));
+    pairs.sort_by_key(|&(j, _)| j);
+
+    buf.0.clear();
+    buf.1.clear();
+    for &(j, x) in pairs.iter() {
+        buf.0.push(j);
+        buf.1.push(x);
+    }
+    (&buf.0, &buf.1)
+}
@@ -0,0 +1,236 @@
+use rayon::prelude::*;
+
+use matrix_base::Dense;
+
+
+// Blocked dense GEMM C += A*B for row-major matrices, structured as in
+// "BLIS: A Framework for Rapidly Instantiating BLAS Functionality", Van Zee and van de Geijn
+// https://doi.org/10.1145/2764454
+//
+// Loops around the micro kernel, from outer to inner:
+//   jc: NC columns of B and C
+//   pc: KC rows of B, the panel B~ (KC x NC) is packed once and shared by all threads
+//   ic: MC rows of A and C, in parallel (macro tiles), every task packs its own A~ (MC x KC)
+//   jr, ir: MR x NR micro tiles of C, computed in registers by the micro kernel
+//
+// A~ is stored as MR-row slivers column by column, B~ as NR-column slivers
+// row by row, so the micro kernel reads both contiguously. Edges are padded
+// with zeros in the packed buffers: the micro kernel always computes a full
+// MR x NR tile and only the valid part is added to C.
+//
+// std::simd is nightly only, so the micro kernel works on fixed size arrays
+// and relies on LLVM to vectorize them. On x86_64 with AVX2 and FMA
+// (checked at runtime) the kernel is compiled a second time for these features.
+//
+// Performance: the target is at most 2x the time of OpenBLAS (blas_dense),
+// workspace_runner records the ratio (benchmark_gemm). It is not met on every
+// machine: on one core of an AVX-512 Xeon at 2.1 GHz the GEMM reaches 16-25
+// GFLOP/s for n = 256..2048, i.e. 50-75% of the AVX2/FMA peak (33.6 GFLOP/s)
+// but only 25-35% of the AVX-512 peak (67 GFLOP/s) that OpenBLAS uses there,
+// so expect a ratio above 2 on AVX-512 hardware. No OpenBLAS was available
+// to measure the ratio itself on that machine.
+const MR: usize = 4;
+const NR: usize = 8;
+
+// A~ = MC*KC*8 bytes = 128 KiB (L2), B~ = KC*NC*8 bytes = 8 MiB (L3)
+const KC: usize = 256;
+const MC: usize = 64;
+const NC: usize = 4096;
+
+
+
+// Read-only view of a row-major matrix or of a block of it
+#[derive(Clone, Copy)]
+pub(crate) struct MatRef<'a> {
+    pub data: &'a [f64],
+    pub rows: usize,
+    pub cols: usize,
+    pub stride: usize
+}
+
+
+impl<'a> MatRef<'a> {
+    pub(crate) fn from_dense(a: &'a Dense) -> Self {
+        MatRef{data: &a.data, rows: a.shape.0, cols: a.shape.1, stride: a.shape.1}
+    }
+
+    pub(crate) fn get(&self, i: usize, j: usize) -> f64 {
+        self.data[i*self.stride + j]
+    }
+
+    // Block of size rows x cols starting at (r0, c0)
+    pub(crate) fn sub(&self, r0: usize, c0: usize, rows: usize, cols: usize) -> MatRef<'a> {
+        assert!(r0 + rows <= self.rows && c0 + cols <= self.cols, "Block out of bounds");
+        let start = (r0*self.stride + c0).min(self.data.len());
+        MatRef{data: &self.data[start..], rows, cols, stride: self.stride}
+    }
+}
+
+
+
+// Mutable view, see MatRef
+pub(crate) struct MatMut<'a> {
+    pub data: &'a mut [f64],
+    pub rows: usize,
+    pub cols: usize,
+    pub stride: usize
+}
+
+
+impl<'a> MatMut<'a> {
+    pub(crate) fn from_dense(a: &'a mut Dense) -> Self {
+        MatMut{rows: a.shape.0, cols: a.shape.1, stride: a.shape.1, data: &mut a.data}
+    }
+
+    pub(crate) fn row_mut(&mut self, i: usize) -> &mut [f64] {
+        &mut self.data[i*self.stride..i*self.stride + self.cols]
+    }
+
+    // Block of size rows x cols starting at (r0, c0)
+    pub(crate) fn sub(&mut self, r0: usize, c0: usize, rows: usize, cols: usize) -> MatMut<'_> {
+        assert!(r0 + rows <= self.rows && c0 + cols <= self.cols, "Block out of bounds");
+        let start = (r0*self.stride + c0).min(self.data.len());
+        MatMut{data: &mut self.data[start..], rows, cols, stride: self.stride}
+    }
+}
+
+
+
+// C += A*B
+pub(crate) fn gemm(a: MatRef, b: MatRef, c: &mut MatMut) {
+    assert_eq!(a.cols, b.rows, "Matrix dimensions do not match for multiplication");
+    assert!(c.rows == a.rows && c.cols == b.cols, "Result matrix has the wrong shape");
+
+    let (m, k, n) = (a.rows, a.cols, b.cols);
+    if m == 0 || n == 0 || k == 0 {
+        return;
+    }
+
+    let mut b_pack = vec![0.; KC * NC.min(n).next_multiple_of(NR)];
+
+    for jc in (0..n).step_by(NC) {
+        let nc = NC.min(n - jc);
+        for pc in (0..k).step_by(KC) {
+            let kc = KC.min(k - pc);
+
+            pack_b(b.sub(pc, jc, kc, nc), &mut b_pack);
+            let b_pack = &b_pack[..kc * nc.next_multiple_of(NR)];
+
+            // Macro tiles: MC rows of C each, disjoint, so no synchronization
+            let stride = c.stride;
+            c.data.par_chunks_mut(MC * stride).take(m.div_ceil(MC)).enumerate()
+            .for_each_init(|| vec![0.; MC * KC], |a_pack, (blk, c_rows)| {
+                let ic = blk * MC;
+                let mc = MC.min(m - ic);
+                pack_a(a.sub(ic, pc, mc, kc), a_pack);
+
+                let mut c_tile = MatMut{data: c_rows, rows: mc, cols: n, stride};
+                macro_kernel(kc, a_pack, b_pack, &mut c_tile, jc);
+            });
+        }
+    }
+}
+
+
+
+// A~: slivers of MR rows, within a sliver column p is a_pack[p*MR..(p+1)*MR]
+fn pack_a(a: MatRef, a_pack: &mut [f64]) {
+    let kc = a.cols;
+    for (s, sliver) in a_pack.chunks_mut(kc * MR).take(a.rows.div_ceil(MR)).enumerate() {
+        for (p, col) in sliver.chunks_exact_mut(MR).enumerate() {
+            for (r, x) in col.iter_mut().enumerate() {
+                let i = s*MR + r;
+                *x = if i < a.rows { a.get(i, p) } else { 0. };
+            }
+        }
+    }
+}
+
+
+
+// B~: slivers of NR columns, within a sliver row p is b_pack[p*NR..(p+1)*NR].
+// Packed in parallel, one sliver per task.
+fn pack_b(b: MatRef, b_pack: &mut [f64]) {
+    let kc = b.rows;
+    b_pack.par_chunks_mut(kc * NR).take(b.cols.div_ceil(NR)).enumerate().for_each(|(t, sliver)| {
+        for (p, row) in sliver.chunks_exact_mut(NR).enumerate() {
+            for (r, x) in row.iter_mut().enumerate() {
+                let j = t*NR + r;
+                *x = if j < b.cols { b.get(p, j) } else { 0. };
+            }
+        }
+    });
+}
+
+
+
+// All micro tiles of one macro tile. c holds the MC rows of the macro tile,
+// the columns of B~ start at column jc of c, c.cols is the width of all of C.
+fn macro_kernel(kc: usize, a_pack: &[f64], b_pack: &[f64], c: &mut MatMut, jc: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            // Safe, the features were just checked
+            unsafe { macro_kernel_fma(kc, a_pack, b_pack, c, jc) };
+            return;
+        }
+    }
+    macro_kernel_generic::<false>(kc, a_pack, b_pack, c, jc);
+}
+
+
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn macro_kernel_fma(kc: usize, a_pack: &[f64], b_pack: &[f64], c: &mut MatMut, jc: usize) {
+    macro_kernel_generic::<true>(kc, a_pack, b_pack, c, jc);
+}
+
+
+
+// FMA selects mul_add, which is only fast if the target has fused multiply-add
+#[inline(always)]
+fn macro_kernel_generic<const FMA: bool>(kc: usize, a_pack: &[f64], b_pack: &[f64], c: &mut MatMut, jc: usize) {
+    let n_slivers_b = b_pack.len() / (kc * NR);
+    let n_slivers_a = c.rows.div_ceil(MR);
+
+    for jr in 0..n_slivers_b {
+        let b_sliver = &b_pack[jr*kc*NR..(jr+1)*kc*NR];
+        let j0 = jr * NR;
+        let nr = NR.min(c.cols - jc - j0);
+
+        for ir in 0..n_slivers_a {
+            let a_sliver = &a_pack[ir*kc*MR..(ir+1)*kc*MR];
+            let tile = micro_kernel::<FMA>(a_sliver, b_sliver);
+
+            let i0 = ir * MR;
+            let mr = MR.min(c.rows - i0);
+            for (r, tile_row) in tile.iter().enumerate().take(mr) {
+                let c_row = &mut c.row_mut(i0 + r)[jc + j0..jc + j0 + nr];
+                for (c_ij, x) in c_row.iter_mut().zip(tile_row) {
+                    *c_ij += x;
+                }
+            }
+        }
+    }
+}
+
+
+
+// MR x NR tile of A~ B~ for one sliver of each, kept in registers
+#[inline(always)]
+fn micro_kernel<const FMA: bool>(a_sliver: &[f64], b_sliver: &[f64]) -> [[f64; NR]; MR] {
+    let mut tile = [[0.; NR]; MR];
+
+    for (a_p, b_p) in a_sliver.chunks_exact(MR).zip(b_sliver.chunks_exact(NR)) {
+        let a_p: &[f64; MR] = a_p.try_into().unwrap();
+        let b_p: &[f64; NR] = b_p.try_into().unwrap();
+        for (tile_row, a_ip) in tile.iter_mut().zip(a_p) {
+            for (t, b_pj) in tile_row.iter_mut().zip(b_p) {
+                *t = if FMA { a_ip.mul_add(*b_pj, *t) } else { *t + a_ip * b_pj };
+            }
+        }
+    }
+
+    tile
+}
@@ -1,8 +1,25 @@
 // pub mod csr;
 // pub use csr::CSR;
 
+pub mod accumulator;
+pub mod amg;
+pub mod chain;
+pub mod context;
 pub mod dense;
+pub mod direct;
+pub mod dropping;
+pub mod eigen;
+pub mod elementwise;
+mod gemm;
+pub mod mask;
+pub mod plan;
+pub mod precond;
+pub mod semiring;
+pub mod solvers;
 pub mod sparse;
+pub mod spmm;
+pub mod symmetric;
+pub mod triangular;
 
 
 
@@ -0,0 +1,56 @@
+use matrix_base::CSR;
+
+
+// Masked SpGEMM C<M> = A*B as in GraphBLAS: only the entries c_ij
+// with (i,j) allowed by the mask M are computed, see
+// "Parallel Algorithms for Masked Sparse Matrix-Matrix Products", Milaković et al.
+// https://doi.org/10.1145/3503221.3508430
+//
+// structural: every stored entry of M allows (i,j), also explicit zeros.
+//             Otherwise (value mask) only stored entries m_ij != 0 do.
+// complement: allowed is everything the mask does not allow, C<!M> = A*B.
+//
+// The default is the GraphBLAS default, a value mask without complement.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MaskOptions {
+    pub complement: bool,
+    pub structural: bool
+}
+
+
+
+// Row M_{i*} scattered into a dense marker of length n, so that
+// the Gustavson row loop can test every column in O(1).
+// Setting and resetting a row costs O(nnz(M_{i*})).
+pub(crate) struct MaskMarker {
+    marker: Vec<bool>,
+    opts: MaskOptions
+}
+
+
+impl MaskMarker {
+    pub(crate) fn new(n: usize, opts: MaskOptions) -> Self {
+        MaskMarker{marker: vec![false; n], opts}
+    }
+
+
+    pub(crate) fn set_row(&mut self, mask: &CSR, i: usize) {
+        for (j, m_ij) in mask.row(i) {
+            if self.opts.structural || *m_ij != 0. {
+                self.marker[j] = true;
+            }
+        }
+    }
+
+
+    pub(crate) fn reset_row(&mut self, mask: &CSR, i: usize) {
+        for (j, _) in mask.row(i) {
+            self.marker[j] = false;
+        }
+    }
+
+
+    pub(crate) fn keep(&self, j: usize) -> bool {
+        self.marker[j] != self.opts.complement
+    }
+}
@@ -0,0 +1,139 @@
+use rayon::prelude::*;
+
+use matrix_base::{RowPartition, CSR};
+
+use crate::accumulator::{product_row_pos_par, row_flops, row_pos_from_counts, spgemm_partition, Accumulator, SpGemmWorkspace};
+
+
+// Two-phase SpGEMM C = A*B for matrices with a fixed sparsity pattern,
+// see section 4 (two-phase method) of "A Systematic Survey of General Sparse
+// Matrix-Matrix Multiplication", Gao et al. https://doi.org/10.1145/3571157
+//
+// The symbolic phase (new) computes the exact row_pos and col_pos of C once,
+// together with the slot in C_{i*} of every product a_ik b_kj (in the order
+// of the Gustavson row loop), i.e. flops(A*B) indices.
+// The numeric phase (execute) only refills c.values through these slots and
+// can be called again whenever the values of A and B change, but not their
+// patterns. execute checks the shapes and nnz only, the patterns are
+// compared by their hashes (CSR::pattern_hash) in debug builds.
+//
+// let plan = SpGemmPlan::new(&a, &b);
+// let mut c = plan.output();
+// loop {
+//     // ... update a.values, b.values ...
+//     plan.execute(&a, &b, &mut c);
+// }
+pub struct SpGemmPlan {
+    pub row_pos: Vec<usize>,
+    pub col_pos: Vec<usize>,
+    pub shape: (usize, usize),
+    // Flop-balanced rows of A, for the numeric phase as well
+    partition: RowPartition,
+    // Slot in C_{i*} of the products of row i are slots[slot_pos[i]..slot_pos[i+1]]
+    slot_pos: Vec<usize>,
+    slots: Vec<usize>,
+    a_nnz: usize,
+    b_nnz: usize,
+    // Patterns of A, B and C the plan was built for, checked in debug builds
+    a_hash: u64,
+    b_hash: u64,
+    c_hash: u64
+}
+
+
+
+impl SpGemmPlan {
+    // Symbolic phase, parallel over the rows of A
+    pub fn new(a: &CSR, b: &CSR) -> Self {
+        assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+        let m = a.shape.0;
+        let n = b.shape.1;
+
+        // Count pass and prefix sum, then every row writes its
+        // column indices into its own slice (see SparseProd::product_sparse_par)
+        let partition = spgemm_partition(a, b);
+        let row_pos = product_row_pos_par(a, b, &partition);
+        let nnz = row_pos[m];
+        let mut pattern = CSR{row_pos, col_pos: vec![0; nnz], values: vec![0.; nnz], shape: (m,n)};
+
+        pattern.par_rows_pattern_mut_by(&partition)
+        .for_each_init(|| (SpGemmWorkspace::new(n), vec![]), |(ws, cols), (i, res_cols, _)| {
+            let (a_cols, _) = a.row_slices(i);
+            ws.row_structure(Accumulator::Auto, a_cols, b, cols);
+            res_cols.copy_from_slice(cols);
+            cols.clear();
+        });
+
+        // Slots of the products, every chunk of the partition fills its own part of slots
+        let flops: Vec<usize> = a.par_rows_by(&partition).map(|(_, a_cols, _)| row_flops(a_cols, b)).collect();
+        let slot_pos = row_pos_from_counts(&flops);
+        let mut slots = vec![0; slot_pos[m]];
+        let mut chunks = Vec::with_capacity(partition.bounds.len());
+        let mut rest = &mut slots[..];
+        for w in partition.bounds.windows(2) {
+            let (chunk, tail) = rest.split_at_mut(slot_pos[w[1]] - slot_pos[w[0]]);
+            chunks.push((w[0]..w[1], chunk));
+            rest = tail;
+        }
+        chunks.into_par_iter().for_each_init(|| vec![0; n], |positions, (rows, chunk)| {
+            let mut s = 0;
+            for i in rows {
+                let c_cols = &pattern.col_pos[pattern.row_pos[i]..pattern.row_pos[i+1]];
+                for (p, j) in c_cols.iter().enumerate() {
+                    positions[*j] = p;
+                }
+                for k in a.row_slices(i).0 {
+                    for (j, _) in b.row(*k) {
+                        chunk[s] = positions[j];
+                        s += 1;
+                    }
+                }
+            }
+        });
+
+        let c_hash = pattern.pattern_hash();
+        SpGemmPlan{row_pos: pattern.row_pos, col_pos: pattern.col_pos, shape: (m,n), partition, slot_pos, slots,
+            a_nnz: a.values.len(), b_nnz: b.values.len(), a_hash: a.pattern_hash(), b_hash: b.pattern_hash(), c_hash}
+    }
+
+
+    pub fn nnz(&self) -> usize {
+        self.col_pos.len()
+    }
+
+
+    // Result matrix with the pattern of the plan, all values 0.
+    // This is the only allocation, execute works in place.
+    pub fn output(&self) -> CSR {
+        CSR{row_pos: self.row_pos.clone(), col_pos: self.col_pos.clone(), values: vec![0.; self.nnz()], shape: self.shape}
+    }
+
+
+    // Numeric phase: c.values = values of A*B, no allocation.
+    // c must have the pattern of the plan (see output), A and B the
+    // patterns they had when the plan was built.
+    pub fn execute(&self, a: &CSR, b: &CSR, c: &mut CSR) {
+        assert_eq!((a.shape.0, b.shape.1), self.shape, "Shape of A*B does not match the plan");
+        assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+        assert!(a.values.len() == self.a_nnz && b.values.len() == self.b_nnz, "Pattern of A or B changed since the plan was built");
+        assert!(c.row_pos.len() == self.row_pos.len() && c.values.len() == self.nnz(), "Pattern of C does not match the plan");
+        debug_assert!(a.pattern_hash() == self.a_hash && b.pattern_hash() == self.b_hash, "Pattern of A or B changed since the plan was built");
+        debug_assert!(c.pattern_hash() == self.c_hash, "Pattern of C does not match the plan");
+
+        // Every row of C is owned by one task, which adds the products
+        // a_ik b_kj into their slots
+        c.par_rows_pattern_mut_by(&self.partition).for_each(|(i, _, c_vals)| {
+            let slots = &self.slots[self.slot_pos[i]..self.slot_pos[i+1]];
+            let (a_cols, a_vals) = a.row_slices(i);
+            c_vals.fill(0.);
+            let mut s = 0;
+            for (k, a_ik) in a_cols.iter().zip(a_vals) {
+                let (b_cols, b_vals) = b.row_slices(*k);
+                for (slot, b_kj) in slots[s..s + b_cols.len()].iter().zip(b_vals) {
+                    c_vals[*slot] += a_ik * b_kj;
+                }
+                s += b_cols.len();
+            }
+        });
+    }
+}
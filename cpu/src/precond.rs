@@ -0,0 +1,357 @@
+use rayon::prelude::*;
+
+use matrix_base::CSR;
+
+use crate::solvers::Preconditioner;
+use crate::triangular::{Diagonal, LevelSchedule, Triangle, TriangularAnalysis};
+
+
+// Preconditioners M ~ A for the Krylov solvers (see solvers.rs), see
+// chapter 10 of "Iterative Methods for Sparse Linear Systems", Saad
+// https://doi.org/10.1137/1.9780898718003
+//
+// Jacobi: M = D
+// Ssor:   M = 1/(w(2-w)) (D + wL) D^{-1} (D + wU), w = 1 is symmetric Gauss-Seidel
+// Ilu0:   M = LU, L unit lower, U upper, both on the pattern of A (no fill-in)
+// Ic0:    M = LL^T, L on the pattern of the lower triangle of A, A SPD
+//
+// All of them work on the CSR pattern of A, which needs sorted rows with a
+// stored diagonal. Constructing fails (Err) on a zero or missing diagonal,
+// resp. a zero or negative pivot in the factorization.
+//
+// apply_serial solves the triangular systems row by row. apply_par, which
+// is used by Preconditioner::apply, solves them level by level with the
+// rows of a level in parallel (see triangular). Both give the same result.
+
+
+
+pub struct Jacobi {
+    inv_diag: Vec<f64>
+}
+
+
+impl Jacobi {
+    pub fn new(a: &CSR) -> Result<Self, &'static str> {
+        if a.shape.0 != a.shape.1 {
+            return Err("Matrix is not square");
+        }
+        let diag = a.diagonal();
+        if diag.len() != a.shape.0 || diag.contains(&0.) {
+            return Err("Jacobi needs a non-zero diagonal");
+        }
+        Ok(Jacobi{inv_diag: diag.iter().map(|d| 1. / d).collect()})
+    }
+
+
+    pub fn apply_serial(&self, r: &[f64], z: &mut [f64]) {
+        for ((z_i, r_i), d_i) in z.iter_mut().zip(r).zip(&self.inv_diag) {
+            *z_i = r_i * d_i;
+        }
+    }
+
+
+    pub fn apply_par(&self, r: &[f64], z: &mut [f64]) {
+        z.par_iter_mut().zip(r).zip(&self.inv_diag).for_each(|((z_i, r_i), d_i)| *z_i = r_i * d_i);
+    }
+}
+
+
+impl Preconditioner for Jacobi {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_par(r, z);
+    }
+}
+
+
+
+// Position of a_ii in every row, Err if a row is unsorted or has no diagonal
+fn diagonal_positions(a: &CSR) -> Result<Vec<usize>, &'static str> {
+    if a.shape.0 != a.shape.1 {
+        return Err("Matrix is not square");
+    }
+    (0..a.shape.0).map(|i| {
+        let (cols, _) = a.row_slices(i);
+        if !cols.is_sorted() {
+            return Err("Rows of the matrix must be sorted");
+        }
+        cols.binary_search(&i).map(|p| a.row_pos[i] + p).map_err(|_| "Missing diagonal entry")
+    }).collect()
+}
+
+
+
+pub struct Ssor<'a> {
+    a: &'a CSR,
+    sweep: SsorSweep
+}
+
+
+impl<'a> Ssor<'a> {
+    // 0 < omega < 2
+    pub fn new(a: &'a CSR, omega: f64) -> Result<Self, &'static str> {
+        Ok(Ssor{a, sweep: SsorSweep::new(a, omega)?})
+    }
+
+
+    // Symmetric Gauss-Seidel, omega = 1
+    pub fn gauss_seidel(a: &'a CSR) -> Result<Self, &'static str> {
+        Ssor::new(a, 1.)
+    }
+
+
+    // Number of levels of the lower and the upper triangular solve
+    pub fn n_levels(&self) -> (usize, usize) {
+        (self.sweep.lower.n_levels(), self.sweep.upper.n_levels())
+    }
+
+
+    pub fn apply_serial(&self, r: &[f64], z: &mut [f64]) {
+        self.sweep.apply(self.a, r, z, false);
+    }
+
+
+    pub fn apply_par(&self, r: &[f64], z: &mut [f64]) {
+        self.sweep.apply(self.a, r, z, true);
+    }
+}
+
+
+// Everything of Ssor but the matrix, for owners of A (see amg)
+pub(crate) struct SsorSweep {
+    omega: f64,
+    diag: Vec<f64>,
+    lower: LevelSchedule,
+    upper: LevelSchedule
+}
+
+
+impl SsorSweep {
+    pub(crate) fn new(a: &CSR, omega: f64) -> Result<Self, &'static str> {
+        if !(omega > 0. && omega < 2.) {
+            return Err("SSOR needs 0 < omega < 2");
+        }
+        let diag_pos = diagonal_positions(a)?;
+        let diag: Vec<f64> = diag_pos.iter().map(|p| a.values[*p]).collect();
+        if diag.contains(&0.) {
+            return Err("SSOR needs a non-zero diagonal");
+        }
+        Ok(SsorSweep{omega, diag, lower: LevelSchedule::new(a, Triangle::Lower), upper: LevelSchedule::new(a, Triangle::Upper)})
+    }
+
+
+    // z = M^{-1} r for the A the sweep was set up for:
+    // (D + wL) y = w(2-w) r, then (D + wU) z = D y.
+    // y is kept in z, z_i overwrites y_i only after row i of the upper solve read it
+    pub(crate) fn apply(&self, a: &CSR, r: &[f64], z: &mut [f64], par: bool) {
+        assert_eq!(r.len(), z.len(), "Matrix and vector dimensions do not match");
+        let (w, d) = (self.omega, &self.diag);
+        let scale = w * (2. - w);
+
+        let lower = |i: usize, y: &[f64]| {
+            let (cols, vals) = a.row_slices(i);
+            let s: f64 = cols.iter().zip(vals).filter(|(j, _)| **j < i).map(|(j, a_ij)| a_ij * y[*j]).sum();
+            (scale * r[i] - w * s) / d[i]
+        };
+        if par { self.lower.solve_par(z, lower) } else { self.lower.solve(z, lower) }
+
+        let upper = |i: usize, z: &[f64]| {
+            let (cols, vals) = a.row_slices(i);
+            let s: f64 = cols.iter().zip(vals).filter(|(j, _)| **j > i).map(|(j, a_ij)| a_ij * z[*j]).sum();
+            z[i] - w * s / d[i]
+        };
+        if par { self.upper.solve_par(z, upper) } else { self.upper.solve(z, upper) }
+    }
+}
+
+
+impl Preconditioner for Ssor<'_> {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_par(r, z);
+    }
+}
+
+
+
+// L and U in one CSR with the pattern of A: the strict lower part is L
+// (unit diagonal not stored), the rest is U
+pub struct Ilu0 {
+    lu: CSR,
+    lower: TriangularAnalysis,
+    upper: TriangularAnalysis
+}
+
+
+impl Ilu0 {
+    // IKJ variant of Gaussian elimination restricted to the pattern of A,
+    // algorithm 10.4 in Saad. The columns of the current row are
+    // scattered into a marker, so that updates outside the pattern are dropped in O(1).
+    pub fn new(a: &CSR) -> Result<Self, &'static str> {
+        let diag_pos = diagonal_positions(a)?;
+        let n = a.shape.0;
+        let mut lu = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+
+        let mut marker = vec![usize::MAX; n];
+        for i in 0..n {
+            let row = lu.row_pos[i]..lu.row_pos[i+1];
+            for p in row.clone() {
+                marker[lu.col_pos[p]] = p;
+            }
+
+            for p in row.start..diag_pos[i] {
+                let k = lu.col_pos[p];
+                let pivot = lu.values[diag_pos[k]];
+                if pivot == 0. {
+                    return Err("Zero pivot in ILU(0)");
+                }
+                let l_ik = lu.values[p] / pivot;
+                lu.values[p] = l_ik;
+                // a_ij -= l_ik u_kj for j > k in the pattern of row i
+                for q in diag_pos[k]+1..lu.row_pos[k+1] {
+                    let m = marker[lu.col_pos[q]];
+                    if m != usize::MAX {
+                        lu.values[m] -= l_ik * lu.values[q];
+                    }
+                }
+            }
+
+            if lu.values[diag_pos[i]] == 0. || !lu.values[diag_pos[i]].is_finite() {
+                return Err("Zero pivot in ILU(0)");
+            }
+            for p in row {
+                marker[lu.col_pos[p]] = usize::MAX;
+            }
+        }
+
+        let lower = TriangularAnalysis::new(&lu, Triangle::Lower, false, Diagonal::Unit)?;
+        let upper = TriangularAnalysis::new(&lu, Triangle::Upper, false, Diagonal::NonUnit)?;
+        Ok(Ilu0{lu, lower, upper})
+    }
+
+
+    // Number of levels of the lower and the upper triangular solve
+    pub fn n_levels(&self) -> (usize, usize) {
+        (self.lower.n_levels(), self.upper.n_levels())
+    }
+
+
+    pub fn apply_serial(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_with(r, z, false);
+    }
+
+
+    pub fn apply_par(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_with(r, z, true);
+    }
+
+
+    // L y = r, then U z = y, with y in z
+    fn apply_with(&self, r: &[f64], z: &mut [f64], par: bool) {
+        z.copy_from_slice(r);
+        self.lower.solve_owned(&self.lu, z, par);
+        self.upper.solve_owned(&self.lu, z, par);
+    }
+}
+
+
+impl Preconditioner for Ilu0 {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_par(r, z);
+    }
+}
+
+
+
+// L with the pattern of the lower triangle of A, the backward solve
+// reads L^T from L (see TriangularAnalysis)
+pub struct Ic0 {
+    l: CSR,
+    lower: TriangularAnalysis,
+    upper: TriangularAnalysis
+}
+
+
+impl Ic0 {
+    // Row-wise Cholesky restricted to the pattern:
+    // l_ik = (a_ik - \sum_{j<k} l_ij l_kj) / l_kk,  l_ii = sqrt(a_ii - \sum_{j<i} l_ij^2)
+    // where the sums run over the common pattern of the (sorted) rows i and k of L.
+    // Only the lower triangle of A is read.
+    pub fn new(a: &CSR) -> Result<Self, &'static str> {
+        diagonal_positions(a)?;
+        let n = a.shape.0;
+
+        let mut row_pos = vec![0];
+        let mut col_pos = vec![];
+        let mut values = vec![];
+        for (i, cols, vals) in a.rows() {
+            for (j, x) in cols.iter().zip(vals).filter(|(j, _)| **j <= i) {
+                col_pos.push(*j);
+                values.push(*x);
+            }
+            row_pos.push(values.len());
+        }
+        let mut l = CSR{row_pos, col_pos, values, shape: (n, n)};
+
+        for i in 0..n {
+            let (start, end) = (l.row_pos[i], l.row_pos[i+1]);
+            for p in start..end {
+                let k = l.col_pos[p];
+                // Sparse dot product of the rows i and k of L left of column k
+                let (mut q, mut s) = (l.row_pos[k], 0.);
+                for pi in start..p {
+                    let j = l.col_pos[pi];
+                    while q < l.row_pos[k+1] && l.col_pos[q] < j {
+                        q += 1;
+                    }
+                    if q < l.row_pos[k+1] && l.col_pos[q] == j {
+                        s += l.values[pi] * l.values[q];
+                    }
+                }
+
+                if k < i {
+                    l.values[p] = (l.values[p] - s) / l.values[l.row_pos[k+1] - 1];
+                } else {
+                    let d = l.values[p] - s;
+                    if d <= 0. || !d.is_finite() {
+                        return Err("Non-positive pivot in IC(0), the matrix is not SPD enough");
+                    }
+                    l.values[p] = d.sqrt();
+                }
+            }
+        }
+
+        let lower = TriangularAnalysis::new(&l, Triangle::Lower, false, Diagonal::NonUnit)?;
+        let upper = TriangularAnalysis::new(&l, Triangle::Lower, true, Diagonal::NonUnit)?;
+        Ok(Ic0{l, lower, upper})
+    }
+
+
+    // Number of levels of the lower and the upper triangular solve
+    pub fn n_levels(&self) -> (usize, usize) {
+        (self.lower.n_levels(), self.upper.n_levels())
+    }
+
+
+    pub fn apply_serial(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_with(r, z, false);
+    }
+
+
+    pub fn apply_par(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_with(r, z, true);
+    }
+
+
+    // L y = r, then L^T z = y, with y in z
+    fn apply_with(&self, r: &[f64], z: &mut [f64], par: bool) {
+        z.copy_from_slice(r);
+        self.lower.solve_owned(&self.l, z, par);
+        self.upper.solve_owned(&self.l, z, par);
+    }
+}
+
+
+impl Preconditioner for Ic0 {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        self.apply_par(r, z);
+    }
+}
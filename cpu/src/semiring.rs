@@ -0,0 +1,74 @@
+// Semirings for the SpGEMM kernels, as in GraphBLAS:
+// C = A (+).(x) B with c_ij = (+)_k a_ik (x) b_kj
+// where (+) is add and (x) is mul. identity is the identity of add,
+// i.e. the value of an entry that is not stored.
+//
+// Only stored entries take part in a product, so for e.g. MinPlus the
+// missing entries are +inf (no edge), not 0.
+//
+// E.g. one step of shortest paths (Bellman-Ford) from d:
+// d.product_sparse_semiring::<MinPlus>(&a)
+pub trait Semiring {
+    fn identity() -> f64;
+    fn add(x: f64, y: f64) -> f64;
+    fn mul(x: f64, y: f64) -> f64;
+}
+
+
+
+// Usual (+, *), default of SparseProd
+pub struct PlusTimes;
+
+// (min, +), shortest paths (APSP, SSSP)
+pub struct MinPlus;
+
+// (max, *), most reliable paths for weights in [0,1]
+pub struct MaxTimes;
+
+// (or, and), reachability, e.g. BFS. Values != 0 are true, results are 1 or 0.
+pub struct OrAnd;
+
+// (+, pair) with pair(x, y) = 1, counts the products, e.g. number of paths
+pub struct PlusPair;
+
+
+
+impl Semiring for PlusTimes {
+    fn identity() -> f64 { 0. }
+    fn add(x: f64, y: f64) -> f64 { x + y }
+    fn mul(x: f64, y: f64) -> f64 { x * y }
+}
+
+
+impl Semiring for MinPlus {
+    fn identity() -> f64 { f64::INFINITY }
+    fn add(x: f64, y: f64) -> f64 { x.min(y) }
+    fn mul(x: f64, y: f64) -> f64 { x + y }
+}
+
+
+impl Semiring for MaxTimes {
+    fn identity() -> f64 { f64::NEG_INFINITY }
+    fn add(x: f64, y: f64) -> f64 { x.max(y) }
+    fn mul(x: f64, y: f64) -> f64 { x * y }
+}
+
+
+impl Semiring for OrAnd {
+    fn identity() -> f64 { 0. }
+    fn add(x: f64, y: f64) -> f64 { bool_value(x != 0. || y != 0.) }
+    fn mul(x: f64, y: f64) -> f64 { bool_value(x != 0. && y != 0.) }
+}
+
+
+impl Semiring for PlusPair {
+    fn identity() -> f64 { 0. }
+    fn add(x: f64, y: f64) -> f64 { x + y }
+    fn mul(_: f64, _: f64) -> f64 { 1. }
+}
+
+
+
+fn bool_value(b: bool) -> f64 {
+    if b { 1. } else { 0. }
+}
@@ -0,0 +1,410 @@
+use rayon::prelude::*;
+
+use matrix_base::LinearOperator;
+
+
+// Krylov solvers for A x = b, see
+// "Iterative Methods for Sparse Linear Systems", Saad, 2nd edition
+// https://doi.org/10.1137/1.9780898718003
+//
+// cg:       A symmetric positive definite (algorithm 9.1, preconditioned)
+// bicgstab: general A (algorithm 7.7, right preconditioned)
+// gmres:    general A, restarted after opts.restart iterations
+//           (algorithm 9.5, right preconditioned, Givens rotations)
+//
+// A is any LinearOperator, only products A x are used.
+// x holds the initial guess and is overwritten by the solution.
+// All solvers stop once ||b - A x|| <= max(rtol ||b||, atol) and return a
+// SolverReport. Not converging (or a breakdown of the method) is reported,
+// not a panic; only mismatching dimensions panic.
+//
+// With right preconditioning the residual monitored by BiCGSTAB and GMRES is
+// the one of the original system, so all three solvers use the same criterion.
+
+
+
+// Applies z = M^{-1} r for a preconditioner M ~ A.
+// Closures |r, z| ... can be used directly.
+pub trait Preconditioner {
+    fn apply(&self, r: &[f64], z: &mut [f64]);
+}
+
+
+// M = I, no preconditioning
+pub struct IdentityPrecond;
+
+
+impl Preconditioner for IdentityPrecond {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        z.copy_from_slice(r);
+    }
+}
+
+
+impl<F: Fn(&[f64], &mut [f64])> Preconditioner for F {
+    fn apply(&self, r: &[f64], z: &mut [f64]) {
+        self(r, z)
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolverOptions {
+    pub rtol: f64,
+    pub atol: f64,
+    pub max_iter: usize,
+    // Krylov dimension of GMRES(m)
+    pub restart: usize,
+    // Keep ||r_k|| / ||b|| of every iteration in SolverReport::history
+    pub record_history: bool
+}
+
+
+impl Default for SolverOptions {
+    fn default() -> Self {
+        SolverOptions{rtol: 1e-8, atol: 0., max_iter: 1000, restart: 30, record_history: true}
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SolverStatus {
+    Converged,
+    MaxIterations,
+    // The method cannot continue, e.g. p^T A p <= 0 in CG (A not SPD),
+    // a vanishing inner product in BiCGSTAB or a non-finite residual
+    Breakdown
+}
+
+
+
+// history[0] is the initial residual, history[k] the one after iteration k,
+// all relative to ||b|| (absolute if b = 0)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolverReport {
+    pub status: SolverStatus,
+    pub iterations: usize,
+    pub residual_norm: f64,
+    pub relative_residual: f64,
+    pub history: Vec<f64>
+}
+
+
+impl SolverReport {
+    pub fn converged(&self) -> bool {
+        self.status == SolverStatus::Converged
+    }
+}
+
+
+
+// Minimal vector length per rayon task of the vector operations
+pub(crate) const VEC_MIN_LEN: usize = 4096;
+
+
+pub(crate) fn dot(x: &[f64], y: &[f64]) -> f64 {
+    x.par_iter().zip(y).with_min_len(VEC_MIN_LEN).map(|(a, b)| a * b).sum()
+}
+
+
+pub(crate) fn norm(x: &[f64]) -> f64 {
+    dot(x, x).sqrt()
+}
+
+
+// y += alpha * x
+pub(crate) fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+    y.par_iter_mut().zip(x).with_min_len(VEC_MIN_LEN).for_each(|(y_i, x_i)| *y_i += alpha * x_i);
+}
+
+
+// r = b - A x
+pub(crate) fn residual(a: &dyn LinearOperator, b: &[f64], x: &[f64], r: &mut [f64]) {
+    a.apply(x, r);
+    r.par_iter_mut().zip(b).with_min_len(VEC_MIN_LEN).for_each(|(r_i, b_i)| *r_i = b_i - *r_i);
+}
+
+
+
+// Convergence test and residual history shared by the solvers
+pub(crate) struct Monitor {
+    b_norm: f64,
+    tol: f64,
+    record: bool,
+    history: Vec<f64>
+}
+
+
+impl Monitor {
+    pub(crate) fn new(b: &[f64], opts: &SolverOptions) -> Self {
+        let b_norm = norm(b);
+        Monitor{b_norm, tol: (opts.rtol * b_norm).max(opts.atol), record: opts.record_history, history: vec![]}
+    }
+
+
+    pub(crate) fn relative(&self, r_norm: f64) -> f64 {
+        if self.b_norm > 0. { r_norm / self.b_norm } else { r_norm }
+    }
+
+
+    // Records r_norm, Some(status) if the solver has to stop
+    pub(crate) fn check(&mut self, r_norm: f64) -> Option<SolverStatus> {
+        if self.record {
+            self.history.push(self.relative(r_norm));
+        }
+        if !r_norm.is_finite() {
+            Some(SolverStatus::Breakdown)
+        } else if r_norm <= self.tol {
+            Some(SolverStatus::Converged)
+        } else {
+            None
+        }
+    }
+
+
+    pub(crate) fn report(self, status: SolverStatus, iterations: usize, r_norm: f64) -> SolverReport {
+        SolverReport{status, iterations, residual_norm: r_norm, relative_residual: self.relative(r_norm), history: self.history}
+    }
+}
+
+
+
+pub(crate) fn check_dims(a: &dyn LinearOperator, b: &[f64], x: &[f64]) {
+    let (m, n) = a.shape();
+    assert_eq!(m, n, "Matrix is not square");
+    assert_eq!(m, b.len(), "Matrix and vector dimensions do not match");
+    assert_eq!(m, x.len(), "Matrix and vector dimensions do not match");
+}
+
+
+
+// Preconditioned conjugate gradients
+pub fn cg(a: &dyn LinearOperator, b: &[f64], x: &mut [f64], precond: &dyn Preconditioner, opts: &SolverOptions) -> SolverReport {
+    check_dims(a, b, x);
+    let n = b.len();
+    let mut mon = Monitor::new(b, opts);
+
+    let mut r = vec![0.; n];
+    residual(a, b, x, &mut r);
+    let mut r_norm = norm(&r);
+    if let Some(status) = mon.check(r_norm) {
+        return mon.report(status, 0, r_norm);
+    }
+
+    let mut z = vec![0.; n];
+    precond.apply(&r, &mut z);
+    let mut p = z.clone();
+    let mut q = vec![0.; n];
+    let mut rz = dot(&r, &z);
+
+    for k in 1..=opts.max_iter {
+        a.apply(&p, &mut q);
+        let pq = dot(&p, &q);
+        if pq <= 0. || !pq.is_finite() {
+            return mon.report(SolverStatus::Breakdown, k-1, r_norm);
+        }
+
+        let alpha = rz / pq;
+        axpy(alpha, &p, x);
+        axpy(-alpha, &q, &mut r);
+
+        r_norm = norm(&r);
+        if let Some(status) = mon.check(r_norm) {
+            return mon.report(status, k, r_norm);
+        }
+
+        precond.apply(&r, &mut z);
+        let rz_new = dot(&r, &z);
+        let beta = rz_new / rz;
+        rz = rz_new;
+        // p = z + beta p
+        p.par_iter_mut().zip(&z).with_min_len(VEC_MIN_LEN).for_each(|(p_i, z_i)| *p_i = z_i + beta * *p_i);
+    }
+
+    mon.report(SolverStatus::MaxIterations, opts.max_iter, r_norm)
+}
+
+
+
+// BiCGSTAB, van der Vorst, https://doi.org/10.1137/0913035
+pub fn bicgstab(a: &dyn LinearOperator, b: &[f64], x: &mut [f64], precond: &dyn Preconditioner, opts: &SolverOptions) -> SolverReport {
+    check_dims(a, b, x);
+    let n = b.len();
+    let mut mon = Monitor::new(b, opts);
+
+    let mut r = vec![0.; n];
+    residual(a, b, x, &mut r);
+    let mut r_norm = norm(&r);
+    if let Some(status) = mon.check(r_norm) {
+        return mon.report(status, 0, r_norm);
+    }
+
+    // Shadow residual
+    let r_hat = r.clone();
+    let (mut rho, mut alpha, mut omega) = (1., 1., 1.);
+    let mut v = vec![0.; n];
+    let mut p = vec![0.; n];
+    let mut p_hat = vec![0.; n];
+    let mut s_hat = vec![0.; n];
+    let mut t = vec![0.; n];
+
+    for k in 1..=opts.max_iter {
+        let rho_new = dot(&r_hat, &r);
+        if rho_new == 0. || !rho_new.is_finite() {
+            return mon.report(SolverStatus::Breakdown, k-1, r_norm);
+        }
+
+        // p = r + beta (p - omega v)
+        let beta = (rho_new / rho) * (alpha / omega);
+        rho = rho_new;
+        p.par_iter_mut().zip(&r).zip(&v).with_min_len(VEC_MIN_LEN)
+            .for_each(|((p_i, r_i), v_i)| *p_i = r_i + beta * (*p_i - omega * v_i));
+
+        precond.apply(&p, &mut p_hat);
+        a.apply(&p_hat, &mut v);
+        let r_hat_v = dot(&r_hat, &v);
+        if r_hat_v == 0. {
+            return mon.report(SolverStatus::Breakdown, k-1, r_norm);
+        }
+        alpha = rho / r_hat_v;
+
+        // s = r - alpha v, stored in r
+        axpy(-alpha, &v, &mut r);
+        axpy(alpha, &p_hat, x);
+        let s_norm = norm(&r);
+        if s_norm <= mon.tol {
+            r_norm = s_norm;
+            mon.check(r_norm);
+            return mon.report(SolverStatus::Converged, k, r_norm);
+        }
+
+        precond.apply(&r, &mut s_hat);
+        a.apply(&s_hat, &mut t);
+        let tt = dot(&t, &t);
+        omega = if tt > 0. { dot(&t, &r) / tt } else { 0. };
+
+        axpy(omega, &s_hat, x);
+        axpy(-omega, &t, &mut r);
+
+        r_norm = norm(&r);
+        if let Some(status) = mon.check(r_norm) {
+            return mon.report(status, k, r_norm);
+        }
+        if omega == 0. {
+            return mon.report(SolverStatus::Breakdown, k, r_norm);
+        }
+    }
+
+    mon.report(SolverStatus::MaxIterations, opts.max_iter, r_norm)
+}
+
+
+
+// Restarted GMRES(m), m = opts.restart.
+// Arnoldi with modified Gram-Schmidt on A M^{-1}, the least squares problem
+// is updated with Givens rotations, so |g_{j+1}| is the residual norm
+// without forming x. x = x_0 + M^{-1} V_j y at every restart.
+pub fn gmres(a: &dyn LinearOperator, b: &[f64], x: &mut [f64], precond: &dyn Preconditioner, opts: &SolverOptions) -> SolverReport {
+    check_dims(a, b, x);
+    assert!(opts.restart > 0, "GMRES needs a restart length of at least 1");
+    let n = b.len();
+    let m = opts.restart;
+    let mut mon = Monitor::new(b, opts);
+
+    let mut r = vec![0.; n];
+    residual(a, b, x, &mut r);
+    let mut r_norm = norm(&r);
+    if let Some(status) = mon.check(r_norm) {
+        return mon.report(status, 0, r_norm);
+    }
+
+    // Krylov basis, Hessenberg matrix (column j in h[j]), Givens rotations
+    let mut basis: Vec<Vec<f64>> = Vec::with_capacity(m+1);
+    let mut h = vec![vec![0.; m+1]; m];
+    let mut cs = vec![0.; m];
+    let mut sn = vec![0.; m];
+    let mut g = vec![0.; m+1];
+    let mut z = vec![0.; n];
+    let mut iterations = 0;
+
+    while iterations < opts.max_iter {
+        basis.clear();
+        basis.push(r.iter().map(|r_i| r_i / r_norm).collect());
+        g.iter_mut().for_each(|g_i| *g_i = 0.);
+        g[0] = r_norm;
+
+        let mut j = 0;
+        let mut status = None;
+        while j < m && iterations < opts.max_iter {
+            // w = A M^{-1} v_j
+            precond.apply(&basis[j], &mut z);
+            let mut w = vec![0.; n];
+            a.apply(&z, &mut w);
+
+            for (i, v_i) in basis.iter().enumerate() {
+                h[j][i] = dot(&w, v_i);
+                axpy(-h[j][i], v_i, &mut w);
+            }
+            h[j][j+1] = norm(&w);
+
+            // Previous rotations on the new column, then a new one for h_{j+1,j}
+            for i in 0..j {
+                let tmp = cs[i] * h[j][i] + sn[i] * h[j][i+1];
+                h[j][i+1] = -sn[i] * h[j][i] + cs[i] * h[j][i+1];
+                h[j][i] = tmp;
+            }
+            let d = h[j][j].hypot(h[j][j+1]);
+            if d == 0. {
+                status = Some(SolverStatus::Breakdown);
+                break;
+            }
+            cs[j] = h[j][j] / d;
+            sn[j] = h[j][j+1] / d;
+            let w_norm = h[j][j+1];
+            h[j][j] = d;
+            h[j][j+1] = 0.;
+            g[j+1] = -sn[j] * g[j];
+            g[j] *= cs[j];
+
+            iterations += 1;
+            j += 1;
+
+            r_norm = g[j].abs();
+            status = mon.check(r_norm);
+            // Happy breakdown: the Krylov space is invariant, x is exact
+            if status.is_some() || w_norm == 0. {
+                break;
+            }
+            basis.push(w.iter().map(|w_i| w_i / w_norm).collect());
+        }
+
+        // y = H_j^{-1} g_j by back substitution, x += M^{-1} V_j y
+        let mut y = g[..j].to_vec();
+        for i in (0..j).rev() {
+            y[i] /= h[i][i];
+            for l in 0..i {
+                y[l] -= h[i][l] * y[i];
+            }
+        }
+        let mut update = vec![0.; n];
+        for (v_i, y_i) in basis.iter().zip(&y) {
+            axpy(*y_i, v_i, &mut update);
+        }
+        precond.apply(&update, &mut z);
+        axpy(1., &z, x);
+
+        // True residual for the restart, and to confirm convergence
+        residual(a, b, x, &mut r);
+        r_norm = norm(&r);
+        match status {
+            Some(SolverStatus::Breakdown) => return mon.report(SolverStatus::Breakdown, iterations, r_norm),
+            _ if r_norm <= mon.tol => return mon.report(SolverStatus::Converged, iterations, r_norm),
+            _ if !r_norm.is_finite() => return mon.report(SolverStatus::Breakdown, iterations, r_norm),
+            _ => {}
+        }
+    }
+
+    mon.report(SolverStatus::MaxIterations, iterations, r_norm)
+}
@@ -1,9 +1,12 @@
-use std::sync::{Mutex, Arc};
-
 use rayon::prelude::*;
 
 use matrix_base::{Dense, CSR, COO};
 
+use crate::accumulator::{product_row_pos_par, row_pos_from_counts, spgemm_partition, Accumulator, SpGemmWorkspace};
+use crate::dropping::{DropOptions, RowDropper};
+use crate::mask::{MaskMarker, MaskOptions};
+use crate::semiring::{PlusTimes, Semiring};
+
 
 
 
@@ -12,6 +15,16 @@ pub trait SparseProd {
     fn product_sparse(&self, other: &CSR) -> CSR;
     fn product_sparse_par(&self, other: &CSR) -> CSR;
     fn product_sparse_to_coo_par(&self, other: &CSR) -> COO;
+    fn product_sparse_with(&self, other: &CSR, acc: Accumulator) -> CSR;
+    fn product_sparse_par_with(&self, other: &CSR, acc: Accumulator) -> CSR;
+    fn product_sparse_masked(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR;
+    fn product_sparse_masked_par(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR;
+    fn product_sparse_semiring<S: Semiring>(&self, other: &CSR) -> CSR;
+    fn product_sparse_semiring_par<S: Semiring>(&self, other: &CSR) -> CSR;
+    fn product_sparse_masked_semiring<S: Semiring>(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR;
+    fn product_sparse_masked_semiring_par<S: Semiring>(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR;
+    fn product_sparse_dropped(&self, other: &CSR, opts: DropOptions) -> CSR;
+    fn product_sparse_dropped_par(&self, other: &CSR, opts: DropOptions) -> CSR;
 }
 
 
@@ -30,22 +43,11 @@ impl SparseProd for CSR {
         // let mut mat = vec![vec![0.;n];m];
         let mut mat = Dense::new_zeros((m,n));
 
-        for i in 0..m {
+        for (i, k, a_ik) in self.iter() {
             // iterate over all non-zero cols of A_{i*}
-            // let cols = self.col_pos[i]..self.col_pos[i+1];
-            for col_pos_pos in self.row_pos[i]..self.row_pos[i+1] {
-                let k = self.col_pos[col_pos_pos];
-
-                for other_col_pos_pos in other.row_pos[k]..other.row_pos[k+1] {
-                    let j = other.col_pos[other_col_pos_pos];
-
-                    // C_{i*} = \sum_{k \in I_i (A)} a_{ik} b_{i*}
-                    // a_{ik} = self.values[col_pos_pos]
-                    // b_{kj} = other.values[other_col_pos_pos]
-                    // mat[i][j] += self.values[col_pos_pos] * other.values[other_col_pos_pos]
-                    mat.set(i,j , mat.get(i, j) +   self.values[col_pos_pos] * other.values[other_col_pos_pos]);
-
-                }
+            for (j, b_kj) in other.row(k) {
+                // C_{i*} = \sum_{k \in I_i (A)} a_{ik} b_{i*}
+                mat.set(i, j, mat.get(i, j) + a_ik * b_kj);
             }
         }
 
@@ -56,256 +58,303 @@ impl SparseProd for CSR {
 
 
     // For the general algorithm see above
-    // This is a modification for directly saving CSR
-    // via dense intermediate results, suited for
-    // parallel execution
+    // This is a modification for directly saving CSR,
+    // the row C_{i*} is collected in an accumulator (see accumulator.rs).
+    // Accumulator::Auto picks the accumulator per row.
     fn product_sparse(&self, other: &CSR) -> CSR {
-        let m = self.shape.0;
-        let n = other.shape.1;
-        // let mut mat = Dense::new_zeros((m,n));
+        self.product_sparse_with(other, Accumulator::Auto)
+    }
 
-        let mut res_rows: Vec<Vec<f64>> = vec![];
-        let mut res_col_idxs: Vec<Vec<usize>> = vec![];
 
-        for i in 0..m {
-            // Iterate over all non-zero cols of A_{i*}
 
-            // Create a dense row for the result matrix, C_{i*}
-            // and also a bool array that flags if some non-zero
-            // entry for the k-th (i.e. for C_{ik}) is calculated.            
-            let mut nz_row_marker = vec![false;n];
-            let mut res_curr_row = vec![0.;n];
+    fn product_sparse_par(&self, other: &CSR) -> CSR {
+        self.product_sparse_par_with(other, Accumulator::Auto)
+    }
 
 
-            for col_pos_pos in self.row_pos[i]..self.row_pos[i+1] {
-                let k = self.col_pos[col_pos_pos];
 
-                for other_col_pos_pos in other.row_pos[k]..other.row_pos[k+1] {
-                    let j = other.col_pos[other_col_pos_pos];
+    fn product_sparse_with(&self, other: &CSR, acc: Accumulator) -> CSR {
+        spgemm::<PlusTimes>(self, other, acc)
+    }
 
-                    // C_{i*} = \sum_{k \in I_i (A)} a_{ik} b_{i*}
-                    // a_{ik} = self.values[col_pos_pos]
-                    // b_{kj} = other.values[other_col_pos_pos]
-                    // mat[i][j] += self.values[col_pos_pos] * other.values[other_col_pos_pos]
-                    // mat.set(i,j , mat.get(i, j) +   self.values[col_pos_pos] * other.values[other_col_pos_pos]);
-                    res_curr_row[j] += self.values[col_pos_pos] * other.values[other_col_pos_pos];
-                    nz_row_marker[j] = true;                    
 
-                }
-            }
 
-            // Go through the row, which may contain 0 entries.
-            // If !marker, then the k-col is 0 and can be ignored
-            // Save only non-zero entries and their index
-            let mut res_curr_row_final_val = vec![];
-            let mut res_curr_row_final_col_idx = vec![];
-            for ((k, x), marker) in res_curr_row.iter().enumerate().into_iter().zip(nz_row_marker) {
-                if marker {
-                    res_curr_row_final_val.push(*x);
-                    res_curr_row_final_col_idx.push(k);
-                }
-            }
-            
-            // Push the final result for the current row
-            res_rows.push(res_curr_row_final_val);
-            res_col_idxs.push(res_curr_row_final_col_idx);
-        }
+    fn product_sparse_par_with(&self, other: &CSR, acc: Accumulator) -> CSR {
+        spgemm_par::<PlusTimes>(self, other, acc)
+    }
 
 
-        // Create the row_pos vector and flatten the other
-        let mut row_pos_counter = 0;
-        let mut row_pos = vec![0];
 
-        for c in &res_col_idxs {
-            row_pos_counter += c.len();
-            row_pos.push(row_pos_counter);
-        }
+    // C<M> = A*B, see mask.rs
+    fn product_sparse_masked(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR {
+        spgemm_masked::<PlusTimes>(self, other, mask, opts)
+    }
 
-        let col_pos = res_col_idxs.concat();
-        let values = res_rows.concat();
 
-        row_pos.push(values.len());
 
-        CSR{row_pos, col_pos, values, shape: (m,n)}
+    fn product_sparse_masked_par(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR {
+        spgemm_masked_par::<PlusTimes>(self, other, mask, opts)
+    }
+
 
+
+    // C = A (+).(x) B over the semiring S, see semiring.rs
+    // E.g. one BFS step from the frontier f (1 x n): f.product_sparse_semiring::<OrAnd>(&a)
+    fn product_sparse_semiring<S: Semiring>(&self, other: &CSR) -> CSR {
+        spgemm::<S>(self, other, Accumulator::Auto)
     }
 
 
 
-    fn product_sparse_par(&self, other: &CSR) -> CSR {
-        let m = self.shape.0;
-        let n = other.shape.1;
+    fn product_sparse_semiring_par<S: Semiring>(&self, other: &CSR) -> CSR {
+        spgemm_par::<S>(self, other, Accumulator::Auto)
+    }
 
-        let res_rows: Arc<Mutex<Vec<(usize, Vec<f64>)>>> = Arc::new(Mutex::new(vec![]));
-        let res_col_idxs: Arc<Mutex<Vec<(usize, Vec<usize>)>>> =  Arc::new(Mutex::new(vec![]));
 
-        (0..m).into_par_iter()
-        .for_each(|i| {
-            // Iterate over all non-zero cols of A_{i*}
 
-            // Create a dense row for the result matrix, C_{i*}
-            // and also a bool array that flags if some non-zero
-            // entry for the k-th (i.e. for C_{ik}) is calculated.            
-            let mut nz_row_marker = vec![false;n];
-            let mut res_curr_row = vec![0.;n];
+    // E.g. BFS step without the visited vertices v:
+    // f.product_sparse_masked_semiring::<OrAnd>(&a, &v, MaskOptions{complement: true, structural: true})
+    fn product_sparse_masked_semiring<S: Semiring>(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR {
+        spgemm_masked::<S>(self, other, mask, opts)
+    }
 
 
-            for col_pos_pos in self.row_pos[i]..self.row_pos[i+1] {
-                let k = self.col_pos[col_pos_pos];
 
-                for other_col_pos_pos in other.row_pos[k]..other.row_pos[k+1] {
-                    let j = other.col_pos[other_col_pos_pos];
+    fn product_sparse_masked_semiring_par<S: Semiring>(&self, other: &CSR, mask: &CSR, opts: MaskOptions) -> CSR {
+        spgemm_masked_par::<S>(self, other, mask, opts)
+    }
 
-                    // C_{i*} = \sum_{k \in I_i (A)} a_{ik} b_{i*}
-                    // a_{ik} = self.values[col_pos_pos]
-                    // b_{kj} = other.values[other_col_pos_pos]
-                    // mat[i][j] += self.values[col_pos_pos] * other.values[other_col_pos_pos]
-                    // mat.set(i,j , mat.get(i, j) +   self.values[col_pos_pos] * other.values[other_col_pos_pos]);
-                    res_curr_row[j] += self.values[col_pos_pos] * other.values[other_col_pos_pos];
-                    nz_row_marker[j] = true;                    
 
-                }
-            }
 
-            // Go through the row, which may contain 0 entries.
-            // If !marker, then the k-col is 0 and can be ignored
-            // Save only non-zero entries and their index
-            let mut res_curr_row_final_val = vec![];
-            let mut res_curr_row_final_col_idx = vec![];
-            for ((k, x), marker) in res_curr_row.iter().enumerate().into_iter().zip(nz_row_marker) {
-                if marker {
-                    res_curr_row_final_val.push(*x);
-                    res_curr_row_final_col_idx.push(k);
-                }
-            }
-            
-            // Push the final result for the current row
-            let mut rr = res_rows.lock().unwrap();
-            let mut rci = res_col_idxs.lock().unwrap();
+    // C = drop(A*B), small entries of every row are dropped
+    // before the next row is computed, see dropping.rs
+    fn product_sparse_dropped(&self, other: &CSR, opts: DropOptions) -> CSR {
+        spgemm_dropped(self, other, opts)
+    }
 
-            rr.push((i,res_curr_row_final_val));
-            rci.push((i,res_curr_row_final_col_idx));        
-        });
 
 
-        // Consume Arc Mutex
-        let mut res_rows = Arc::try_unwrap(res_rows).unwrap().into_inner().unwrap();
-        let mut res_col_idxs = Arc::try_unwrap(res_col_idxs).unwrap().into_inner().unwrap();
+    fn product_sparse_dropped_par(&self, other: &CSR, opts: DropOptions) -> CSR {
+        spgemm_dropped_par(self, other, opts)
+    }
 
-        res_rows.sort_by_key(|&(i, _)| i);
-        res_col_idxs.sort_by_key(|&(i, _)| i);
 
-        // Create the row_pos vector and flatten the other
-        let mut row_pos_counter = 0;
-        let mut row_pos = vec![0];
 
-        for (_, c) in &res_col_idxs {
-            row_pos_counter += c.len();
-            row_pos.push(row_pos_counter);
-        }
+    // Same as product_sparse_par, the rows are then expanded to
+    // (i, j, c_ij) in parallel, keeping the row-major order.
+    fn product_sparse_to_coo_par(&self, other: &CSR) -> COO {
+        let res = self.product_sparse_par(other);
 
-        let col_pos = res_col_idxs.into_iter().flat_map(|(_, inner_vec)| inner_vec).collect();
-        let values: Vec<_> = res_rows.into_iter().flat_map(|(_, inner_vec)| inner_vec).collect();
+        let data = res.par_rows()
+            .flat_map_iter(|(i, cols, vals)| cols.iter().zip(vals).map(move |(j, x)| (i, *j, *x)))
+            .collect();
 
-        row_pos.push(values.len());
+        COO{data, shape: res.shape}
+    }
+
+}
+
+
+
+// Gustavson SpGEMM over the semiring S, serial.
+// The row C_{i*} is collected in an accumulator (see accumulator.rs).
+fn spgemm<S: Semiring>(a: &CSR, b: &CSR, acc: Accumulator) -> CSR {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = a.shape.0;
+    let n = b.shape.1;
 
-        CSR{row_pos, col_pos, values, shape: (m,n)}
+    // One workspace for all rows
+    let mut ws = SpGemmWorkspace::new(n);
 
+    let mut row_pos = vec![0];
+    let mut col_pos = vec![];
+    let mut values = vec![];
+
+    for (_, a_cols, a_vals) in a.rows() {
+        // C_{i*} = \sum_{k \in I_i (A)} a_{ik} b_{i*}
+        // is appended directly to the result
+        ws.row_product::<S>(acc, a_cols, a_vals, b, &mut col_pos, &mut values);
+        row_pos.push(values.len());
     }
 
+    CSR{row_pos, col_pos, values, shape: (m,n)}
+}
 
 
-    fn product_sparse_to_coo_par(&self, other: &CSR) -> COO {
-        let m = self.shape.0;
-        let n = other.shape.1;
 
-        let _res_rows: Arc<Mutex<Vec<(usize, Vec<f64>)>>> = Arc::new(Mutex::new(vec![]));
-        let _res_col_idxs: Arc<Mutex<Vec<(usize, Vec<usize>)>>> =  Arc::new(Mutex::new(vec![]));
+// Two passes, no locks:
+// 1. count nnz(C_{i*}) for all rows in parallel, prefix sum gives row_pos
+// 2. compute the rows in parallel, every row is written directly into
+//    its own slice of the preallocated col_pos / values
+// Both passes run over the chunks of a flop-balanced row partition
+// (see spgemm_partition), so hub rows do not stall a single thread.
+// Every rayon task reuses its workspace and row buffers over its rows.
+// The result is independent of the number of threads.
+fn spgemm_par<S: Semiring>(a: &CSR, b: &CSR, acc: Accumulator) -> CSR {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = a.shape.0;
+    let n = b.shape.1;
 
-        let res_data: Arc<Mutex<Vec<(usize, usize, f64)>>> = Arc::new(Mutex::new(vec![]));
+    let partition = spgemm_partition(a, b);
+    let row_pos = product_row_pos_par(a, b, &partition);
+    let nnz = row_pos[m];
 
-        (0..m).into_par_iter()
-        .for_each(|i| {
-            // Iterate over all non-zero cols of A_{i*}
+    let mut res = CSR{row_pos, col_pos: vec![0; nnz], values: vec![0.; nnz], shape: (m,n)};
 
-            // Create a dense row for the result matrix, C_{i*}
-            // and also a bool array that flags if some non-zero
-            // entry for the k-th (i.e. for C_{ik}) is calculated.            
-            let mut nz_row_marker = vec![false;n];
-            let mut res_curr_row = vec![0.;n];
+    res.par_rows_pattern_mut_by(&partition)
+    .for_each_init(|| (SpGemmWorkspace::new(n), vec![], vec![]), |(ws, cols, vals), (i, res_cols, res_vals)| {
+        // Row C_{i*}, see spgemm
+        let (a_cols, a_vals) = a.row_slices(i);
+        ws.row_product::<S>(acc, a_cols, a_vals, b, cols, vals);
 
-            for col_pos_pos in self.row_pos[i]..self.row_pos[i+1] {
-                let k = self.col_pos[col_pos_pos];
+        res_cols.copy_from_slice(cols);
+        res_vals.copy_from_slice(vals);
+        cols.clear();
+        vals.clear();
+    });
 
-                for other_col_pos_pos in other.row_pos[k]..other.row_pos[k+1] {
-                    let j = other.col_pos[other_col_pos_pos];
+    res
+}
 
 
 
-                    // C_{i*} = \sum_{k \in I_i (A)} a_{ik} b_{i*}
-                    // a_{ik} = self.values[col_pos_pos]
-                    // b_{kj} = other.values[other_col_pos_pos]
-                    // mat[i][j] += self.values[col_pos_pos] * other.values[other_col_pos_pos]
-                    // mat.set(i,j , mat.get(i, j) +   self.values[col_pos_pos] * other.values[other_col_pos_pos]);
-                    res_curr_row[j] += self.values[col_pos_pos] * other.values[other_col_pos_pos];
-                    nz_row_marker[j] = true;                    
+// C<M> = A*B, see mask.rs. Same row loop as spgemm,
+// products a_ik b_kj for columns j outside the mask are skipped
+// before they reach the accumulator.
+fn spgemm_masked<S: Semiring>(a: &CSR, b: &CSR, mask: &CSR, opts: MaskOptions) -> CSR {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = a.shape.0;
+    let n = b.shape.1;
+    assert_eq!(mask.shape, (m,n), "Mask does not have the shape of the product");
 
-                }
-            }
+    let mut ws = SpGemmWorkspace::new(n);
+    let mut marker = MaskMarker::new(n, opts);
+
+    let mut row_pos = vec![0];
+    let mut col_pos = vec![];
+    let mut values = vec![];
+
+    for (i, a_cols, a_vals) in a.rows() {
+        // Nothing is allowed in an empty row of a non-complemented mask
+        if opts.complement || mask.get_row_nnz(i) > 0 {
+            marker.set_row(mask, i);
+            ws.row_product_filtered::<S>(Accumulator::Auto, a_cols, a_vals, b, &mut col_pos, &mut values, |j| marker.keep(j));
+            marker.reset_row(mask, i);
+        }
+        row_pos.push(values.len());
+    }
+
+    CSR{row_pos, col_pos, values, shape: (m,n)}
+}
 
-            // Go through the row, which may contain 0 entries.
-            // If !marker, then the k-col is 0 and can be ignored
-            // Save only non-zero entries and their index
-            // let mut res_curr_row_final_val = vec![];
-            // let mut res_curr_row_final_col_idx = vec![];
-            let mut data_thread: Vec<(usize, usize, f64)> = vec![];
-            for ((k, x), marker) in res_curr_row.iter().enumerate().into_iter().zip(nz_row_marker) {
-                if marker {
-                    // res_curr_row_final_val.push(*x);
-                    // res_curr_row_final_col_idx.push(k);
-                    data_thread.push((i, k, *x));
-                }
-            }
-            
-            let mut rd = res_data.lock().unwrap();
-            rd.append(&mut data_thread);
 
-            // Push the final result for the current row
-            // let mut rr = res_rows.lock().unwrap();
-            // let mut rci = res_col_idxs.lock().unwrap();
 
-            // rr.push((i,res_curr_row_final_val));
-            // rci.push((i,res_curr_row_final_col_idx));        
-        });
+// Same two passes as spgemm_par, the count pass
+// only counts columns inside the mask.
+fn spgemm_masked_par<S: Semiring>(a: &CSR, b: &CSR, mask: &CSR, opts: MaskOptions) -> CSR {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = a.shape.0;
+    let n = b.shape.1;
+    assert_eq!(mask.shape, (m,n), "Mask does not have the shape of the product");
 
+    // Partition by the flops of the unmasked product, an upper bound of the work
+    let partition = spgemm_partition(a, b);
+    let counts: Vec<usize> = a.par_rows_by(&partition)
+        .map_init(|| (SpGemmWorkspace::new(n), MaskMarker::new(n, opts)), |(ws, marker), (i, a_cols, _)| {
+            if !opts.complement && mask.get_row_nnz(i) == 0 {
+                return 0;
+            }
+            marker.set_row(mask, i);
+            let count = ws.row_nnz(a_cols, b, |j| marker.keep(j));
+            marker.reset_row(mask, i);
+            count
+        })
+        .collect();
+    let row_pos = row_pos_from_counts(&counts);
+    let nnz = row_pos[m];
+
+    let mut res = CSR{row_pos, col_pos: vec![0; nnz], values: vec![0.; nnz], shape: (m,n)};
+
+    res.par_rows_pattern_mut_by(&partition)
+    .for_each_init(|| (SpGemmWorkspace::new(n), MaskMarker::new(n, opts), vec![], vec![]), |(ws, marker, cols, vals), (i, res_cols, res_vals)| {
+        if res_cols.is_empty() {
+            return;
+        }
+        let (a_cols, a_vals) = a.row_slices(i);
+        marker.set_row(mask, i);
+        ws.row_product_filtered::<S>(Accumulator::Auto, a_cols, a_vals, b, cols, vals, |j| marker.keep(j));
+        marker.reset_row(mask, i);
+
+        res_cols.copy_from_slice(cols);
+        res_vals.copy_from_slice(vals);
+        cols.clear();
+        vals.clear();
+    });
+
+    res
+}
 
-        // Consume Arc Mutex
-        let res_data = Arc::try_unwrap(res_data).unwrap().into_inner().unwrap();
 
-        // let mut res_rows = Arc::try_unwrap(res_rows).unwrap().into_inner().unwrap();
-        // let mut res_col_idxs = Arc::try_unwrap(res_col_idxs).unwrap().into_inner().unwrap();
 
-        // res_rows.sort_by_key(|&(i, _)| i);
-        // res_col_idxs.sort_by_key(|&(i, _)| i);
+// Same row loop as spgemm, every row is compacted right after
+// it has been appended to the result.
+fn spgemm_dropped(a: &CSR, b: &CSR, opts: DropOptions) -> CSR {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = a.shape.0;
+    let n = b.shape.1;
 
-        // Create the row_pos vector and flatten the other
-        // let mut row_pos_counter = 0;
-        // let mut row_pos = vec![0];
+    let mut ws = SpGemmWorkspace::new(n);
+    let mut dropper = RowDropper::new(opts);
 
-        // for (_, c) in &res_col_idxs {
-        //     row_pos_counter += c.len();
-        //     row_pos.push(row_pos_counter);
-        // }
+    let mut row_pos = vec![0];
+    let mut col_pos = vec![];
+    let mut values = vec![];
 
-        // let col_pos = res_col_idxs.into_iter().flat_map(|(_, inner_vec)| inner_vec).collect();
-        // let values: Vec<_> = res_rows.into_iter().flat_map(|(_, inner_vec)| inner_vec).collect();
+    for (_, a_cols, a_vals) in a.rows() {
+        let start = values.len();
+        ws.row_product::<PlusTimes>(Accumulator::Auto, a_cols, a_vals, b, &mut col_pos, &mut values);
+        dropper.compact(&mut col_pos, &mut values, start);
+        row_pos.push(values.len());
+    }
 
-        // row_pos.push(values.len());
+    CSR{row_pos, col_pos, values, shape: (m,n)}
+}
 
-        // CSR{row_pos, col_pos, values, shape: (m,n)};
 
-        COO{data: res_data, shape: (m,n)}
 
+// nnz(C_{i*}) is only known after dropping, so the count pass of spgemm_par
+// does not apply. Every chunk of the flop-balanced partition computes its
+// rows into its own buffers instead, which are concatenated in chunk order.
+// Only the kept entries are ever stored.
+fn spgemm_dropped_par(a: &CSR, b: &CSR, opts: DropOptions) -> CSR {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    let m = a.shape.0;
+    let n = b.shape.1;
+
+    let partition = spgemm_partition(a, b);
+    let chunks: Vec<_> = partition.chunks().collect();
+    let parts: Vec<(Vec<usize>, Vec<usize>, Vec<f64>)> = chunks.into_par_iter().map(|rows| {
+        let mut ws = SpGemmWorkspace::new(n);
+        let mut dropper = RowDropper::new(opts);
+        let (mut counts, mut cols, mut vals) = (vec![], vec![], vec![]);
+        for i in rows {
+            let start = vals.len();
+            let (a_cols, a_vals) = a.row_slices(i);
+            ws.row_product::<PlusTimes>(Accumulator::Auto, a_cols, a_vals, b, &mut cols, &mut vals);
+            dropper.compact(&mut cols, &mut vals, start);
+            counts.push(vals.len() - start);
+        }
+        (counts, cols, vals)
+    }).collect();
+
+    let counts: Vec<usize> = parts.iter().flat_map(|(counts, _, _)| counts.iter().copied()).collect();
+    let row_pos = row_pos_from_counts(&counts);
+    let mut col_pos = Vec::with_capacity(row_pos[m]);
+    let mut values = Vec::with_capacity(row_pos[m]);
+    for (_, cols, vals) in &parts {
+        col_pos.extend_from_slice(cols);
+        values.extend_from_slice(vals);
     }
 
-}
\ No newline at end of file
+    CSR{row_pos, col_pos, values, shape: (m,n)}
+}
@@ -0,0 +1,200 @@
+use rayon::prelude::*;
+
+use matrix_base::{Dense, CSC, CSR};
+
+
+// Mixed sparse / dense products with a dense result
+//
+// SpMV:           y = A*x, A sparse (m x k), x a vector of length k
+// SpMM:           Y = A*X, A sparse (m x k), X dense (k x p)
+// DenseSparseProd: Y = X*B, X dense (m x k), B sparse (k x n)
+//
+// Tuned for tall-skinny dense operands (block vectors, p between 4 and 256),
+// as in block Krylov methods and GNN layers.
+pub trait SpMV {
+    fn spmv(&self, x: &[f64]) -> Vec<f64>;
+    fn spmv_par(&self, x: &[f64]) -> Vec<f64>;
+    fn spmv_par_into(&self, x: &[f64], y: &mut [f64]);
+}
+
+
+pub trait SpMM {
+    fn spmm(&self, x: &Dense) -> Dense;
+    fn spmm_par(&self, x: &Dense) -> Dense;
+}
+
+
+pub trait DenseSparseProd {
+    fn product_csr(&self, b: &CSR) -> Dense;
+    fn product_csr_par(&self, b: &CSR) -> Dense;
+}
+
+
+
+// Columns of Y_{i*} that are kept in registers while the row of A is traversed
+const SPMM_WIDTH: usize = 8;
+
+// Minimal number of rows per rayon task
+const SPMM_MIN_ROWS: usize = 16;
+
+// Minimal number of rows per rayon task of spmv_par, a row is only a few flops
+const SPMV_MIN_ROWS: usize = 1024;
+
+// Minimal number of columns of B per rayon task in product_csr_par
+const COLUMN_MIN_LEN: usize = 64;
+
+
+
+impl SpMV for CSR {
+    fn spmv(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(self.shape.1, x.len(), "Matrix and vector dimensions do not match");
+        self.rows().map(|(_, cols, vals)| spmv_row(cols, vals, x)).collect()
+    }
+
+
+    fn spmv_par(&self, x: &[f64]) -> Vec<f64> {
+        let mut y = vec![0.; self.shape.0];
+        self.spmv_par_into(x, &mut y);
+        y
+    }
+
+
+    // y = A*x into an existing vector, e.g. for the iterations of a solver
+    fn spmv_par_into(&self, x: &[f64], y: &mut [f64]) {
+        assert_eq!(self.shape.1, x.len(), "Matrix and vector dimensions do not match");
+        assert_eq!(self.shape.0, y.len(), "Matrix and vector dimensions do not match");
+        y.par_iter_mut().zip(self.par_rows()).with_min_len(SPMV_MIN_ROWS).for_each(|(y_i, (_, cols, vals))| {
+            *y_i = spmv_row(cols, vals, x);
+        });
+    }
+}
+
+
+
+// y_i = \sum_j a_ij x_j
+fn spmv_row(cols: &[usize], vals: &[f64], x: &[f64]) -> f64 {
+    cols.iter().zip(vals).map(|(j, a_ij)| a_ij * x[*j]).sum()
+}
+
+
+
+impl SpMM for CSR {
+    fn spmm(&self, x: &Dense) -> Dense {
+        assert_eq!(self.shape.1, x.shape.0, "Matrix dimensions do not match for multiplication");
+
+        let mut y = Dense::new_zeros((self.shape.0, x.shape.1));
+        for (i, y_row) in y.rows_mut() {
+            spmm_row(self, x, i, y_row);
+        }
+        y
+    }
+
+
+    // Row-parallel, every task owns its rows of Y
+    fn spmm_par(&self, x: &Dense) -> Dense {
+        assert_eq!(self.shape.1, x.shape.0, "Matrix dimensions do not match for multiplication");
+
+        let mut y = Dense::new_zeros((self.shape.0, x.shape.1));
+        y.par_rows_mut().with_min_len(SPMM_MIN_ROWS).for_each(|(i, y_row)| {
+            spmm_row(self, x, i, y_row);
+        });
+        y
+    }
+}
+
+
+
+// Y_{i*} = \sum_k a_ik X_{k*}
+// Y_{i*} is processed in column blocks of SPMM_WIDTH which stay in
+// registers over the whole row of A, so every a_ik only loads X_{k*}.
+fn spmm_row(a: &CSR, x: &Dense, i: usize, y_row: &mut [f64]) {
+    let (a_cols, a_vals) = a.row_slices(i);
+    let p = y_row.len();
+    let p_blocked = p - p % SPMM_WIDTH;
+
+    for c in (0..p_blocked).step_by(SPMM_WIDTH) {
+        let mut acc = [0.; SPMM_WIDTH];
+        for (k, a_ik) in a_cols.iter().zip(a_vals) {
+            let x_block: &[f64; SPMM_WIDTH] = x.row(*k)[c..c + SPMM_WIDTH].try_into().unwrap();
+            for (acc_j, x_kj) in acc.iter_mut().zip(x_block) {
+                *acc_j += a_ik * x_kj;
+            }
+        }
+        y_row[c..c + SPMM_WIDTH].copy_from_slice(&acc);
+    }
+
+    // Remaining p % SPMM_WIDTH columns
+    if p_blocked < p {
+        let y_rest = &mut y_row[p_blocked..];
+        for (k, a_ik) in a_cols.iter().zip(a_vals) {
+            for (y_ij, x_kj) in y_rest.iter_mut().zip(&x.row(*k)[p_blocked..]) {
+                *y_ij += a_ik * x_kj;
+            }
+        }
+    }
+}
+
+
+
+impl DenseSparseProd for Dense {
+    fn product_csr(&self, b: &CSR) -> Dense {
+        assert_eq!(self.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+
+        let mut y = Dense::new_zeros((self.shape.0, b.shape.1));
+        for (i, y_row) in y.rows_mut() {
+            dense_sparse_row(self.row(i), b, y_row);
+        }
+        y
+    }
+
+
+    // Enough rows of X: row-parallel as in spmm_par.
+    // Only a few rows (e.g. X^T A for a block vector X): column-parallel,
+    // Y_{*j} = X B_{*j} from the columns of B (CSC), computed as the rows
+    // of Y^T. Both add up x_ik b_kj by increasing k, as product_csr, so the
+    // result does not depend on the number of threads.
+    fn product_csr_par(&self, b: &CSR) -> Dense {
+        assert_eq!(self.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+        let (m, n) = (self.shape.0, b.shape.1);
+
+        if m >= SPMM_MIN_ROWS * rayon::current_num_threads() {
+            let mut y = Dense::new_zeros((m, n));
+            y.par_rows_mut().with_min_len(SPMM_MIN_ROWS).for_each(|(i, y_row)| {
+                dense_sparse_row(self.row(i), b, y_row);
+            });
+            return y;
+        }
+
+        let b_csc = CSC::from_csr(b);
+        let mut y_t = Dense::new_zeros((n, m));
+        y_t.par_rows_mut().with_min_len(COLUMN_MIN_LEN).for_each(|(j, y_col)| {
+            let range = b_csc.col_pos[j]..b_csc.col_pos[j+1];
+            for (k, b_kj) in b_csc.row_pos[range.clone()].iter().zip(&b_csc.values[range]) {
+                for (i, y_ij) in y_col.iter_mut().enumerate() {
+                    *y_ij += self.get(i, *k) * b_kj;
+                }
+            }
+        });
+
+        let mut y = Dense::new_zeros((m, n));
+        y.par_rows_mut().for_each(|(i, y_row)| {
+            for (j, y_ij) in y_row.iter_mut().enumerate() {
+                *y_ij = y_t.get(j, i);
+            }
+        });
+        y
+    }
+}
+
+
+
+// Y_{i*} += \sum_k x_ik B_{k*}, x_row = X_{i*}.
+// Zeros of X are multiplied as well, so NaN and Inf in B propagate as in
+// the dense product.
+fn dense_sparse_row(x_row: &[f64], b: &CSR, y_row: &mut [f64]) {
+    for (k, x_ik) in x_row.iter().enumerate() {
+        for (j, b_kj) in b.row(k) {
+            y_row[j] += x_ik * b_kj;
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use rayon::prelude::*;
+
+use matrix_base::{CSR, SymCSR};
+
+use crate::accumulator::{row_pos_from_counts, Accumulator, SpGemmWorkspace};
+use crate::semiring::PlusTimes;
+
+
+// Products with a symmetric matrix A = U + U^T - D stored as its
+// upper triangle U (see SymCSR). The lower triangle is never
+// materialized, every stored off-diagonal entry a_ij is used twice:
+// once as a_ij and once as a_ji.
+pub trait SymmetricProd {
+    fn spmv(&self, x: &[f64]) -> Vec<f64>;
+    fn spmv_par(&self, x: &[f64]) -> Vec<f64>;
+    fn product_sparse(&self, other: &CSR) -> CSR;
+    fn product_sparse_par(&self, other: &CSR) -> CSR;
+}
+
+
+// Rows per parallel task of spmv_par
+const SPMV_CHUNK: usize = 4096;
+
+
+
+impl SymmetricProd for SymCSR {
+    // y = A*x
+    // y_i += a_ij x_j for the stored entry, and y_j += a_ij x_i for the mirrored one
+    fn spmv(&self, x: &[f64]) -> Vec<f64> {
+        let n = self.shape.0;
+        assert_eq!(n, x.len(), "Matrix and vector dimensions do not match");
+
+        let mut y = vec![0.; n];
+        for (i, j, a_ij) in self.iter() {
+            y[i] += a_ij * x[j];
+            if i != j {
+                y[j] += a_ij * x[i];
+            }
+        }
+        y
+    }
+
+
+    // The mirrored updates y_j of spmv would scatter into rows owned by other
+    // threads. Instead every row gathers its own output through the LowerIndex:
+    // y_i = \sum_{k<i} a_ki x_k + \sum_{j>=i} a_ij x_j, no reduction is needed.
+    fn spmv_par(&self, x: &[f64]) -> Vec<f64> {
+        let n = self.shape.0;
+        assert_eq!(n, x.len(), "Matrix and vector dimensions do not match");
+        let lower = LowerIndex::new(self);
+
+        let mut y = vec![0.; n];
+        y.par_chunks_mut(SPMV_CHUNK).enumerate().for_each(|(c, y_chunk)| {
+            for (l, y_i) in y_chunk.iter_mut().enumerate() {
+                let i = c*SPMV_CHUNK + l;
+                let mirrored: f64 = (lower.col_ptr[i]..lower.col_ptr[i+1]).map(|q| self.values[lower.pos[q]] * x[lower.row[q]]).sum();
+                let stored: f64 = self.row_upper(i).map(|(j, a_ij)| a_ij * x[j]).sum();
+                *y_i = mirrored + stored;
+            }
+        });
+        y
+    }
+
+
+    fn product_sparse(&self, other: &CSR) -> CSR {
+        assert_eq!(self.shape.1, other.shape.0, "Matrix dimensions do not match for multiplication");
+        let lower = LowerIndex::new(self);
+        let mut ws = SymRowWorkspace::new(other.shape.1);
+
+        // Rows are appended directly to the result, see sparse::spgemm
+        let mut row_pos = vec![0];
+        let mut col_pos = vec![];
+        let mut values = vec![];
+        for i in 0..self.shape.0 {
+            sym_row_product(self, &lower, other, i, &mut ws, &mut col_pos, &mut values);
+            row_pos.push(values.len());
+        }
+        CSR{row_pos, col_pos, values, shape: (self.shape.0, other.shape.1)}
+    }
+
+
+    // Count pass and prefix sum, then every row is written into its own
+    // slice of the preallocated col_pos / values (see sparse::spgemm_par)
+    fn product_sparse_par(&self, other: &CSR) -> CSR {
+        assert_eq!(self.shape.1, other.shape.0, "Matrix dimensions do not match for multiplication");
+        let (m, n) = (self.shape.0, other.shape.1);
+        let lower = LowerIndex::new(self);
+
+        let counts: Vec<usize> = (0..m).into_par_iter()
+            .map_init(|| SymRowWorkspace::new(n), |ws, i| {
+                ws.gather_row(self, &lower, i);
+                ws.ws.row_nnz(&ws.a_cols, other, |_| true)
+            })
+            .collect();
+        let row_pos = row_pos_from_counts(&counts);
+        let nnz = row_pos[m];
+
+        let mut res = CSR{row_pos, col_pos: vec![0; nnz], values: vec![0.; nnz], shape: (m,n)};
+        res.par_rows_pattern_mut()
+        .for_each_init(|| (SymRowWorkspace::new(n), vec![], vec![]), |(ws, cols, vals), (i, res_cols, res_vals)| {
+            sym_row_product(self, &lower, other, i, ws, cols, vals);
+            res_cols.copy_from_slice(cols);
+            res_vals.copy_from_slice(vals);
+            cols.clear();
+            vals.clear();
+        });
+        res
+    }
+}
+
+
+
+// Row i of A consists of the mirrored entries a_ki, k < i (column i of U
+// above the diagonal) and the stored row i of U. To reach the first ones
+// without a transposed copy of the values, this keeps for every column i
+// the positions p in U.col_pos / U.values with U.col_pos[p] = i and row k < i.
+// Costs one usize per off-diagonal entry, no values are duplicated.
+struct LowerIndex {
+    col_ptr: Vec<usize>,
+    pos: Vec<usize>,
+    row: Vec<usize>
+}
+
+
+impl LowerIndex {
+    fn new(a: &SymCSR) -> Self {
+        let n = a.shape.0;
+
+        let mut col_ptr = vec![0; n+1];
+        for (i, j, _) in a.iter() {
+            if i != j {
+                col_ptr[j+1] += 1;
+            }
+        }
+        for j in 0..n {
+            col_ptr[j+1] += col_ptr[j];
+        }
+
+        let n_off = col_ptr[n];
+        let mut pos = vec![0; n_off];
+        let mut row = vec![0; n_off];
+        let mut next = col_ptr.clone();
+        for i in 0..n {
+            for p in a.row_pos[i]..a.row_pos[i+1] {
+                let j = a.col_pos[p];
+                if i != j {
+                    pos[next[j]] = p;
+                    row[next[j]] = i;
+                    next[j] += 1;
+                }
+            }
+        }
+
+        LowerIndex{col_ptr, pos, row}
+    }
+}
+
+
+
+// SpGEMM workspace plus a buffer for the full row A_{i*}
+struct SymRowWorkspace {
+    ws: SpGemmWorkspace,
+    a_cols: Vec<usize>,
+    a_vals: Vec<f64>
+}
+
+
+impl SymRowWorkspace {
+    fn new(n: usize) -> Self {
+        SymRowWorkspace{ws: SpGemmWorkspace::new(n), a_cols: vec![], a_vals: vec![]}
+    }
+
+
+    // The full row A_{i*} is put together from the implicit lower part (k < i)
+    // and the stored upper part (k >= i), which keeps it sorted.
+    fn gather_row(&mut self, a: &SymCSR, lower: &LowerIndex, i: usize) {
+        self.a_cols.clear();
+        self.a_vals.clear();
+        for q in lower.col_ptr[i]..lower.col_ptr[i+1] {
+            self.a_cols.push(lower.row[q]);
+            self.a_vals.push(a.values[lower.pos[q]]);
+        }
+        for (k, a_ik) in a.row_upper(i) {
+            self.a_cols.push(k);
+            self.a_vals.push(*a_ik);
+        }
+    }
+}
+
+
+
+// C_{i*} = \sum_k a_ik B_{k*}, appended to cols and vals
+fn sym_row_product(a: &SymCSR, lower: &LowerIndex, b: &CSR, i: usize, ws: &mut SymRowWorkspace, cols: &mut Vec<usize>, vals: &mut Vec<f64>) {
+    ws.gather_row(a, lower, i);
+    ws.ws.row_product::<PlusTimes>(Accumulator::Auto, &ws.a_cols, &ws.a_vals, b, cols, vals);
+}
@@ -0,0 +1,459 @@
+use rayon::prelude::*;
+
+use matrix_base::{CSC, CSR};
+
+
+// Sparse triangular solves op(T) x = b with op(T) = T or T^T, T lower or
+// upper triangular in CSR or CSC (like BLAS trsv: the triangle refers to the
+// stored T, the transposition is applied on top). Only the entries of the given
+// triangle are used, entries of the other one are ignored, so L and U of a
+// factorization can share one CSR (see precond::Ilu0).
+//
+// TriangularAnalysis does the analysis of the pattern once and can be reused
+// for every matrix with the same pattern. TriangularSolve::solve_triangular
+// on a CSR or CSC analyses and solves in one go.
+//
+// Level scheduling, see section 11.6.3 of
+// "Iterative Methods for Sparse Linear Systems", Saad
+// https://doi.org/10.1137/1.9780898718003
+//
+// Row i of a lower triangular solve depends on the rows j < i with a stored
+// entry t_ij. level(i) = 1 + max level(j) over these rows (0 without any),
+// so all rows of a level only depend on earlier levels and can be solved in
+// parallel. Upper triangular solves are the same with j > i.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Triangle {
+    Lower,
+    Upper
+}
+
+
+// Unit: t_ii = 1 is assumed, stored diagonal entries are ignored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Diagonal {
+    NonUnit,
+    Unit
+}
+
+
+
+// Levels with fewer rows are solved serially
+const LEVEL_MIN_PAR: usize = 256;
+
+// Minimal number of rows per rayon task within a level
+const LEVEL_MIN_LEN: usize = 64;
+
+
+
+// Rows grouped by level: level l holds order[level_pos[l]..level_pos[l+1]]
+pub(crate) struct LevelSchedule {
+    triangle: Triangle,
+    order: Vec<usize>,
+    level_pos: Vec<usize>
+}
+
+
+impl LevelSchedule {
+    pub(crate) fn new(t: &CSR, triangle: Triangle) -> Self {
+        assert_eq!(t.shape.0, t.shape.1, "Matrix is not square");
+        LevelSchedule::from_pattern(&t.row_pos, &t.col_pos, triangle)
+    }
+
+
+    // Same as new for the rows row_pos and the column indices col_pos of a CSR pattern
+    pub(crate) fn from_pattern(row_pos: &[usize], col_pos: &[usize], triangle: Triangle) -> Self {
+        let n = row_pos.len() - 1;
+
+        let mut level = vec![0; n];
+        let mut visit = |i: usize| {
+            let deps = col_pos[row_pos[i]..row_pos[i+1]].iter().filter(|&&j| match triangle {
+                Triangle::Lower => j < i,
+                Triangle::Upper => j > i
+            });
+            level[i] = deps.map(|&j| level[j] + 1).max().unwrap_or(0);
+        };
+        match triangle {
+            Triangle::Lower => (0..n).for_each(&mut visit),
+            Triangle::Upper => (0..n).rev().for_each(&mut visit)
+        }
+
+        // Counting sort of the rows by level, rows of a level stay in order
+        let n_levels = level.iter().max().map_or(0, |l| l + 1);
+        let mut level_pos = vec![0; n_levels+1];
+        for l in &level {
+            level_pos[l+1] += 1;
+        }
+        for l in 0..n_levels {
+            level_pos[l+1] += level_pos[l];
+        }
+        let mut next = level_pos.clone();
+        let mut order = vec![0; n];
+        for (i, l) in level.iter().enumerate() {
+            order[next[*l]] = i;
+            next[*l] += 1;
+        }
+
+        LevelSchedule{triangle, order, level_pos}
+    }
+
+
+    pub(crate) fn n_rows(&self) -> usize {
+        self.order.len()
+    }
+
+
+    pub(crate) fn n_levels(&self) -> usize {
+        self.level_pos.len() - 1
+    }
+
+
+    pub(crate) fn levels(&self) -> impl Iterator<Item = &[usize]> {
+        self.level_pos.windows(2).map(|w| &self.order[w[0]..w[1]])
+    }
+
+
+    // x_i = row(i, x) for all rows in dependency order, serially.
+    // row may only read the entries x_j the row i depends on.
+    pub(crate) fn solve(&self, x: &mut [f64], row: impl Fn(usize, &[f64]) -> f64) {
+        assert_eq!(x.len(), self.n_rows(), "Matrix and vector dimensions do not match");
+        match self.triangle {
+            Triangle::Lower => {
+                for i in 0..x.len() {
+                    x[i] = row(i, x);
+                }
+            }
+            Triangle::Upper => {
+                for i in (0..x.len()).rev() {
+                    x[i] = row(i, x);
+                }
+            }
+        }
+    }
+
+
+    // Same as solve, level by level. The rows of a level are computed in
+    // parallel from the previous levels and written back afterwards.
+    pub(crate) fn solve_par(&self, x: &mut [f64], row: impl Fn(usize, &[f64]) -> f64 + Sync) {
+        assert_eq!(x.len(), self.n_rows(), "Matrix and vector dimensions do not match");
+        let mut buf = vec![];
+
+        for level in self.levels() {
+            if level.len() < LEVEL_MIN_PAR {
+                for &i in level {
+                    x[i] = row(i, x);
+                }
+            } else {
+                let x_prev: &[f64] = x;
+                level.par_iter().with_min_len(LEVEL_MIN_LEN).map(|&i| row(i, x_prev)).collect_into_vec(&mut buf);
+                for (&i, x_i) in level.iter().zip(&buf) {
+                    x[i] = *x_i;
+                }
+            }
+        }
+    }
+}
+
+
+
+// Analysis of op(T) x = b for the pattern of T: the level schedule of op(T)
+// and the position of its diagonal. For op(T) = T^T the pattern of T^T is
+// kept together with the position of every entry in T, so the values are
+// read from T itself and no transposed copy is needed.
+//
+// T can be a CSR or a CSC (new_csc / solve_csc): the arrays of T in CSC are
+// the ones of T^T in CSR, so a CSC is analysed as the CSR of T^T with the
+// other triangle and the other transposition. The solves check the format,
+// size and nnz of T, the hash of the pattern only in debug builds. Callers
+// that own T (precond, direct) skip the checks, see solve_owned.
+pub struct TriangularAnalysis {
+    // Triangle and transposition for the arrays in CSR orientation
+    triangle: Triangle,
+    transposed: Option<TransposedPattern>,
+    diagonal: Diagonal,
+    // Position of t_ii in T.values, only for Diagonal::NonUnit
+    diag_pos: Vec<usize>,
+    csc: bool,
+    nnz: usize,
+    pattern_hash: u64,
+    schedule: LevelSchedule
+}
+
+
+// T^T in CSR, value_pos[q] is the position of the entry q of T^T in T.values
+struct TransposedPattern {
+    row_pos: Vec<usize>,
+    col_pos: Vec<usize>,
+    value_pos: Vec<usize>
+}
+
+
+// The arrays of a square T in CSR orientation, those of T^T for a CSC
+struct Arrays<'a> {
+    row_pos: &'a [usize],
+    col_pos: &'a [usize],
+    values: &'a [f64]
+}
+
+
+impl<'a> Arrays<'a> {
+    fn csr(t: &'a CSR) -> Self {
+        assert_eq!(t.shape.0, t.shape.1, "Matrix is not square");
+        Arrays{row_pos: &t.row_pos, col_pos: &t.col_pos, values: &t.values}
+    }
+
+
+    fn csc(t: &'a CSC) -> Self {
+        assert_eq!(t.shape.0, t.shape.1, "Matrix is not square");
+        Arrays{row_pos: &t.col_pos, col_pos: &t.row_pos, values: &t.values}
+    }
+
+
+    fn n(&self) -> usize {
+        self.row_pos.len() - 1
+    }
+
+
+    fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+
+    fn row_slices(&self, i: usize) -> (&[usize], &[f64]) {
+        let range = self.row_pos[i]..self.row_pos[i+1];
+        (&self.col_pos[range.clone()], &self.values[range])
+    }
+}
+
+
+impl TriangularAnalysis {
+    // Err if a diagonal entry is missing or zero (Diagonal::NonUnit)
+    pub fn new(t: &CSR, triangle: Triangle, transposed: bool, diagonal: Diagonal) -> Result<Self, &'static str> {
+        Self::analyse(&Arrays::csr(t), triangle, transposed, diagonal, false, t.pattern_hash())
+    }
+
+
+    // Same for T in CSC, e.g. the factors of direct::Lu and direct::Cholesky
+    pub fn new_csc(t: &CSC, triangle: Triangle, transposed: bool, diagonal: Diagonal) -> Result<Self, &'static str> {
+        Self::analyse(&Arrays::csc(t), flip(triangle), !transposed, diagonal, true, t.pattern_hash())
+    }
+
+
+    fn analyse(t: &Arrays, triangle: Triangle, transposed: bool, diagonal: Diagonal, csc: bool, pattern_hash: u64) -> Result<Self, &'static str> {
+        let n = t.n();
+
+        let diag_pos = match diagonal {
+            Diagonal::Unit => vec![],
+            Diagonal::NonUnit => (0..n).map(|i| {
+                let p = (t.row_pos[i]..t.row_pos[i+1]).find(|&p| t.col_pos[p] == i).ok_or("Missing diagonal entry")?;
+                if t.values[p] == 0. { Err("Zero diagonal entry") } else { Ok(p) }
+            }).collect::<Result<Vec<usize>, &'static str>>()?
+        };
+
+        // op(T) = T^T turns a lower into an upper triangular solve and vice versa
+        let (transposed, schedule) = if transposed {
+            let pattern = TransposedPattern::new(t);
+            let schedule = LevelSchedule::from_pattern(&pattern.row_pos, &pattern.col_pos, flip(triangle));
+            (Some(pattern), schedule)
+        } else {
+            (None, LevelSchedule::from_pattern(t.row_pos, t.col_pos, triangle))
+        };
+
+        Ok(TriangularAnalysis{triangle, transposed, diagonal, diag_pos, csc, nnz: t.nnz(), pattern_hash, schedule})
+    }
+
+
+    pub fn n_levels(&self) -> usize {
+        self.schedule.n_levels()
+    }
+
+
+    // op(T) x = b, x holds b and is overwritten by the solution.
+    // t must have the pattern the analysis was done for.
+    pub fn solve(&self, t: &CSR, x: &mut [f64]) {
+        let arrays = Arrays::csr(t);
+        self.check(false, &arrays);
+        debug_assert_eq!(t.pattern_hash(), self.pattern_hash, "Matrix does not match the analysis");
+        self.solve_arrays(&arrays, x)
+    }
+
+
+    // Same as solve, the rows of a level in parallel
+    pub fn solve_par(&self, t: &CSR, x: &mut [f64]) {
+        let arrays = Arrays::csr(t);
+        self.check(false, &arrays);
+        debug_assert_eq!(t.pattern_hash(), self.pattern_hash, "Matrix does not match the analysis");
+        self.solve_arrays_par(&arrays, x)
+    }
+
+
+    // solve for an analysis done with new_csc
+    pub fn solve_csc(&self, t: &CSC, x: &mut [f64]) {
+        let arrays = Arrays::csc(t);
+        self.check(true, &arrays);
+        debug_assert_eq!(t.pattern_hash(), self.pattern_hash, "Matrix does not match the analysis");
+        self.solve_arrays(&arrays, x)
+    }
+
+
+    pub fn solve_csc_par(&self, t: &CSC, x: &mut [f64]) {
+        let arrays = Arrays::csc(t);
+        self.check(true, &arrays);
+        debug_assert_eq!(t.pattern_hash(), self.pattern_hash, "Matrix does not match the analysis");
+        self.solve_arrays_par(&arrays, x)
+    }
+
+
+    // solve / solve_par without checks, for a T that is owned by the caller
+    // and was passed to new, so its pattern cannot have changed
+    pub(crate) fn solve_owned(&self, t: &CSR, x: &mut [f64], par: bool) {
+        if par { self.solve_arrays_par(&Arrays::csr(t), x) } else { self.solve_arrays(&Arrays::csr(t), x) }
+    }
+
+
+    // Same for an analysis done with new_csc
+    pub(crate) fn solve_csc_owned(&self, t: &CSC, x: &mut [f64], par: bool) {
+        if par { self.solve_arrays_par(&Arrays::csc(t), x) } else { self.solve_arrays(&Arrays::csc(t), x) }
+    }
+
+
+    fn solve_arrays(&self, t: &Arrays, x: &mut [f64]) {
+        match &self.transposed {
+            None => self.schedule.solve(x, |i, x| self.row(t, i, x)),
+            Some(p) => self.schedule.solve(x, |i, x| self.row_transposed(t, p, i, x))
+        }
+    }
+
+
+    fn solve_arrays_par(&self, t: &Arrays, x: &mut [f64]) {
+        match &self.transposed {
+            None => self.schedule.solve_par(x, |i, x| self.row(t, i, x)),
+            Some(p) => self.schedule.solve_par(x, |i, x| self.row_transposed(t, p, i, x))
+        }
+    }
+
+
+    fn check(&self, csc: bool, t: &Arrays) {
+        assert_eq!(csc, self.csc, "Matrix format does not match the analysis");
+        assert!(t.n() == self.schedule.n_rows() && t.nnz() == self.nnz, "Matrix does not match the analysis");
+    }
+
+
+    // True for the off-diagonal entries (i, j) of row i of op(T) that are used
+    fn in_triangle(triangle: Triangle, i: usize, j: usize) -> bool {
+        match triangle {
+            Triangle::Lower => j < i,
+            Triangle::Upper => j > i
+        }
+    }
+
+
+    fn divide(&self, t: &Arrays, i: usize, s: f64) -> f64 {
+        match self.diagonal {
+            Diagonal::Unit => s,
+            Diagonal::NonUnit => s / t.values[self.diag_pos[i]]
+        }
+    }
+
+
+    // x_i = (b_i - \sum_j t_ij x_j) / t_ii with b_i still in x[i]
+    fn row(&self, t: &Arrays, i: usize, x: &[f64]) -> f64 {
+        let (cols, vals) = t.row_slices(i);
+        let s: f64 = cols.iter().zip(vals)
+            .filter(|(j, _)| Self::in_triangle(self.triangle, i, **j))
+            .map(|(j, t_ij)| t_ij * x[*j])
+            .sum();
+        self.divide(t, i, x[i] - s)
+    }
+
+
+    // Same for row i of T^T, i.e. column i of T
+    fn row_transposed(&self, t: &Arrays, p: &TransposedPattern, i: usize, x: &[f64]) -> f64 {
+        let range = p.row_pos[i]..p.row_pos[i+1];
+        let s: f64 = p.col_pos[range.clone()].iter().zip(&p.value_pos[range])
+            .filter(|(j, _)| Self::in_triangle(self.triangle, **j, i))
+            .map(|(j, q)| t.values[*q] * x[*j])
+            .sum();
+        self.divide(t, i, x[i] - s)
+    }
+}
+
+
+fn flip(triangle: Triangle) -> Triangle {
+    match triangle {
+        Triangle::Lower => Triangle::Upper,
+        Triangle::Upper => Triangle::Lower
+    }
+}
+
+
+impl TransposedPattern {
+    // Counting sort by column as in CSR::transpose, with the positions instead of the values
+    fn new(t: &Arrays) -> Self {
+        let n = t.n();
+        let mut row_pos = vec![0; n+1];
+        for j in t.col_pos {
+            row_pos[j+1] += 1;
+        }
+        for j in 0..n {
+            row_pos[j+1] += row_pos[j];
+        }
+
+        let mut next = row_pos.clone();
+        let mut col_pos = vec![0; t.col_pos.len()];
+        let mut value_pos = vec![0; t.col_pos.len()];
+        for i in 0..n {
+            for q in t.row_pos[i]..t.row_pos[i+1] {
+                let j = t.col_pos[q];
+                col_pos[next[j]] = i;
+                value_pos[next[j]] = q;
+                next[j] += 1;
+            }
+        }
+
+        TransposedPattern{row_pos, col_pos, value_pos}
+    }
+}
+
+
+
+// One-shot triangular solves on a CSR or CSC, see TriangularAnalysis
+pub trait TriangularSolve {
+    fn solve_triangular(&self, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Result<Vec<f64>, &'static str>;
+    fn solve_triangular_par(&self, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Result<Vec<f64>, &'static str>;
+}
+
+
+impl TriangularSolve for CSR {
+    fn solve_triangular(&self, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Result<Vec<f64>, &'static str> {
+        let analysis = TriangularAnalysis::new(self, triangle, transposed, diagonal)?;
+        let mut x = b.to_vec();
+        analysis.solve(self, &mut x);
+        Ok(x)
+    }
+
+
+    fn solve_triangular_par(&self, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Result<Vec<f64>, &'static str> {
+        let analysis = TriangularAnalysis::new(self, triangle, transposed, diagonal)?;
+        let mut x = b.to_vec();
+        analysis.solve_par(self, &mut x);
+        Ok(x)
+    }
+}
+
+
+impl TriangularSolve for CSC {
+    fn solve_triangular(&self, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Result<Vec<f64>, &'static str> {
+        let analysis = TriangularAnalysis::new_csc(self, triangle, transposed, diagonal)?;
+        let mut x = b.to_vec();
+        analysis.solve_csc(self, &mut x);
+        Ok(x)
+    }
+
+
+    fn solve_triangular_par(&self, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Result<Vec<f64>, &'static str> {
+        let analysis = TriangularAnalysis::new_csc(self, triangle, transposed, diagonal)?;
+        let mut x = b.to_vec();
+        analysis.solve_csc_par(self, &mut x);
+        Ok(x)
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{laplacian_2d, residual, rhs};
+use fakscpu::amg::{aggregate, strength_of_connection, Amg, AmgOptions, Smoother};
+use fakscpu::elementwise::SparseElementwise;
+use fakscpu::solvers::{cg, IdentityPrecond, SolverOptions};
+use matrix_base::CSR;
+
+
+
+#[test]
+fn test_aggregation() {
+    let nx = 30;
+    let a = laplacian_2d(nx);
+    let s = strength_of_connection(&a, 0.08);
+    assert_eq!(s.values.len(), a.values.len() - nx * nx);
+    assert!(s.iter().all(|(i, j, _)| i != j));
+    // Weak connections are dropped
+    assert_eq!(strength_of_connection(&a, 0.5).values.len(), 0);
+
+    let (agg, n_agg) = aggregate(&s);
+    assert!(agg.iter().all(|g| *g < n_agg));
+    let mut size = vec![0; n_agg];
+    for g in &agg {
+        size[*g] += 1;
+    }
+    assert!(size.iter().all(|s| *s > 0));
+    // Aggregates of about 5 (the neighborhood of a root) to 9 nodes
+    assert!(n_agg > nx * nx / 10 && n_agg < nx * nx / 3, "{}", n_agg);
+
+    // Every node of an aggregate is strongly connected to another one of it
+    for (i, g) in agg.iter().enumerate() {
+        if size[*g] > 1 {
+            assert!(s.row(i).any(|(j, _)| agg[j] == *g));
+        }
+    }
+
+    // Isolated nodes are aggregates of their own
+    let (agg, n_agg) = aggregate(&strength_of_connection(&CSR::identity(4), 0.));
+    assert_eq!(agg, [0, 1, 2, 3]);
+    assert_eq!(n_agg, 4);
+}
+
+
+
+#[test]
+fn test_amg() {
+    let opts = SolverOptions::default();
+    let amg_opts = AmgOptions{max_coarse: 50, ..Default::default()};
+
+    // Number of V-cycles (nearly) independent of the grid size
+    let mut cycles = vec![];
+    for nx in [32, 64, 128] {
+        let a = laplacian_2d(nx);
+        let amg = Amg::new(&a, &amg_opts).unwrap();
+        let sizes = amg.level_sizes();
+        println!("levels {:?}, operator complexity {:.2}", sizes, amg.operator_complexity());
+        assert_eq!(sizes.len(), amg.n_levels());
+        assert_eq!(sizes[0], nx * nx);
+        assert!(sizes.windows(2).all(|w| w[1] < w[0]));
+        assert!(*sizes.last().unwrap() <= 50);
+        assert!(amg.operator_complexity() < 2.);
+
+        let b = rhs(nx * nx);
+        let mut x = vec![0.; nx * nx];
+        let report = amg.solve(&b, &mut x, &opts);
+        assert!(report.converged(), "{:?}", report.status);
+        assert_eq!(report.history.len(), report.iterations + 1);
+        assert!(residual(&a, &b, &x) <= opts.rtol * 1.01);
+        cycles.push(report.iterations);
+
+        // As preconditioner for CG
+        let mut x = vec![0.; nx * nx];
+        let precond = cg(&a, &b, &mut x, &amg, &opts);
+        let mut x = vec![0.; nx * nx];
+        let plain = cg(&a, &b, &mut x, &IdentityPrecond, &opts);
+        assert!(precond.converged());
+        assert!(precond.iterations < report.iterations);
+        assert!(precond.iterations * 4 < plain.iterations);
+    }
+    println!("V-cycles: {:?}", cycles);
+    assert!(cycles.iter().all(|c| *c < 15));
+    assert!(cycles[2] <= 2 * cycles[0]);
+
+    // Jacobi needs more cycles than Gauss-Seidel
+    let a = laplacian_2d(64);
+    let b = rhs(64 * 64);
+    let jacobi = Amg::new(&a, &AmgOptions{smoother: Smoother::Jacobi, ..amg_opts}).unwrap();
+    let mut x = vec![0.; 64 * 64];
+    let report = jacobi.solve(&b, &mut x, &opts);
+    assert!(report.converged());
+    assert!(report.iterations > cycles[1]);
+
+    // Small matrices are solved directly
+    let a = laplacian_2d(5);
+    let amg = Amg::new(&a, &amg_opts).unwrap();
+    assert_eq!(amg.n_levels(), 1);
+    assert_eq!(amg.matrix().values, a.values);
+    let mut x = vec![0.; 25];
+    assert_eq!(amg.solve(&rhs(25), &mut x, &opts).iterations, 1);
+
+    // Not SPD
+    let mut indefinite = laplacian_2d(32);
+    indefinite.map_values(|x| -x);
+    assert!(Amg::new(&indefinite, &amg_opts).is_err());
+}
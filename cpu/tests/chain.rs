@@ -0,0 +1,130 @@
+use fakscpu::chain::{chain_product, chain_product_with, dense_chain_order, dense_chain_product, estimate_chain_nnz,
+    rap_product, sparse_chain_order, NnzEstimator};
+use fakscpu::dense::DenseProd;
+use fakscpu::sparse::SparseProd;
+use matrix_base::{Dense, COO, CSR};
+
+
+
+
+#[cfg(test)]
+fn cmp_dense(a: &Dense, b: &Dense, eps: f64) -> bool {
+    a.shape == b.shape && a.data.iter().zip(b.data.iter()).all(|(x, y)| (x-y).abs() < eps)
+}
+
+
+// 5-point stencil on an 8 x 8 grid (up to the boundary) and
+// piecewise constant aggregation of 4 consecutive unknowns
+#[cfg(test)]
+fn galerkin_setup() -> (CSR, CSR, CSR) {
+    let n = 64;
+    let a = CSR::from_diagonals(&[&[-1.], &[-1.], &[4.], &[-1.], &[-1.]], &[-8, -1, 0, 1, 8], (n, n));
+    let p = CSR::from_coo(&COO{data: (0..n).map(|i| (i, i/4, 1.)).collect(), shape: (n, n/4)});
+    let r = CSR::from_coo(&COO{data: (0..n).map(|i| (i/4, i, 1.)).collect(), shape: (n/4, n)});
+    (r, a, p)
+}
+
+
+
+#[test]
+fn test_rap_product() {
+    let eps = 1e-10;
+    let (r, a, p) = galerkin_setup();
+    let expected = r.product_sparse(&a).product_sparse(&p);
+
+    let c = rap_product(&r, &a, &p);
+    assert_eq!(c.shape, (16, 16));
+    assert_eq!(c.row_pos, expected.row_pos);
+    assert_eq!(c.col_pos, expected.col_pos);
+    assert!(cmp_dense(&c.to_dense(), &expected.to_dense(), eps));
+
+    // Coarse operator keeps the row sums of A (here 0 in the interior)
+    let row_sums: f64 = c.values.iter().sum();
+    assert!((row_sums - a.values.iter().sum::<f64>()).abs() < eps);
+}
+
+
+
+#[test]
+fn test_chain_product() {
+    let eps = 1e-10;
+    let (r, a, p) = galerkin_setup();
+    let a_dense = a.to_dense();
+
+    let expected = r.to_dense().product_dense_par(&a_dense).product_dense_par(&a_dense).product_dense_par(&p.to_dense());
+    for est in [NnzEstimator::UpperBound, NnzEstimator::Sampling(4), NnzEstimator::Sampling(1000)] {
+        let c = chain_product_with(&[&r, &a, &a, &p], est);
+        assert!(cmp_dense(&c.to_dense(), &expected, eps));
+    }
+
+    let rap = chain_product(&[&r, &a, &p]);
+    assert!(cmp_dense(&rap.to_dense(), &rap_product(&r, &a, &p).to_dense(), eps));
+
+    // Single matrix
+    let single = chain_product(&[&a]);
+    assert_eq!(single.values, a.values);
+    assert_eq!(single.col_pos, a.col_pos);
+}
+
+
+
+#[test]
+fn test_chain_nnz_estimates() {
+    let (r, a, p) = galerkin_setup();
+    let ra = r.product_sparse(&a);
+    let rap = ra.product_sparse(&p);
+    let aa = a.product_sparse(&a);
+
+    // Sampling all rows is exact
+    let exact = estimate_chain_nnz(&[&r, &a, &p], NnzEstimator::Sampling(1000));
+    assert_eq!(exact[0][0], r.values.len());
+    assert_eq!(exact[0][1], ra.values.len());
+    assert_eq!(exact[0][2], rap.values.len());
+    assert_eq!(exact[1][2], a.product_sparse(&p).values.len());
+
+    let exact = estimate_chain_nnz(&[&a, &a], NnzEstimator::Sampling(64));
+    assert_eq!(exact[0][1], aa.values.len());
+
+    // Upper bound for two matrices, fewer samples stay close
+    let ub = estimate_chain_nnz(&[&r, &a, &p], NnzEstimator::UpperBound);
+    assert!(ub[0][1] >= ra.values.len());
+    assert!(ub[1][2] >= a.values.len());
+    assert!(ub[0][2] <= 16 * 16);
+
+    let sampled = estimate_chain_nnz(&[&a, &a], NnzEstimator::Sampling(16));
+    let rel = (sampled[0][1] as f64 - aa.values.len() as f64).abs() / aa.values.len() as f64;
+    assert!(rel < 0.25);
+}
+
+
+
+#[test]
+fn test_chain_order() {
+    // Example from Cormen et al., section 15.2
+    let dims = [30, 35, 15, 5, 10, 20, 25];
+    let mats: Vec<Dense> = dims.windows(2).enumerate().map(|(l, w)| {
+        let mut x = Dense::new_zeros((w[0], w[1]));
+        for (i, j, v) in x.iter_mut() {
+            *v = ((i * 5 + j * 3 + l) % 11) as f64 * 0.1 - 0.5;
+        }
+        x
+    }).collect();
+    let refs: Vec<&Dense> = mats.iter().collect();
+
+    let order = dense_chain_order(&refs);
+    assert_eq!(order.cost, 15125.);
+    assert_eq!(order.to_string(), "((M0 (M1 M2)) ((M3 M4) M5))");
+
+    let expected = refs[1..].iter().fold(Dense{data: mats[0].data.clone(), shape: mats[0].shape}, |acc, x| acc.product_dense_par(x));
+    assert!(cmp_dense(&dense_chain_product(&refs), &expected, 1e-10));
+    assert_eq!(dense_chain_product(&refs[..1]).data, mats[0].data);
+
+    // Sparse: a vector at the end of the chain is multiplied first, at the start last
+    let (_, a, _) = galerkin_setup();
+    let v = CSR::from_coo(&COO{data: (0..64).map(|i| (i, 0, 1.)).collect(), shape: (64, 1)});
+    let w = CSR::from_coo(&COO{data: (0..64).map(|i| (0, i, 1.)).collect(), shape: (1, 64)});
+    for est in [NnzEstimator::UpperBound, NnzEstimator::Sampling(8)] {
+        assert_eq!(sparse_chain_order(&[&a, &a, &v], est).to_string(), "(M0 (M1 M2))");
+        assert_eq!(sparse_chain_order(&[&w, &a, &a], est).to_string(), "((M0 M1) M2)");
+    }
+}
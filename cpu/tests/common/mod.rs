@@ -0,0 +1,47 @@
+// Test problems shared by the integration tests, every test crate
+// includes this module (mod common;) and uses a part of it
+#![allow(dead_code)]
+
+use fakscpu::elementwise::SparseElementwise;
+use fakscpu::spmm::SpMV;
+use matrix_base::{CSR, kron};
+
+
+
+
+// tridiag(-1, 2, -1)
+#[cfg(test)]
+pub fn laplacian_1d(n: usize) -> CSR {
+    CSR::from_diagonals(&[&[-1.], &[2.], &[-1.]], &[-1, 0, 1], (n, n))
+}
+
+
+// 5-point Laplacian on an nx x nx grid, T (x) I + I (x) T, SPD
+#[cfg(test)]
+pub fn laplacian_2d(nx: usize) -> CSR {
+    let t = laplacian_1d(nx);
+    let id = CSR::identity(nx);
+    kron(&t, &id).sparse_add(1., &kron(&id, &t), 1.)
+}
+
+
+// Convection-diffusion, not symmetric
+#[cfg(test)]
+pub fn convection_2d(nx: usize) -> CSR {
+    let n = nx * nx;
+    CSR::from_diagonals(&[&[-1.], &[-1.6], &[4.], &[-0.4], &[-1.]], &[-(nx as isize), -1, 0, 1, nx as isize], (n, n))
+}
+
+
+#[cfg(test)]
+pub fn rhs(n: usize) -> Vec<f64> {
+    (0..n).map(|i| ((i * 7) % 13) as f64 - 6.).collect()
+}
+
+
+// ||b - A x|| / ||b||
+#[cfg(test)]
+pub fn residual(a: &CSR, b: &[f64], x: &[f64]) -> f64 {
+    let ax = a.spmv(x);
+    ax.iter().zip(b).map(|(y, b_i)| (b_i - y).powi(2)).sum::<f64>().sqrt() / b.iter().map(|b_i| b_i * b_i).sum::<f64>().sqrt()
+}
@@ -0,0 +1,63 @@
+use fakscpu::accumulator::spgemm_partition;
+use fakscpu::context::{current_cores, ExecContext, ExecOptions};
+use fakscpu::sparse::SparseProd;
+use matrix_base::{CSR, kron};
+
+
+
+#[test]
+fn test_exec_context() {
+    let t = CSR::from_diagonals(&[&[1.1], &[-2.3], &[0.7]], &[-1, 0, 1], (40, 40));
+    let mut a = kron(&CSR::identity(40), &t);
+    for (i, j, x) in a.iter_mut() {
+        *x *= 1. + ((i * 5 + j * 3) % 7) as f64 * 0.01;
+    }
+    let b = kron(&t, &CSR::identity(40));
+    let c_seq = a.product_sparse(&b);
+
+    for threads in [1, 2, 3] {
+        for chunks_per_thread in [1, 4] {
+            let ctx = ExecContext::new(ExecOptions{threads, chunks_per_thread, ..Default::default()}).unwrap();
+            assert_eq!(ctx.threads(), threads);
+            assert_eq!(ctx.install(rayon::current_num_threads), threads);
+
+            // The granularity of the SpGEMM partition follows the context
+            let partition = ctx.install(|| spgemm_partition(&a, &b));
+            assert_eq!(partition.n_chunks(), threads * chunks_per_thread);
+
+            // Same bits as the serial product
+            let c = ctx.install(|| a.product_sparse_par(&b));
+            assert_eq!(c.row_pos, c_seq.row_pos);
+            assert_eq!(c.col_pos, c_seq.col_pos);
+            assert_eq!(c.values, c_seq.values);
+        }
+    }
+
+    // By default the size of rayon's default pool, RAYON_NUM_THREADS or one thread per core
+    let ctx = ExecContext::new(ExecOptions::default()).unwrap();
+    let default_threads = rayon::ThreadPoolBuilder::new().build().unwrap().current_num_threads();
+    assert_eq!(ctx.threads(), default_threads);
+
+    assert!(ExecContext::new(ExecOptions{chunks_per_thread: 0, ..Default::default()}).is_err());
+}
+
+
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_exec_context_pinned() {
+    let a = CSR::from_diagonals(&[&[1.], &[2.], &[-1.]], &[-1, 0, 1], (500, 500));
+    let cores = current_cores().unwrap();
+    let ctx = ExecContext::new(ExecOptions{threads: 2, pin: true, ..Default::default()}).unwrap();
+    assert!(ctx.options().pin);
+
+    // Worker t runs on the t-th core of the mask only
+    let pinned = ctx.install(|| rayon::broadcast(|w| (w.index(), current_cores().unwrap())));
+    assert_eq!(pinned.len(), 2);
+    for (t, worker_cores) in pinned {
+        assert_eq!(worker_cores, vec![cores[t % cores.len()]]);
+    }
+
+    let c = ctx.install(|| a.product_sparse_par(&a));
+    assert_eq!(c.values, a.product_sparse(&a).values);
+}
@@ -61,4 +61,79 @@ fn test_product_dense() {
     }
     
     
-}
\ No newline at end of file
+}
+
+
+#[test]
+fn test_product_dense_blocked() {
+    let eps = 1e-9;
+
+    // Shapes around the block sizes of the kernel (MR, NR, MC, KC, NC), including empty ones
+    let shapes = [(1, 1, 1), (3, 5, 2), (4, 8, 8), (67, 300, 13), (130, 257, 9), (5, 3, 4100), (0, 3, 4), (3, 0, 4)];
+
+    for (m, k, n) in shapes {
+        let mut a = Dense::new_zeros((m, k));
+        let mut b = Dense::new_zeros((k, n));
+        for (i, j, x) in a.iter_mut() {
+            *x = ((i * 7 + j * 3) % 11) as f64 - 5.;
+        }
+        for (i, j, x) in b.iter_mut() {
+            *x = ((i * 5 + j * 2) % 13) as f64 * 0.25;
+        }
+
+        // Naive i-k-j product as reference
+        let mut c = Dense::new_zeros((m, n));
+        for i in 0..m {
+            for l in 0..k {
+                let a_il = a.get(i, l);
+                for (c_ij, b_lj) in c.row_mut(i).iter_mut().zip(b.row(l)) {
+                    *c_ij += a_il * b_lj;
+                }
+            }
+        }
+
+        let c_test = a.product_dense_par(&b);
+        assert_eq!(c_test.shape, (m, n));
+        assert!(cmp_dense(&c, &c_test, eps));
+    }
+}
+
+
+
+#[test]
+fn test_product_strassen_winograd() {
+    // Odd sizes at several recursion levels (peeling), rectangular shapes,
+    // and a cutoff above the size (only the blocked kernel)
+    let shapes = [(64, 64, 64), (75, 61, 83), (130, 97, 45), (33, 200, 17), (7, 7, 7)];
+
+    for (m, k, n) in shapes {
+        let mut a = Dense::new_zeros((m, k));
+        let mut b = Dense::new_zeros((k, n));
+        for (i, j, x) in a.iter_mut() {
+            *x = ((i * 7 + j * 3) % 11) as f64 * 0.3 - 1.;
+        }
+        for (i, j, x) in b.iter_mut() {
+            *x = ((i * 5 + j * 2) % 13) as f64 * 0.1 + 0.5;
+        }
+        let c = a.product_dense_par(&b);
+
+        for cutoff in [4, 16, STRASSEN_CUTOFF] {
+            let c_strassen = a.product_strassen(&b, cutoff);
+            let c_winograd = a.product_winograd(&b, cutoff);
+            assert_eq!(c_strassen.shape, (m, n));
+            assert_eq!(c_winograd.shape, (m, n));
+
+            assert!(product_error(&a, &b, &c_strassen) < 1e-12);
+            assert!(product_error(&a, &b, &c_winograd) < 1e-12);
+            assert!(cmp_dense(&c, &c_strassen, 1e-9));
+            assert!(cmp_dense(&c, &c_winograd, 1e-9));
+        }
+    }
+
+    // Exact for small integers
+    let mut a = Dense::new_zeros((16, 16));
+    for (i, j, x) in a.iter_mut() {
+        *x = ((i + 2 * j) % 5) as f64;
+    }
+    assert_eq!(product_error(&a, &a, &a.product_winograd(&a, 2)), 0.);
+}
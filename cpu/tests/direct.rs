@@ -0,0 +1,195 @@
+mod common;
+
+use std::path::Path;
+
+use common::{residual, rhs};
+use fakscpu::direct::{Cholesky, CholeskySymbolic, Lu, LuSymbolic};
+use fakscpu::elementwise::SparseElementwise;
+use matrix_base::{COO, CSC, CSR, SymCSR, rcm, amd, nested_dissection};
+
+const DATA_PATH: &str = "../matrix_instances";
+
+
+
+
+#[cfg(test)]
+fn read_instance(name: &str) -> CSR {
+    let fname = Path::new(DATA_PATH).join(Path::new(name));
+    CSR::from_coo(&COO::read_mtx(&fname, true).expect("Failed reading matrix during test"))
+}
+
+
+// Toeplitz instances of generate_toeplitz.py (n = 100, 1000), tridiag(1, -2, 1), negative definite
+#[cfg(test)]
+fn toeplitz(n: usize) -> CSR {
+    read_instance(&format!("generated/toeplitz/toeplit_{}_A.mtx", n))
+}
+
+
+// Laplacian instances of generate_toeplitz.py (nx = 20, 30),
+// 2D Laplacian on an nx x nx grid, T (x) I + I (x) T, SPD
+#[cfg(test)]
+fn laplacian_instance(nx: usize) -> CSR {
+    read_instance(&format!("generated/laplacian/laplacian_{}_A.mtx", nx))
+}
+
+
+
+#[test]
+fn test_cholesky() {
+    // -T is SPD, a path: no fill-in, etree is the path itself
+    for n in [100, 1000] {
+        let mut a = toeplitz(n);
+        a.map_values(|x| -x);
+        let symbolic = CholeskySymbolic::new(&a);
+        assert_eq!(symbolic.nnz_l(), 2*n - 1);
+        assert!((0..n-1).all(|j| symbolic.parent[j] == j + 1));
+
+        let chol = symbolic.factorize(&a).unwrap();
+        let b = rhs(n);
+        assert!(residual(&a, &b, &chol.solve(&b)) < 1e-12);
+    }
+
+    // Laplacian: fill-in up to the bandwidth nx
+    let nx = 20;
+    let a = laplacian_instance(nx);
+    let symbolic = CholeskySymbolic::new(&a);
+    assert!(symbolic.nnz_l() > a.values.len());
+    assert!(symbolic.nnz_l() <= nx * nx * (nx + 1));
+    let chol = symbolic.factorize(&a).unwrap();
+    assert_eq!(chol.l().values.len(), symbolic.nnz_l());
+    let b = rhs(nx * nx);
+    assert!(residual(&a, &b, &chol.solve(&b)) < 1e-12);
+    assert_eq!(chol.solve_par(&b), chol.solve(&b));
+
+    // L L^T = A
+    let l = chol.l().to_csr();
+    let llt = fakscpu::sparse::SparseProd::product_sparse(&l, &l.transpose());
+    let diff = llt.sparse_add(1., &a, -1.);
+    assert!(diff.values.iter().all(|x| x.abs() < 1e-12));
+
+    // Numeric phase again with new values on the same pattern
+    let shifted = a.sparse_add(1., &CSR::identity(nx * nx), 3.);
+    let chol = symbolic.factorize(&shifted).unwrap();
+    assert!(residual(&shifted, &b, &chol.solve(&b)) < 1e-12);
+
+    // Only the lower triangle is read: a symmetric .mtx gives just that one
+    let fname = Path::new(DATA_PATH).join(Path::new("symmetric/s001.mtx"));
+    let lower = CSR::from_coo(&COO::read_mtx(&fname, true).expect("Failed reading matrix during test"));
+    let full = SymCSR::read_mtx(&fname).expect("Failed reading matrix during test").to_csr();
+    assert!(lower.values.len() < full.values.len());
+    let chol_lower = Cholesky::new(&lower).unwrap();
+    let chol_full = Cholesky::new(&full).unwrap();
+    assert_eq!(chol_lower.l().values, chol_full.l().values);
+    let b = rhs(4);
+    assert!(residual(&full, &b, &chol_lower.solve(&b)) < 1e-12);
+
+    // Entries above the diagonal are ignored
+    let mut upper_changed = CSR{row_pos: full.row_pos.clone(), col_pos: full.col_pos.clone(), values: full.values.clone(), shape: full.shape};
+    for (i, j, x) in upper_changed.iter_mut() {
+        if j > i {
+            *x = 100.;
+        }
+    }
+    assert_eq!(Cholesky::new(&upper_changed).unwrap().l().values, chol_full.l().values);
+
+    // Errors
+    assert!(Cholesky::new(&toeplitz(100)).is_err());
+    assert!(symbolic.factorize(&CSR::from_diagonals(&[&[1.], &[4.]], &[-2, 0], (nx * nx, nx * nx))).is_err());
+}
+
+
+
+#[test]
+fn test_lu() {
+    // Toeplitz (negative definite) and the Laplacian
+    for a in [toeplitz(1000), laplacian_instance(20)] {
+        let b = rhs(a.shape.0);
+        let lu = Lu::new(&CSC::from_csr(&a), 1.).unwrap();
+        assert!(residual(&a, &b, &lu.solve(&b)) < 1e-12);
+    }
+    let b = rhs(400);
+
+    // Not symmetric, with zeros on the diagonal: needs pivoting
+    let n = 400;
+    // Rows of a non-symmetric, diagonally dominant matrix shifted by 3
+    let t = CSR::from_diagonals(&[&[0.5], &[-1.], &[4.], &[-2.], &[0.3]], &[-7, -1, 0, 1, 5], (n, n));
+    let mut data: Vec<(usize, usize, f64)> = t.iter().map(|(i, j, x)| ((i + n - 3) % n, j, *x)).collect();
+    data.sort_by_key(|(i, j, _)| (*i, *j));
+    let a = CSR::from_coo(&COO{data, shape: (n, n)});
+    assert!(a.diagonal().contains(&0.));
+    let a_csc = CSC::from_csr(&a);
+    for threshold in [1., 0.1, 0.001] {
+        let lu = Lu::new(&a_csc, threshold).unwrap();
+        assert!(residual(&a, &b, &lu.solve(&b)) < 1e-10);
+        let mut rows = lu.pivot_rows().to_vec();
+        rows.sort();
+        assert_eq!(rows, (0..n).collect::<Vec<usize>>());
+    }
+
+    // Column ordering: reversed columns
+    let perm: Vec<usize> = (0..n).rev().collect();
+    let symbolic = LuSymbolic::new(&a_csc, Some(&perm));
+    let mut lu = symbolic.factorize(&a_csc, 0.5).unwrap();
+    assert!(residual(&a, &b, &lu.solve(&b)) < 1e-10);
+
+    // Refactor with new values, same pattern
+    let mut scaled = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+    scaled.map_values(|x| 1.5 * x + 0.25);
+    lu.refactor(&CSC::from_csr(&scaled)).unwrap();
+    let x = lu.solve(&b);
+    assert!(residual(&scaled, &b, &x) < 1e-10);
+
+    // A failed refactor leaves the factors as they were, here the
+    // column eliminated last is zero, so all others are done before
+    let mut zero_pivot = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+    for (_, j, x) in zero_pivot.iter_mut() {
+        *x = if j == perm[n-1] { 0. } else { 2. * *x };
+    }
+    assert!(lu.refactor(&CSC::from_csr(&zero_pivot)).is_err());
+    assert_eq!(lu.solve(&b), x);
+
+    // Errors
+    let singular = CSR::from_diagonals(&[&[1.], &[1.]], &[0, 1], (3, 3)).sparse_add(1., &CSR::from_coo(&COO{data: vec![(2, 2, -1.)], shape: (3, 3)}), 1.);
+    assert!(Lu::new(&CSC::from_csr(&singular), 1.).is_err());
+    assert!(lu.refactor(&CSC::from_csr(&laplacian_instance(20))).is_err());
+}
+
+
+
+#[test]
+fn test_ordering_fill() {
+    let nx = 30;
+    let n = nx * nx;
+    let a = laplacian_instance(nx);
+    let b = rhs(n);
+
+    // Scrambled grid: i -> 7 i mod n
+    let scramble: Vec<usize> = (0..n).map(|i| 7 * i % n).collect();
+    let s = a.permute(&scramble, &scramble);
+    let nnz_l = |perm: &[usize]| CholeskySymbolic::new(&s.permute(perm, perm)).nnz_l();
+
+    let natural = nnz_l(&(0..n).map(|i| scramble.iter().position(|&j| j == i).unwrap()).collect::<Vec<usize>>());
+    let scrambled = nnz_l(&(0..n).collect::<Vec<usize>>());
+    let (fill_rcm, fill_amd, fill_nd) = (nnz_l(&rcm(&s)), nnz_l(&amd(&s)), nnz_l(&nested_dissection(&s, 16)));
+    println!("nnz(L): natural {}, scrambled {}, rcm {}, amd {}, nd {}", natural, scrambled, fill_rcm, fill_amd, fill_nd);
+    assert!(fill_rcm <= natural * 11 / 10);
+    assert!(fill_amd < natural / 2 && fill_nd < natural / 2);
+    assert!(scrambled > 2 * natural);
+
+    // The factorization of the reordered matrix solves the original system
+    let perm = amd(&s);
+    let chol = Cholesky::new(&s.permute(&perm, &perm)).unwrap();
+    let y = chol.solve(&perm.iter().map(|&i| b[i]).collect::<Vec<f64>>());
+    let mut x = vec![0.; n];
+    for (k, &i) in perm.iter().enumerate() {
+        x[i] = y[k];
+    }
+    assert!(residual(&s, &b, &x) < 1e-12);
+
+    // As column ordering of LU
+    let s_csc = CSC::from_csr(&s);
+    let lu_amd = LuSymbolic::new(&s_csc, Some(&perm)).factorize(&s_csc, 1.).unwrap();
+    assert!(lu_amd.nnz() < Lu::new(&s_csc, 1.).unwrap().nnz() / 2);
+    assert!(residual(&s, &b, &lu_amd.solve(&b)) < 1e-12);
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use std::f64::consts::PI;
+
+use common::{laplacian_1d, laplacian_2d};
+use fakscpu::eigen::{jacobi_eigen, lanczos, lobpcg, lobpcg_start, power_iteration, EigenOptions, EigenReport, Which};
+use fakscpu::elementwise::SparseElementwise;
+use fakscpu::precond::Ic0;
+use fakscpu::solvers::{IdentityPrecond, SolverStatus};
+use fakscpu::spmm::SpMV;
+use matrix_base::{Dense, CSR, MatrixFree, Scaled, Sum};
+
+
+
+
+// Eigenvalues of laplacian_1d, 2 - 2 cos(j pi / (n+1)), j = 1..n
+#[cfg(test)]
+fn laplacian_1d_eigs(n: usize) -> Vec<f64> {
+    (1..=n).map(|j| 2. - 2. * (j as f64 * PI / (n + 1) as f64).cos()).collect()
+}
+
+
+// Eigenvalues of laplacian_2d, mu_i + mu_j (mostly double)
+#[cfg(test)]
+fn laplacian_2d_eigs(nx: usize) -> Vec<f64> {
+    let mu = laplacian_1d_eigs(nx);
+    let mut eigs: Vec<f64> = mu.iter().flat_map(|a| mu.iter().map(move |b| a + b)).collect();
+    eigs.sort_by(f64::total_cmp);
+    eigs
+}
+
+
+// Values match, residuals are small and the vectors are orthonormal eigenvectors
+#[cfg(test)]
+fn check_eigenpairs(a: &CSR, report: &EigenReport, expected: &[f64], tol: f64) {
+    assert!(report.converged(), "{:?}", report.status);
+    let k = expected.len();
+    assert_eq!(report.values.len(), k);
+    assert_eq!(report.vectors.shape, (a.shape.0, k));
+    for (value, e) in report.values.iter().zip(expected) {
+        assert!((value - e).abs() < tol, "{} != {}", value, e);
+    }
+
+    let columns: Vec<Vec<f64>> = (0..k).map(|c| report.vectors.rows().map(|(_, row)| row[c]).collect()).collect();
+    for (c, v) in columns.iter().enumerate() {
+        let av = a.spmv(v);
+        let r: f64 = av.iter().zip(v).map(|(y, x)| (y - report.values[c] * x).powi(2)).sum::<f64>().sqrt();
+        assert!((r - report.residual_norms[c]).abs() < 1e-8);
+        assert!(r < tol.sqrt());
+        for (d, u) in columns.iter().enumerate() {
+            let uv: f64 = u.iter().zip(v).map(|(x, y)| x * y).sum();
+            assert!((uv - if c == d { 1. } else { 0. }).abs() < 1e-6);
+        }
+    }
+}
+
+
+
+#[test]
+fn test_jacobi_eigen() {
+    let n = 12;
+    let mut a = Dense::new_zeros((n, n));
+    for i in 0..n {
+        for j in 0..n {
+            a.set(i, j, (((i + 1) * (j + 1) * 7) % 11) as f64 + if i == j { i as f64 } else { 0. });
+        }
+    }
+    let (values, vectors) = jacobi_eigen(&a);
+    assert!(values.is_sorted());
+    for (c, lambda) in values.iter().enumerate() {
+        for i in 0..n {
+            let av: f64 = (0..n).map(|j| a.get(i, j) * vectors.get(j, c)).sum();
+            assert!((av - lambda * vectors.get(i, c)).abs() < 1e-10);
+        }
+        for d in 0..n {
+            let vv: f64 = (0..n).map(|i| vectors.get(i, c) * vectors.get(i, d)).sum();
+            assert!((vv - if c == d { 1. } else { 0. }).abs() < 1e-12);
+        }
+    }
+
+    let (values, _) = jacobi_eigen(&laplacian_1d(30).to_dense());
+    assert!(values.iter().zip(laplacian_1d_eigs(30)).all(|(x, e)| (x - e).abs() < 1e-12));
+}
+
+
+
+#[test]
+fn test_power_iteration() {
+    // One well separated eigenvalue ~ 20
+    let n = 60;
+    let d: Vec<f64> = (0..n).map(|i| if i == 0 { 20. } else { (i % 7) as f64 }).collect();
+    let mut a = CSR::from_diagonals(&[&[1.], &d, &[1.]], &[-1, 0, 1], (n, n));
+    let (values, _) = jacobi_eigen(&a.to_dense());
+
+    let opts = EigenOptions{tol: 1e-10, ..Default::default()};
+    let report = power_iteration(&a, &opts);
+    check_eigenpairs(&a, &report, &values[n-1..], 1e-8);
+    assert_eq!(report.matvecs, report.iterations);
+
+    // Dominant by magnitude, also if negative
+    a.map_values(|x| -x);
+    let report = power_iteration(&a, &opts);
+    check_eigenpairs(&a, &report, &[-values[n-1]], 1e-8);
+
+    let report = power_iteration(&a, &EigenOptions{max_iter: 3, ..opts});
+    assert!(!report.converged());
+    assert_eq!(report.iterations, 3);
+}
+
+
+
+#[test]
+fn test_lanczos() {
+    let n = 400;
+    let a = laplacian_1d(n);
+    let eigs = laplacian_1d_eigs(n);
+    let opts = EigenOptions{tol: 1e-10, max_iter: 2000, max_dim: 30};
+
+    let report = lanczos(&a, 5, Which::Largest, &opts);
+    let largest: Vec<f64> = eigs.iter().rev().take(5).copied().collect();
+    check_eigenpairs(&a, &report, &largest, 1e-8);
+    assert!(report.iterations > 1);
+
+    let report = lanczos(&a, 3, Which::Smallest, &opts);
+    check_eigenpairs(&a, &report, &eigs[..3], 1e-8);
+
+    // Basis as large as the matrix: the whole spectrum in one pass
+    let report = lanczos(&laplacian_1d(10), 10, Which::Smallest, &opts);
+    check_eigenpairs(&laplacian_1d(10), &report, &laplacian_1d_eigs(10), 1e-10);
+    assert_eq!(report.iterations, 1);
+
+    let report = lanczos(&a, 3, Which::Smallest, &EigenOptions{max_iter: 2, max_dim: 10, ..opts});
+    assert!(!report.converged());
+
+    // Spectral shift as an operator: the largest eigenvalues of 4 I - A
+    // are 4 minus the smallest ones of A, with the same eigenvectors
+    let shifted = Sum::new(Scaled::new(4., CSR::identity(n)), Scaled::new(-1., &a));
+    let report = lanczos(&shifted, 3, Which::Largest, &opts);
+    assert!(report.converged());
+    for (value, e) in report.values.iter().zip(&eigs) {
+        assert!((4. - value - e).abs() < 1e-8);
+    }
+
+    // Matrix-free tridiag(-1, 2, -1)
+    let stencil = MatrixFree::symmetric(n, |x: &[f64], y: &mut [f64]| {
+        for (i, y_i) in y.iter_mut().enumerate() {
+            *y_i = 2. * x[i] - if i > 0 { x[i-1] } else { 0. } - if i + 1 < n { x[i+1] } else { 0. };
+        }
+    });
+    let report = lanczos(&stencil, 3, Which::Smallest, &opts);
+    check_eigenpairs(&a, &report, &eigs[..3], 1e-8);
+}
+
+
+
+#[test]
+fn test_lobpcg() {
+    let nx = 20;
+    let a = laplacian_2d(nx);
+    let eigs = laplacian_2d_eigs(nx);
+    let opts = EigenOptions{tol: 1e-8, max_iter: 500, ..Default::default()};
+
+    // Smallest eigenvalues, with double eigenvalues among them
+    let plain = lobpcg(&a, 4, Which::Smallest, &IdentityPrecond, &opts);
+    check_eigenpairs(&a, &plain, &eigs[..4], 1e-8);
+
+    let ic0 = Ic0::new(&a).unwrap();
+    let preconditioned = lobpcg(&a, 4, Which::Smallest, &ic0, &opts);
+    check_eigenpairs(&a, &preconditioned, &eigs[..4], 1e-8);
+    println!("lobpcg iterations: {} plain, {} ic0", plain.iterations, preconditioned.iterations);
+    assert!(preconditioned.iterations < plain.iterations);
+
+    let largest: Vec<f64> = eigs.iter().rev().take(3).copied().collect();
+    let report = lobpcg(&a, 3, Which::Largest, &IdentityPrecond, &opts);
+    check_eigenpairs(&a, &report, &largest, 1e-8);
+    assert_eq!(report.matvecs, 3 * (report.iterations + 1));
+
+    // Lanczos with a single start vector only sees one copy of the double
+    // eigenvalues, the largest one is simple
+    let report_lanczos = lanczos(&a, 1, Which::Largest, &opts);
+    assert!((report.values[0] - report_lanczos.values[0]).abs() < 1e-8);
+
+    // Start block of rank 2 < k = 3: breakdown with the start block and its
+    // Rayleigh quotients, not a panic
+    let n = nx * nx;
+    let mut x0 = Dense::new_zeros((n, 3));
+    for i in 0..n {
+        let v = ((i * 7) % 13) as f64 - 6.;
+        x0.set(i, 0, v);
+        x0.set(i, 1, 1.);
+        x0.set(i, 2, 2. * v);
+    }
+    let report = lobpcg_start(&a, &x0, Which::Smallest, &IdentityPrecond, &opts);
+    assert_eq!(report.status, SolverStatus::Breakdown);
+    assert_eq!(report.iterations, 0);
+    assert_eq!(report.vectors.data, x0.data);
+    assert!((report.values[0] - report.values[2]).abs() < 1e-12);
+    // 1^T A 1 = 4 nx, from the boundary rows
+    assert!((report.values[1] - (4 * nx) as f64 / n as f64).abs() < 1e-12);
+
+    // A full rank start block converges like the default one
+    for i in 0..n {
+        x0.set(i, 2, (i % 5) as f64);
+    }
+    let report = lobpcg_start(&a, &x0, Which::Smallest, &ic0, &opts);
+    check_eigenpairs(&a, &report, &eigs[..3], 1e-8);
+}
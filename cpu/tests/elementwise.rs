@@ -0,0 +1,101 @@
+use fakscpu::elementwise::{sparse_gemm, SparseElementwise};
+use fakscpu::sparse::SparseProd;
+use matrix_base::{Dense, COO, CSR};
+
+
+
+
+#[cfg(test)]
+fn cmp_dense(a: &Dense, b: &Dense, eps: f64) -> bool {
+    a.shape == b.shape && a.data.iter().zip(b.data.iter()).all(|(x, y)| (x-y).abs() < eps)
+}
+
+
+// Dense reference for c_ij = f(a_ij, b_ij)
+#[cfg(test)]
+fn zip_dense(a: &Dense, b: &Dense, f: impl Fn(f64, f64) -> f64) -> Dense {
+    let mut c = Dense::new_zeros(a.shape);
+    for ((x, y), z) in a.data.iter().zip(&b.data).zip(c.data.iter_mut()) {
+        *z = f(*x, *y);
+    }
+    c
+}
+
+
+
+#[test]
+fn test_sparse_add_hadamard() {
+    let eps = 1e-12;
+    let shape = (120, 90);
+    let a = CSR::from_diagonals(&[&[1.], &[-2.], &[0.5]], &[-3, 0, 7], shape);
+    let data = (0..shape.0).flat_map(|i| [(i, (i * 7) % 90, 1.5), (i, (i * 13 + 5) % 90, -1.)]).collect();
+    let b = CSR::from_coo(&COO{data, shape});
+    let (a_dense, b_dense) = (a.to_dense(), b.to_dense());
+
+    for (alpha, beta) in [(1., 1.), (2., -0.5), (0., 3.)] {
+        let expected = zip_dense(&a_dense, &b_dense, |x, y| alpha * x + beta * y);
+        let c = a.sparse_add(alpha, &b, beta);
+        let c_par = a.sparse_add_par(alpha, &b, beta);
+        assert!(cmp_dense(&c.to_dense(), &expected, eps));
+        assert_eq!(c.row_pos, c_par.row_pos);
+        assert_eq!(c.col_pos, c_par.col_pos);
+        assert_eq!(c.values, c_par.values);
+        for i in 0..shape.0 {
+            assert!(c.row_slices(i).0.is_sorted());
+        }
+    }
+
+    // Pattern is the union, also with alpha = 0
+    let c = a.sparse_add(0., &b, 1.);
+    let union = a.iter().map(|(i, j, _)| (i, j)).chain(b.iter().map(|(i, j, _)| (i, j)))
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(c.values.len(), union.len());
+
+    let expected = zip_dense(&a_dense, &b_dense, |x, y| x * y);
+    let h = a.hadamard(&b);
+    let h_par = a.hadamard_par(&b);
+    assert!(cmp_dense(&h.to_dense(), &expected, eps));
+    assert_eq!(h.col_pos, h_par.col_pos);
+    assert_eq!(h.values, h_par.values);
+    assert!(h.values.len() < a.values.len().min(b.values.len()));
+
+    // Unsorted rows, e.g. a hand-built CSR or CSR::from_coo on a COO sorted by row only
+    let unsorted = CSR{row_pos: vec![0, 3, 3, 5], col_pos: vec![4, 0, 2, 3, 1], values: vec![1., 2., 3., 4., 5.], shape: (3, 5)};
+    let other = CSR::from_diagonals(&[&[1.]], &[1], (3, 5));
+    let expected = zip_dense(&unsorted.to_dense(), &other.to_dense(), |x, y| x - y);
+    let c = unsorted.sparse_add_par(1., &other, -1.);
+    assert!(cmp_dense(&c.to_dense(), &expected, eps));
+    assert_eq!(c.col_pos, [0, 1, 2, 4, 2, 1, 3]);
+    assert_eq!(unsorted.hadamard(&other).values, [4.]);
+}
+
+
+
+#[test]
+fn test_scaling_and_map() {
+    let eps = 1e-12;
+    let mut a = CSR::from_diagonals(&[&[1.], &[-2.], &[0.5]], &[-1, 0, 2], (40, 50));
+    let a_dense = a.to_dense();
+    let r: Vec<f64> = (0..40).map(|i| 1. + i as f64).collect();
+    let c: Vec<f64> = (0..50).map(|j| 0.5 - j as f64 * 0.01).collect();
+
+    a.scale_rows(&r);
+    a.scale_cols(&c);
+    let mut expected = Dense::new_zeros(a.shape);
+    for (i, j, x) in expected.iter_mut() {
+        *x = r[i] * a_dense.get(i, j) * c[j];
+    }
+    assert!(cmp_dense(&a.to_dense(), &expected, eps));
+
+    let nnz = a.values.len();
+    a.map_values(|x| x.abs());
+    assert_eq!(a.values.len(), nnz);
+    assert!(a.values.iter().all(|x| *x >= 0.));
+
+    // C = alpha*A*B + beta*C
+    let b = CSR::from_diagonals(&[&[1.], &[3.]], &[0, -5], (50, 30));
+    let c0 = CSR::from_diagonals(&[&[2.]], &[1], (40, 30));
+    let ab = a.product_sparse(&b).to_dense();
+    let expected = zip_dense(&ab, &c0.to_dense(), |x, y| 0.5 * x - 2. * y);
+    assert!(cmp_dense(&sparse_gemm(0.5, &a, &b, -2., &c0).to_dense(), &expected, eps));
+}
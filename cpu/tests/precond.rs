@@ -0,0 +1,153 @@
+mod common;
+
+use common::{convection_2d, laplacian_2d, residual, rhs};
+use fakscpu::elementwise::SparseElementwise;
+use fakscpu::precond::{Ic0, Ilu0, Jacobi, Ssor};
+use fakscpu::solvers::{bicgstab, cg, gmres, IdentityPrecond, Preconditioner, SolverOptions};
+use matrix_base::{COO, CSR};
+
+
+
+
+// laplacian_2d plus a varying positive diagonal, SPD
+#[cfg(test)]
+fn shifted_laplacian_2d(nx: usize) -> CSR {
+    let n = nx * nx;
+    let shift: Vec<f64> = (0..n).map(|i| (i % 7) as f64 * 0.05).collect();
+    laplacian_2d(nx).sparse_add(1., &CSR::from_diagonals(&[&shift], &[0], (n, n)), 1.)
+}
+
+
+
+#[test]
+fn test_exact_factorizations() {
+    // No fill-in for tridiagonal matrices, ILU(0) and IC(0) are exact
+    let n = 50;
+    let diag: Vec<f64> = (0..n).map(|i| 3. + (i % 3) as f64).collect();
+    let a = CSR::from_diagonals(&[&[-1.], &diag, &[-1.2]], &[-1, 0, 1], (n, n));
+    let spd = CSR::from_diagonals(&[&[-1.], &diag, &[-1.]], &[-1, 0, 1], (n, n));
+    let b = rhs(n);
+
+    let ilu = Ilu0::new(&a).unwrap();
+    let mut x = vec![0.; n];
+    ilu.apply(&b, &mut x);
+    assert!(residual(&a, &b, &x) < 1e-12);
+    assert_eq!(ilu.n_levels(), (n, n));
+
+    let ic = Ic0::new(&spd).unwrap();
+    ic.apply(&b, &mut x);
+    assert!(residual(&spd, &b, &x) < 1e-12);
+    let report = cg(&spd, &b, &mut vec![0.; n], &ic, &SolverOptions::default());
+    assert!(report.converged());
+    assert_eq!(report.iterations, 1);
+
+    // Diagonal matrix: a single level
+    let d = CSR::from_diagonals(&[&diag], &[0], (n, n));
+    assert_eq!(Ilu0::new(&d).unwrap().n_levels(), (1, 1));
+    assert_eq!(Ssor::gauss_seidel(&d).unwrap().n_levels(), (1, 1));
+}
+
+
+
+#[test]
+fn test_preconditioned_solvers() {
+    let nx = 40;
+    let n = nx * nx;
+    let b = rhs(n);
+    let opts = SolverOptions::default();
+
+    // 2D grid: the levels are the anti-diagonals of the grid
+    let spd = shifted_laplacian_2d(nx);
+    assert_eq!(Ic0::new(&spd).unwrap().n_levels(), (2*nx - 1, 2*nx - 1));
+
+    let plain = cg(&spd, &b, &mut vec![0.; n], &IdentityPrecond, &opts);
+    assert!(plain.converged());
+    let spd_preconds: Vec<Box<dyn Preconditioner>> = vec![
+        Box::new(Jacobi::new(&spd).unwrap()),
+        Box::new(Ssor::gauss_seidel(&spd).unwrap()),
+        Box::new(Ssor::new(&spd, 1.5).unwrap()),
+        Box::new(Ic0::new(&spd).unwrap()),
+        Box::new(Ilu0::new(&spd).unwrap())];
+    let mut iterations = vec![];
+    for m in &spd_preconds {
+        let mut x = vec![0.; n];
+        let report = cg(&spd, &b, &mut x, m.as_ref(), &opts);
+        assert!(report.converged());
+        assert!(residual(&spd, &b, &x) <= opts.rtol * 1.01);
+        iterations.push(report.iterations);
+    }
+    // Jacobi barely helps on an almost constant diagonal, the others do
+    assert!(iterations[0] <= plain.iterations);
+    assert!(iterations[1..].iter().all(|it| *it < plain.iterations * 2 / 3));
+    assert_eq!(iterations[3], iterations[4]);
+
+    let a = convection_2d(nx);
+    for solver in [bicgstab, gmres] {
+        let plain = solver(&a, &b, &mut vec![0.; n], &IdentityPrecond, &opts);
+        let ilu = Ilu0::new(&a).unwrap();
+        let mut x = vec![0.; n];
+        let report = solver(&a, &b, &mut x, &ilu, &opts);
+        assert!(report.converged());
+        assert!(residual(&a, &b, &x) <= opts.rtol * 1.01);
+        assert!(report.iterations < plain.iterations);
+    }
+}
+
+
+
+#[test]
+fn test_serial_parallel_apply() {
+    // Large enough for levels with parallel rows
+    let nx = 400;
+    let n = nx * nx;
+    let spd = shifted_laplacian_2d(nx);
+    let r = rhs(n);
+
+    let ssor = Ssor::new(&spd, 1.2).unwrap();
+    let ilu = Ilu0::new(&spd).unwrap();
+    let ic = Ic0::new(&spd).unwrap();
+    let jacobi = Jacobi::new(&spd).unwrap();
+
+    let (mut z_ser, mut z_par) = (vec![0.; n], vec![0.; n]);
+    ssor.apply_serial(&r, &mut z_ser);
+    ssor.apply_par(&r, &mut z_par);
+    assert_eq!(z_ser, z_par);
+    ilu.apply_serial(&r, &mut z_ser);
+    ilu.apply_par(&r, &mut z_par);
+    assert_eq!(z_ser, z_par);
+    ic.apply_serial(&r, &mut z_ser);
+    ic.apply_par(&r, &mut z_par);
+    assert_eq!(z_ser, z_par);
+    jacobi.apply_serial(&r, &mut z_ser);
+    jacobi.apply_par(&r, &mut z_par);
+    assert_eq!(z_ser, z_par);
+
+    // SPD pattern: IC(0) and ILU(0) give the same preconditioner, L U = L D^{-1} D L^T
+    let mut z_ilu = vec![0.; n];
+    ilu.apply(&r, &mut z_ilu);
+    ic.apply(&r, &mut z_par);
+    assert!(z_ilu.iter().zip(&z_par).all(|(a, b)| (a - b).abs() < 1e-10 * (1. + a.abs())));
+}
+
+
+
+#[test]
+fn test_precond_errors() {
+    let missing_diag = CSR::from_coo(&COO{data: vec![(0, 1, 1.), (1, 0, 1.)], shape: (2, 2)});
+    assert!(Jacobi::new(&missing_diag).is_err());
+    assert!(Ssor::new(&missing_diag, 1.).is_err());
+    assert!(Ilu0::new(&missing_diag).is_err());
+    assert!(Ic0::new(&missing_diag).is_err());
+
+    // Full diagonal, but not square
+    let wide = CSR::from_diagonals(&[&[2.], &[-1.]], &[0, 1], (3, 5));
+    assert!(Jacobi::new(&wide).is_err());
+    assert!(Ssor::new(&wide, 1.).is_err());
+
+    let spd = shifted_laplacian_2d(4);
+    assert!(Ssor::new(&spd, 2.).is_err());
+    let indefinite = CSR::from_diagonals(&[&[1.], &[1., -1.]], &[-1, 0], (2, 2));
+    assert!(Ic0::new(&indefinite).is_err());
+    let singular = CSR::from_diagonals(&[&[1.], &[1.], &[1.]], &[-1, 0, 1], (2, 2));
+    assert!(Ilu0::new(&singular).is_err());
+}
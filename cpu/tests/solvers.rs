@@ -0,0 +1,165 @@
+mod common;
+
+use common::{convection_2d, laplacian_2d, residual, rhs};
+use fakscpu::solvers::{bicgstab, cg, gmres, IdentityPrecond, SolverOptions, SolverReport, SolverStatus};
+use fakscpu::spmm::SpMV;
+use matrix_base::{CSR, SymCSR, LinearOperator, MatrixFree, Product, Transposed};
+
+
+
+
+// ||b - A x|| / ||b|| matches the report
+#[cfg(test)]
+fn check_solution(a: &CSR, b: &[f64], x: &[f64], report: &SolverReport, rtol: f64) {
+    assert!(report.converged(), "{:?}", report.status);
+    let r = residual(a, b, x);
+    assert!(r <= rtol * 1.01);
+    assert!((report.relative_residual - r).abs() < 1e-3 * rtol + 1e-12);
+    assert_eq!(report.history.len(), report.iterations + 1);
+    assert!(report.history[0] > rtol);
+    assert!(*report.history.last().unwrap() <= rtol * 1.01);
+}
+
+
+
+#[test]
+fn test_spmv() {
+    let a = convection_2d(9);
+    let x: Vec<f64> = (0..81).map(|i| i as f64 * 0.1).collect();
+    let dense = a.to_dense();
+    let expected: Vec<f64> = dense.rows().map(|(_, row)| row.iter().zip(&x).map(|(a, b)| a*b).sum()).collect();
+    assert!(a.spmv(&x).iter().zip(&expected).all(|(y, e)| (y - e).abs() < 1e-12));
+    assert_eq!(a.spmv_par(&x), a.spmv(&x));
+
+    // Same as the symmetric SpMV
+    let p = laplacian_2d(9);
+    let y = p.spmv_par(&x);
+    let y_sym = fakscpu::symmetric::SymmetricProd::spmv(&SymCSR::from_csr(&p), &x);
+    assert!(y.iter().zip(&y_sym).all(|(a, b)| (a - b).abs() < 1e-12));
+}
+
+
+
+#[test]
+fn test_krylov_solvers() {
+    let opts = SolverOptions::default();
+    let a = laplacian_2d(20);
+    let b = rhs(400);
+
+    let mut x = vec![0.; 400];
+    let report = cg(&a, &b, &mut x, &IdentityPrecond, &opts);
+    check_solution(&a, &b, &x, &report, opts.rtol);
+    assert!(report.iterations < 100);
+
+    for solver in [bicgstab, gmres] {
+        for a in [laplacian_2d(20), convection_2d(20)] {
+            let mut x = vec![0.; 400];
+            let report = solver(&a, &b, &mut x, &IdentityPrecond, &opts);
+            check_solution(&a, &b, &x, &report, opts.rtol);
+        }
+    }
+
+    // GMRES residuals never increase, also over restarts
+    let a = convection_2d(20);
+    let mut x = vec![0.; 400];
+    let report = gmres(&a, &b, &mut x, &IdentityPrecond, &SolverOptions{restart: 5, ..opts});
+    check_solution(&a, &b, &x, &report, opts.rtol);
+    assert!(report.history.windows(2).all(|w| w[1] <= w[0] * (1. + 1e-10)));
+
+    // Converged initial guess, zero right hand side
+    let report = cg(&a, &b, &mut x, &IdentityPrecond, &opts);
+    assert!(report.converged());
+    assert_eq!(report.iterations, 0);
+    let mut x = vec![1.; 400];
+    let report = bicgstab(&a, &[0.; 400], &mut x, &IdentityPrecond, &SolverOptions{atol: 1e-10, ..opts});
+    assert!(report.converged());
+    assert!(x.iter().all(|x_i| x_i.abs() < 1e-8));
+}
+
+
+
+#[test]
+fn test_solver_controls() {
+    let a = laplacian_2d(20);
+    let b = rhs(400);
+
+    // Iteration limit is reported, not a panic
+    for solver in [cg, bicgstab, gmres] {
+        let mut x = vec![0.; 400];
+        let report = solver(&a, &b, &mut x, &IdentityPrecond, &SolverOptions{max_iter: 3, record_history: false, ..Default::default()});
+        assert_eq!(report.status, SolverStatus::MaxIterations);
+        assert_eq!(report.iterations, 3);
+        assert!(report.history.is_empty());
+        assert!(report.relative_residual < 1.);
+    }
+
+    // Badly scaled SPD matrix D A D, a closure as Jacobi preconditioner
+    let d: Vec<f64> = (0..400).map(|i| 1. + (i % 10) as f64 * 10.).collect();
+    let mut data = vec![];
+    for (i, j, x) in a.iter() {
+        data.push((i, j, d[i] * x * d[j]));
+    }
+    let scaled = CSR::from_coo(&matrix_base::COO{data, shape: a.shape});
+    let diag: Vec<f64> = (0..400).map(|i| 4. * d[i] * d[i]).collect();
+    let jacobi = |r: &[f64], z: &mut [f64]| {
+        for ((z_i, r_i), d_i) in z.iter_mut().zip(r).zip(&diag) {
+            *z_i = r_i / d_i;
+        }
+    };
+
+    let opts = SolverOptions::default();
+    let mut x = vec![0.; 400];
+    let plain = cg(&scaled, &b, &mut x, &IdentityPrecond, &opts);
+    let mut x = vec![0.; 400];
+    let precond = cg(&scaled, &b, &mut x, &jacobi, &opts);
+    check_solution(&scaled, &b, &x, &precond, opts.rtol);
+    assert!(precond.iterations < plain.iterations);
+
+    for solver in [bicgstab, gmres] {
+        let mut x = vec![0.; 400];
+        let report = solver(&scaled, &b, &mut x, &jacobi, &opts);
+        check_solution(&scaled, &b, &x, &report, opts.rtol);
+    }
+
+    // CG on an indefinite matrix breaks down
+    let indefinite = CSR::from_diagonals(&[&[1., -1.]], &[0], (2, 2));
+    let mut x = vec![0.; 2];
+    let report = cg(&indefinite, &[1., 1.], &mut x, &IdentityPrecond, &opts);
+    assert_eq!(report.status, SolverStatus::Breakdown);
+}
+
+
+
+#[test]
+fn test_operator_solvers() {
+    let opts = SolverOptions::default();
+    let b = rhs(400);
+
+    // laplacian_2d matrix-free, same iterates as the CSR
+    let stencil = MatrixFree::symmetric(400, |x: &[f64], y: &mut [f64]| {
+        for (i, y_i) in y.iter_mut().enumerate() {
+            *y_i = 4. * x[i];
+            if i % 20 > 0 { *y_i -= x[i - 1]; }
+            if i % 20 < 19 { *y_i -= x[i + 1]; }
+            if i >= 20 { *y_i -= x[i - 20]; }
+            if i + 20 < 400 { *y_i -= x[i + 20]; }
+        }
+    });
+    let a = laplacian_2d(20);
+    let mut x = vec![0.; 400];
+    let report = cg(&stencil, &b, &mut x, &IdentityPrecond, &opts);
+    check_solution(&a, &b, &x, &report, opts.rtol);
+    let mut x_csr = vec![0.; 400];
+    assert_eq!(cg(&a, &b, &mut x_csr, &IdentityPrecond, &opts).iterations, report.iterations);
+
+    // CG on the normal equations A^T A x = A^T b without forming A^T A (CGNR)
+    let a = convection_2d(20);
+    let normal = Product::new(Transposed::new(&a), &a);
+    let mut a_t_b = vec![0.; 400];
+    a.apply_transpose(&b, &mut a_t_b);
+    let mut x = vec![0.; 400];
+    let report = cg(&normal, &a_t_b, &mut x, &IdentityPrecond, &SolverOptions{rtol: 1e-10, ..opts});
+    assert!(report.converged());
+    let ax = a.spmv(&x);
+    assert!(ax.iter().zip(&b).all(|(y, b_i)| (y - b_i).abs() < 1e-6));
+}
@@ -1,7 +1,12 @@
 use std::path::Path;
 
+use fakscpu::accumulator::Accumulator;
+use fakscpu::dropping::DropOptions;
+use fakscpu::mask::MaskOptions;
+use fakscpu::plan::SpGemmPlan;
+use fakscpu::semiring::{Semiring, PlusTimes, MinPlus, MaxTimes, OrAnd, PlusPair};
 use fakscpu::sparse::SparseProd;
-use matrix_base::{Dense, COO, CSR};
+use matrix_base::{Dense, COO, CSR, kron};
 
 // Im Endeffekt etwas umständlich über Path joinen.
 // Kann man auch mit String-Concat machen, aber
@@ -167,4 +172,486 @@ fn test_product_csr_sparse_to_coo_par() {
     }
     
     
-}
\ No newline at end of file
+}
+
+
+#[test]
+fn test_product_csr_accumulators() {
+    let eps = 1e-7;
+
+    // Number of matrices to test
+    let n = 9;
+
+    let accs = [Accumulator::Dense, Accumulator::Hash, Accumulator::Esc, Accumulator::Heap, Accumulator::Auto];
+
+    for k in 0..n {
+        println!("Testing k={}", k);
+
+        let fname = Path::new(DATA_PATH).join(Path::new(&format!("generated/case_{:04}_A.mtx", k)));
+        let a = COO::read_mtx(&fname, true).expect("Failed reading matrix during test");
+        let fname = Path::new(DATA_PATH).join(Path::new(&format!("generated/case_{:04}_B.mtx", k)));
+        let b = COO::read_mtx(&fname, true).expect("Failed reading matrix during test");
+        let fname = Path::new(DATA_PATH).join(Path::new(&format!("generated/case_{:04}_C.mtx", k)));
+        let c = COO::read_mtx(&fname, true).expect("Failed reading matrix during test");
+
+        let a = CSR::from_coo(&a);
+        let b = CSR::from_coo(&b);
+        let c = c.to_dense();
+
+        for acc in accs {
+            println!("Accumulator {:?}", acc);
+            assert!(cmp_dense(&c, &a.product_sparse_with(&b, acc).to_dense(), eps));
+            assert!(cmp_dense(&c, &a.product_sparse_par_with(&b, acc).to_dense(), eps));
+        }
+    }
+}
+
+
+
+#[test]
+fn test_product_csr_accumulators_wide() {
+    let eps = 1e-10;
+
+    // Wide banded matrices, so the rows have few entries compared to n
+    // and Auto picks every accumulator at least once
+    let a = CSR::from_diagonals(&[&[1.], &[2.], &[-1.], &[0.5], &[3.]], &[0, 1, 7, 40, 300], (60, 3000));
+    let b = CSR::from_diagonals(&[&[1.], &[-2.], &[1.], &[4.]], &[-1, 0, 1, 900], (3000, 3000));
+    let c = a.product(&b);
+
+    let accs = [Accumulator::Dense, Accumulator::Hash, Accumulator::Esc, Accumulator::Heap, Accumulator::Auto];
+
+    let mut reference: Option<CSR> = None;
+    for acc in accs {
+        let c_seq = a.product_sparse_with(&b, acc);
+        let c_par = a.product_sparse_par_with(&b, acc);
+        assert!(cmp_dense(&c, &c_seq.to_dense(), eps));
+        assert!(cmp_dense(&c, &c_par.to_dense(), eps));
+
+        // Same structure for all accumulators, sorted rows
+        assert_eq!(c_seq.row_pos.len(), a.shape.0 + 1);
+        assert_eq!(c_seq.row_pos, c_par.row_pos);
+        assert_eq!(c_seq.col_pos, c_par.col_pos);
+        match &reference {
+            Some(r) => {
+                assert_eq!(r.row_pos, c_seq.row_pos);
+                assert_eq!(r.col_pos, c_seq.col_pos);
+            }
+            None => reference = Some(c_seq)
+        }
+    }
+
+    // Auto: few products -> Esc, short row of A -> Heap,
+    // long row of A -> Hash, many products compared to n -> Dense
+    let band_offsets: Vec<isize> = (0..20).map(|k| 10 * k).collect();
+    let band_diags: Vec<&[f64]> = vec![&[1.]; 20];
+    let band = CSR::from_diagonals(&band_diags, &band_offsets, (3000, 3000));
+    assert_eq!(Accumulator::Auto.choose(&[0], &b), Accumulator::Esc);
+    assert_eq!(Accumulator::Auto.choose(&[0, 5], &band), Accumulator::Heap);
+    assert_eq!(Accumulator::Auto.choose(&[0, 5, 10, 15, 20, 25], &band), Accumulator::Hash);
+    assert_eq!(Accumulator::Auto.choose(&(0..200).collect::<Vec<_>>(), &band), Accumulator::Dense);
+    assert_eq!(Accumulator::Hash.choose(&[0], &b), Accumulator::Hash);
+
+    // Unsorted rows of B, e.g. from a COO sorted by row only: Auto must not merge them with Heap
+    let mut unsorted = CSR{row_pos: band.row_pos.clone(), col_pos: band.col_pos.clone(), values: band.values.clone(), shape: band.shape};
+    for i in 0..unsorted.shape.0 {
+        let (start, end) = (unsorted.row_pos[i], unsorted.row_pos[i+1]);
+        unsorted.col_pos[start..end].reverse();
+        for (l, x) in unsorted.values[start..end].iter_mut().enumerate() {
+            *x += l as f64;
+        }
+    }
+    assert_eq!(Accumulator::Auto.choose(&[0, 5], &unsorted), Accumulator::Hash);
+    let a = CSR::from_diagonals(&[&[1.], &[-3.]], &[0, 5], (3000, 3000));
+    assert!(cmp_dense(&a.product(&unsorted), &a.product_sparse(&unsorted).to_dense(), eps));
+    assert!(cmp_dense(&a.product(&unsorted), &a.product_sparse_par(&unsorted).to_dense(), eps));
+}
+
+
+
+#[test]
+fn test_spgemm_plan() {
+    let eps = 1e-10;
+
+    let mut a = CSR::from_diagonals(&[&[1.], &[2.], &[-1.], &[3.]], &[-2, 0, 1, 17], (40, 50));
+    let mut b = CSR::from_diagonals(&[&[1.], &[-2.], &[1.]], &[-1, 0, 1], (50, 30));
+
+    let plan = SpGemmPlan::new(&a, &b);
+    let mut c = plan.output();
+
+    // Same pattern as the one-shot product
+    let c_ref = a.product_sparse(&b);
+    assert_eq!(plan.row_pos, c_ref.row_pos);
+    assert_eq!(plan.col_pos, c_ref.col_pos);
+    assert_eq!(plan.nnz(), c_ref.values.len());
+
+    // "Time steps": only the values change
+    for step in 0..3 {
+        for (i, j, x) in a.iter_mut() {
+            *x = (i + 2*j + step) as f64 * 0.1;
+        }
+        for (i, j, x) in b.iter_mut() {
+            *x = 1. / (1 + i + j + step) as f64;
+        }
+
+        plan.execute(&a, &b, &mut c);
+        assert!(cmp_dense(&a.product(&b), &c.to_dense(), eps));
+    }
+}
+
+
+
+#[test]
+#[should_panic]
+fn test_spgemm_plan_pattern_changed() {
+    let a = CSR::from_diagonals(&[&[1.], &[2.]], &[0, 1], (10, 10));
+    let b = CSR::identity(10);
+    let plan = SpGemmPlan::new(&a, &b);
+    let mut c = plan.output();
+
+    let a2 = CSR::from_diagonals(&[&[1.], &[2.]], &[0, 2], (10, 10));
+    plan.execute(&a2, &b, &mut c);
+}
+
+
+
+// The pattern itself is only compared in debug builds
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn test_spgemm_plan_pattern_moved() {
+    // Same nnz and every product still lands in the pattern of C
+    let a = CSR::from_diagonals(&[&[1.], &[2.]], &[0, 2], (10, 10));
+    let b = CSR::from_diagonals(&[&[1.], &[2.], &[1.]], &[-1, 0, 1], (10, 10));
+    let plan = SpGemmPlan::new(&a, &b);
+    let mut c = plan.output();
+
+    // a_35 moved to a_34
+    let mut a2 = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+    a2.col_pos[a2.row_pos[3] + 1] = 4;
+    plan.execute(&a2, &b, &mut c);
+}
+
+
+
+#[test]
+fn test_product_csr_sparse_par_deterministic() {
+    // 2D Laplacian-like operator with non-trivial values
+    let t = CSR::from_diagonals(&[&[1.1], &[-2.3], &[0.7]], &[-1, 0, 1], (30, 30));
+    let mut a = kron(&CSR::identity(30), &t);
+    for (i, j, x) in a.iter_mut() {
+        *x *= 1. + ((i * 7 + j * 3) % 11) as f64 * 0.01;
+    }
+    let b = kron(&t, &CSR::identity(30));
+
+    let c_seq = a.product_sparse(&b);
+
+    // Same bits, no matter how many threads
+    for n_threads in [1, 3, 8] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build().unwrap();
+        let c_par = pool.install(|| a.product_sparse_par(&b));
+        assert_eq!(c_par.row_pos, c_seq.row_pos);
+        assert_eq!(c_par.col_pos, c_seq.col_pos);
+        assert_eq!(c_par.values, c_seq.values);
+
+        let coo = pool.install(|| a.product_sparse_to_coo_par(&b));
+        let expected: Vec<(usize, usize, f64)> = c_seq.iter().map(|(i, j, x)| (i, j, *x)).collect();
+        assert_eq!(coo.data, expected);
+    }
+}
+
+
+
+#[test]
+fn test_product_sparse_masked() {
+    let eps = 1e-10;
+
+    let a = CSR::from_diagonals(&[&[1.], &[2.], &[-1.], &[0.5]], &[-3, 0, 1, 9], (40, 30));
+    let b = CSR::from_diagonals(&[&[1.], &[-2.], &[1.], &[4.]], &[-1, 0, 1, 12], (30, 50));
+    let c = a.product(&b);
+
+    // Mask with some explicit zeros, which only count for a structural mask
+    let mut mask = CSR::from_diagonals(&[&[1.], &[0.], &[3.], &[1.]], &[-4, 0, 2, 13], (40, 50));
+    for (i, _, x) in mask.iter_mut() {
+        if i % 5 == 0 {
+            *x = 0.;
+        }
+    }
+    let mask_dense = mask.to_dense();
+
+    for complement in [false, true] {
+        for structural in [false, true] {
+            let opts = MaskOptions{complement, structural};
+
+            // Reference: dense product, entries outside the mask set to 0
+            let mut expected = Dense::new_zeros(c.shape);
+            for i in 0..c.shape.0 {
+                for j in 0..c.shape.1 {
+                    let stored = mask.row(i).any(|(k, x)| k == j && (structural || *x != 0.));
+                    if stored != complement {
+                        expected.set(i, j, c.get(i, j));
+                    }
+                }
+            }
+
+            let c_seq = a.product_sparse_masked(&b, &mask, opts);
+            let c_par = a.product_sparse_masked_par(&b, &mask, opts);
+            assert!(cmp_dense(&expected, &c_seq.to_dense(), eps));
+            assert_eq!(c_seq.row_pos, c_par.row_pos);
+            assert_eq!(c_seq.col_pos, c_par.col_pos);
+            assert_eq!(c_seq.values, c_par.values);
+
+            // No entry outside the mask is even stored
+            for (i, j, _) in c_seq.iter() {
+                let allowed = mask_dense.get(i, j) != 0. || (structural && mask.row(i).any(|(k, _)| k == j));
+                assert!(allowed != complement);
+            }
+        }
+    }
+
+    // Complement of an empty mask is the plain product
+    let empty = CSR::from_diagonals(&[], &[], (40, 50));
+    let full = a.product_sparse_masked_par(&b, &empty, MaskOptions{complement: true, structural: true});
+    let plain = a.product_sparse(&b);
+    assert_eq!(full.col_pos, plain.col_pos);
+    assert_eq!(full.values, plain.values);
+}
+
+
+
+#[test]
+fn test_product_sparse_masked_triangles() {
+    // Triangle counting: L strictly lower triangle of the adjacency matrix
+    // of an undirected graph, sum(C) with C<L> = L*L is the number of triangles.
+    // Graph: 0,1,3,4 fully connected (4 triangles) and the square 1-2-5-4 (none)
+    let edges = [(1, 0), (3, 0), (4, 1), (4, 3), (4, 0), (3, 1), (2, 1), (5, 2), (5, 4)];
+    let mut data: Vec<(usize, usize, f64)> = edges.iter().map(|&(i, j)| (i, j, 1.)).collect();
+    data.sort_by_key(|&(i, j, _)| (i, j));
+    let l = CSR::from_coo(&COO{data, shape: (6, 6)});
+
+    let c = l.product_sparse_masked(&l, &l, MaskOptions::default());
+    let triangles: f64 = c.values.iter().sum();
+    assert_eq!(triangles, 4.);
+    assert_eq!(l.product_sparse_masked_par(&l, &l, MaskOptions::default()).values, c.values);
+}
+
+
+
+// Reference for C = A (+).(x) B over the stored entries only,
+// None where no product contributes
+#[cfg(test)]
+fn product_semiring_ref<S: Semiring>(a: &CSR, b: &CSR) -> Vec<Vec<Option<f64>>> {
+    let mut c = vec![vec![None; b.shape.1]; a.shape.0];
+    for (i, k, a_ik) in a.iter() {
+        for (j, b_kj) in b.row(k) {
+            let x = S::mul(*a_ik, *b_kj);
+            c[i][j] = Some(c[i][j].map_or(x, |c_ij| S::add(c_ij, x)));
+        }
+    }
+    c
+}
+
+
+#[cfg(test)]
+fn check_semiring<S: Semiring>(a: &CSR, b: &CSR, eps: f64) {
+    let expected = product_semiring_ref::<S>(a, b);
+    let c = a.product_sparse_semiring::<S>(b);
+    let c_par = a.product_sparse_semiring_par::<S>(b);
+
+    assert_eq!(c.row_pos, c_par.row_pos);
+    assert_eq!(c.col_pos, c_par.col_pos);
+    assert_eq!(c.values, c_par.values);
+
+    let mut dense = vec![vec![None; c.shape.1]; c.shape.0];
+    for (i, j, x) in c.iter() {
+        dense[i][j] = Some(*x);
+    }
+    for (row, row_ref) in dense.iter().zip(&expected) {
+        for (x, y) in row.iter().zip(row_ref) {
+            match (x, y) {
+                (Some(x), Some(y)) => assert!(cmp_float(*x, *y, eps)),
+                (None, None) => (),
+                _ => panic!("Pattern differs from the reference")
+            }
+        }
+    }
+}
+
+
+
+#[test]
+fn test_product_sparse_semiring() {
+    let eps = 1e-10;
+
+    let mut a = CSR::from_diagonals(&[&[1.], &[2.], &[-1.], &[0.5]], &[-3, 0, 1, 9], (40, 30));
+    let mut b = CSR::from_diagonals(&[&[1.], &[-2.], &[1.], &[4.]], &[-1, 0, 1, 12], (30, 50));
+    for (i, j, x) in a.iter_mut() {
+        *x *= 0.1 * ((i + 3*j) % 7) as f64;
+    }
+    for (i, j, x) in b.iter_mut() {
+        *x = 0.05 * ((2*i + j) % 5) as f64;
+    }
+
+    check_semiring::<PlusTimes>(&a, &b, eps);
+    check_semiring::<MinPlus>(&a, &b, eps);
+    check_semiring::<MaxTimes>(&a, &b, eps);
+    check_semiring::<OrAnd>(&a, &b, eps);
+    check_semiring::<PlusPair>(&a, &b, eps);
+
+    // PlusTimes is the default product
+    let c = a.product_sparse_semiring::<PlusTimes>(&b);
+    assert_eq!(c.values, a.product_sparse(&b).values);
+
+    // Identities and boolean values
+    assert_eq!(MinPlus::identity(), f64::INFINITY);
+    assert_eq!(OrAnd::add(0., 2.), 1.);
+    assert_eq!(PlusPair::mul(3., -2.), 1.);
+}
+
+
+
+#[test]
+fn test_product_sparse_semiring_graphs() {
+    // Weighted directed graph, a_ij = weight of the edge i -> j
+    let edges = [(0, 1, 4.), (0, 2, 1.), (2, 1, 2.), (1, 3, 1.), (2, 3, 5.), (3, 4, 3.), (4, 0, 1.), (5, 4, 2.)];
+    let n = 6;
+    let mut data: Vec<(usize, usize, f64)> = edges.to_vec();
+    data.sort_by_key(|&(i, j, _)| (i, j));
+    let a = CSR::from_coo(&COO{data: data.clone(), shape: (n, n)});
+
+    // APSP by repeated squaring of D = A + 0 on the diagonal (explicitly stored)
+    data.extend((0..n).map(|i| (i, i, 0.)));
+    data.sort_by_key(|&(i, j, _)| (i, j));
+    let mut d = CSR::from_coo(&COO{data, shape: (n, n)});
+    for _ in 0..3 {
+        d = d.product_sparse_semiring_par::<MinPlus>(&d);
+    }
+
+    // Floyd-Warshall on the dense matrix
+    let mut fw = vec![vec![f64::INFINITY; n]; n];
+    for (i, row) in fw.iter_mut().enumerate() {
+        row[i] = 0.;
+    }
+    for &(i, j, w) in &edges {
+        fw[i][j] = w;
+    }
+    for k in 0..n {
+        let row_k = fw[k].clone();
+        for row in fw.iter_mut() {
+            let d_ik = row[k];
+            for (d_ij, d_kj) in row.iter_mut().zip(&row_k) {
+                *d_ij = d_ij.min(d_ik + d_kj);
+            }
+        }
+    }
+    for (i, row) in fw.iter().enumerate() {
+        for (j, dist_fw) in row.iter().enumerate() {
+            let dist = d.row(i).find(|(k, _)| *k == j).map_or(MinPlus::identity(), |(_, x)| *x);
+            assert_eq!(dist, *dist_fw);
+        }
+    }
+
+    // BFS from vertex 5 with OrAnd: frontier f (1 x n), visited v as complemented mask
+    let mut f = CSR::from_coo(&COO{data: vec![(0, 5, 1.)], shape: (1, n)});
+    let mut v = CSR::from_coo(&COO{data: vec![(0, 5, 1.)], shape: (1, n)});
+    let mut levels = vec![usize::MAX; n];
+    levels[5] = 0;
+    let mut level = 0;
+    while !f.values.is_empty() {
+        level += 1;
+        f = f.product_sparse_masked_semiring::<OrAnd>(&a, &v, MaskOptions{complement: true, structural: true});
+        assert!(f.values.iter().all(|x| *x == 1.));
+        for (_, j, _) in f.iter() {
+            levels[j] = level;
+        }
+        let mut visited: Vec<(usize, usize, f64)> = v.iter().map(|(i, j, x)| (i, j, *x)).chain(f.iter().map(|(i, j, x)| (i, j, *x))).collect();
+        visited.sort_by_key(|&(i, j, _)| (i, j));
+        v = CSR::from_coo(&COO{data: visited, shape: (1, n)});
+    }
+    assert_eq!(levels, vec![2, 3, 3, 4, 1, 0]);
+
+    // PlusPair counts the paths of length 2
+    let paths = a.product_sparse_semiring::<PlusPair>(&a);
+    let ones = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: vec![1.; a.values.len()], shape: a.shape};
+    assert_eq!(paths.values, ones.product_sparse(&ones).values);
+}
+
+
+
+#[test]
+fn test_product_sparse_dropped() {
+    // Entries of very different magnitudes
+    let mut a = CSR::from_diagonals(&[&[1.], &[2.], &[-1.], &[0.25]], &[-3, 0, 1, 9], (200, 150));
+    let mut b = CSR::from_diagonals(&[&[1.], &[-2.], &[1.], &[4.]], &[-1, 0, 1, 12], (150, 250));
+    for (i, j, x) in a.iter_mut() {
+        *x *= ((i * 7 + j * 3) % 11) as f64 / 4. - 1.;
+    }
+    for (i, j, x) in b.iter_mut() {
+        *x *= 10f64.powi((i + j) as i32 % 5 - 2);
+    }
+    let c = a.product_sparse(&b);
+
+    // Nothing is dropped by default
+    let d = a.product_sparse_dropped_par(&b, DropOptions::default());
+    assert_eq!(d.row_pos, c.row_pos);
+    assert_eq!(d.values, c.values);
+
+    for opts in [
+        DropOptions{abs_tol: 0.5, ..Default::default()},
+        DropOptions{rel_tol: 0.3, ..Default::default()},
+        DropOptions{top_k: Some(3), ..Default::default()},
+        DropOptions{top_k: Some(0), ..Default::default()},
+        DropOptions{abs_tol: 0.05, rel_tol: 0.1, top_k: Some(2), prune_zeros: true}
+    ] {
+        // Reference: the rows of the full product, dropped one by one
+        let mut expected = vec![];
+        for (i, cols, vals) in c.rows() {
+            let row_max = vals.iter().fold(0., |acc: f64, x| acc.max(x.abs()));
+            let mut row: Vec<(usize, f64)> = cols.iter().copied().zip(vals.iter().copied())
+                .filter(|(_, x)| x.abs() >= opts.abs_tol.max(opts.rel_tol * row_max) && !(opts.prune_zeros && *x == 0.))
+                .collect();
+            if let Some(k) = opts.top_k {
+                // Stable sort, equal magnitudes stay in column order
+                row.sort_by(|(_, x), (_, y)| y.abs().total_cmp(&x.abs()));
+                row.truncate(k);
+                row.sort_by_key(|(j, _)| *j);
+            }
+            expected.extend(row.into_iter().map(|(j, x)| (i, j, x)));
+        }
+
+        let d_seq = a.product_sparse_dropped(&b, opts);
+        let d_par = a.product_sparse_dropped_par(&b, opts);
+        assert_eq!(d_seq.iter().map(|(i, j, x)| (i, j, *x)).collect::<Vec<_>>(), expected);
+        assert_eq!(d_seq.row_pos, d_par.row_pos);
+        assert_eq!(d_seq.col_pos, d_par.col_pos);
+        assert_eq!(d_seq.values, d_par.values);
+        if let Some(k) = opts.top_k {
+            assert!((0..d_seq.shape.0).all(|i| d_seq.get_row_nnz(i) <= k));
+        }
+    }
+
+    // Exact cancellation: c_ii = 1 * 1 + 1 * (-1) = 0 is stored, unless pruned
+    let a = CSR::from_diagonals(&[&[1.], &[1.]], &[0, 1], (10, 10));
+    let b = CSR::from_diagonals(&[&[-1.], &[1.]], &[-1, 0], (10, 10));
+    let c = a.product_sparse(&b);
+    assert_eq!(c.iter().filter(|(_, _, x)| **x == 0.).count(), 9);
+    let pruned = a.product_sparse_dropped_par(&b, DropOptions{prune_zeros: true, ..Default::default()});
+    assert_eq!(pruned.values.len(), c.values.len() - 9);
+    assert!(pruned.values.iter().all(|x| *x != 0.));
+
+    // NaN is kept by the thresholds and ranks above every number in top_k,
+    // exactly k entries are kept
+    let a = CSR{row_pos: vec![0, 1], col_pos: vec![0], values: vec![1.], shape: (1, 1)};
+    for (b_vals, opts, kept_cols) in [
+        (vec![f64::NAN, 1.], DropOptions{top_k: Some(1), ..Default::default()}, vec![0]),
+        (vec![3., f64::NAN, 2.], DropOptions{top_k: Some(2), ..Default::default()}, vec![0, 1]),
+        (vec![2., 3., f64::NAN], DropOptions{top_k: Some(2), ..Default::default()}, vec![1, 2]),
+        (vec![3., f64::NAN, 2.], DropOptions{abs_tol: 2.5, top_k: Some(1), ..Default::default()}, vec![1]),
+        (vec![f64::NAN, 1., f64::NAN], DropOptions{top_k: Some(2), ..Default::default()}, vec![0, 2])
+    ] {
+        let n = b_vals.len();
+        let b = CSR{row_pos: vec![0, n], col_pos: (0..n).collect(), values: b_vals, shape: (1, n)};
+        let d = a.product_sparse_dropped(&b, opts);
+        assert_eq!(d.col_pos, kept_cols);
+        assert_eq!(a.product_sparse_dropped_par(&b, opts).col_pos, kept_cols);
+    }
+}
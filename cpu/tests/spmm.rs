@@ -0,0 +1,77 @@
+use fakscpu::dense::DenseProd;
+use fakscpu::spmm::{DenseSparseProd, SpMM};
+use matrix_base::{Dense, CSR};
+
+
+
+
+#[cfg(test)]
+fn cmp_dense(a: &Dense, b: &Dense, eps: f64) -> bool {
+    a.shape == b.shape && a.data.iter().zip(b.data.iter()).all(|(x, y)| (x-y).abs() < eps)
+}
+
+
+#[cfg(test)]
+fn block_vector(shape: (usize, usize)) -> Dense {
+    let mut x = Dense::new_zeros(shape);
+    for (i, j, v) in x.iter_mut() {
+        *v = ((i * 3 + j * 7) % 17) as f64 * 0.125 - 1.;
+    }
+    x
+}
+
+
+
+#[test]
+fn test_spmm() {
+    let eps = 1e-10;
+
+    let mut a = CSR::from_diagonals(&[&[1.], &[-2.], &[0.5], &[3.]], &[-7, 0, 1, 40], (300, 250));
+    for (i, j, x) in a.iter_mut() {
+        *x *= 1. + ((i + j) % 5) as f64 * 0.1;
+    }
+    let a_dense = a.to_dense();
+
+    // Block vectors of different widths, with and without remainder columns
+    for p in [1, 4, 7, 8, 13, 64, 256] {
+        let x = block_vector((250, p));
+        let expected = a_dense.product_dense_par(&x);
+
+        assert!(cmp_dense(&expected, &a.spmm(&x), eps));
+        assert!(cmp_dense(&expected, &a.spmm_par(&x), eps));
+    }
+}
+
+
+
+#[test]
+fn test_dense_sparse_product() {
+    let eps = 1e-10;
+
+    let mut b = CSR::from_diagonals(&[&[1.], &[-2.], &[0.5], &[3.]], &[-7, 0, 1, 40], (1500, 120));
+    for (i, j, x) in b.iter_mut() {
+        *x *= 1. + ((i + j) % 5) as f64 * 0.1;
+    }
+    let b_dense = b.to_dense();
+
+    // Few rows (column-parallel) and many rows (row-parallel),
+    // both with the summation order of the serial product
+    for m in [1, 4, 16, 200] {
+        let x = block_vector((m, 1500));
+        let expected = x.product_dense_par(&b_dense);
+
+        assert!(cmp_dense(&expected, &x.product_csr(&b), eps));
+        assert!(cmp_dense(&expected, &x.product_csr_par(&b), eps));
+        assert_eq!(x.product_csr_par(&b).data, x.product_csr(&b).data);
+    }
+
+    // NaN and Inf in B propagate through zeros of X, as in the dense product
+    let mut b = CSR::from_diagonals(&[&[1.]], &[0], (3, 3));
+    b.values[1] = f64::NAN;
+    b.values[2] = f64::INFINITY;
+    let x = Dense{data: vec![1., 0., 0.], shape: (1, 3)};
+    for y in [x.product_csr(&b), x.product_csr_par(&b)] {
+        assert_eq!(y.data[0], 1.);
+        assert!(y.data[1].is_nan() && y.data[2].is_nan());
+    }
+}
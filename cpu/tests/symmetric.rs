@@ -0,0 +1,81 @@
+mod common;
+
+use std::path::Path;
+
+use common::{laplacian_1d, laplacian_2d};
+use fakscpu::sparse::SparseProd;
+use fakscpu::spmm::SpMV;
+use fakscpu::symmetric::SymmetricProd;
+use matrix_base::{kron, Dense, CSR, SymCSR};
+
+const DATA_PATH: &str = "../matrix_instances";
+
+
+
+
+#[cfg(test)]
+fn cmp_dense(a: &Dense, b: &Dense, eps: f64) -> bool {
+    a.shape == b.shape && a.data.iter().zip(b.data.iter()).all(|(x, y)| (x-y).abs() < eps)
+}
+
+
+
+#[test]
+fn test_symmetric_spmv() {
+    let eps = 1e-10;
+
+    let fname = Path::new(DATA_PATH).join(Path::new("symmetric/s001.mtx"));
+    let sym = SymCSR::read_mtx(&fname).expect("Failed reading matrix during test");
+    let full = sym.to_dense();
+
+    let x = [1., 2., -1., 0.5];
+    let expected: Vec<f64> = full.rows().map(|(_, row)| row.iter().zip(x).map(|(a, b)| a*b).sum()).collect();
+
+    for y in [sym.spmv(&x), sym.spmv_par(&x)] {
+        assert!(y.iter().zip(&expected).all(|(a, b)| (a-b).abs() < eps));
+    }
+}
+
+
+
+#[test]
+fn test_symmetric_product_sparse() {
+    let eps = 1e-10;
+
+    let fname = Path::new(DATA_PATH).join(Path::new("symmetric/s001.mtx"));
+    let sym = SymCSR::read_mtx(&fname).expect("Failed reading matrix during test");
+    let full = sym.to_csr();
+
+    // A*A, A*B with a non-square B
+    let b = CSR::from_diagonals(&[&[1.], &[2.], &[-3.]], &[0, 1, 3], (4, 6));
+
+    for other in [&full, &b] {
+        let expected = full.product(other);
+        assert!(cmp_dense(&sym.product_sparse(other).to_dense(), &expected, eps));
+        assert!(cmp_dense(&sym.product_sparse_par(other).to_dense(), &expected, eps));
+    }
+}
+
+
+
+#[test]
+fn test_symmetric_par_many_rows() {
+    // Several chunks of spmv_par, 2D operator with non-trivial values
+    let mut full = laplacian_2d(90);
+    for (i, j, x) in full.iter_mut() {
+        *x *= 1. + ((i + j) % 5) as f64 * 0.1;
+    }
+    let sym = SymCSR::from_csr(&full);
+
+    let x: Vec<f64> = (0..90 * 90).map(|i| (i % 7) as f64 - 3.).collect();
+    let expected = SpMV::spmv(&full, &x);
+    assert!(sym.spmv_par(&x).iter().zip(&expected).all(|(a, b)| (a-b).abs() < 1e-12));
+
+    let b = kron(&CSR::identity(90), &laplacian_1d(90));
+    let c = sym.product_sparse(&b);
+    let c_par = sym.product_sparse_par(&b);
+    assert_eq!(c_par.row_pos, c.row_pos);
+    assert_eq!(c_par.col_pos, c.col_pos);
+    assert_eq!(c_par.values, c.values);
+    assert_eq!(c.values, full.product_sparse(&b).values);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::rhs;
+use fakscpu::triangular::{Diagonal, Triangle, TriangularAnalysis, TriangularSolve};
+use matrix_base::{Dense, CSC, CSR};
+
+
+
+
+// op(T) x = b by dense substitution on the used triangle of op(T)
+#[cfg(test)]
+fn dense_solve(t: &Dense, triangle: Triangle, transposed: bool, diagonal: Diagonal, b: &[f64]) -> Vec<f64> {
+    let n = t.shape.0;
+    let op = |i: usize, j: usize| if transposed { t.get(j, i) } else { t.get(i, j) };
+    let lower = (triangle == Triangle::Lower) != transposed;
+    let mut x = b.to_vec();
+    let rows: Vec<usize> = if lower { (0..n).collect() } else { (0..n).rev().collect() };
+    for &i in &rows {
+        let deps: Vec<usize> = if lower { (0..i).collect() } else { (i+1..n).collect() };
+        let s: f64 = deps.iter().map(|&j| op(i, j) * x[j]).sum();
+        x[i] = match diagonal {
+            Diagonal::Unit => x[i] - s,
+            Diagonal::NonUnit => (x[i] - s) / op(i, i)
+        };
+    }
+    x
+}
+
+
+
+#[test]
+fn test_triangular_solve() {
+    // Full non-symmetric matrix, every solve only uses one triangle of it
+    let n = 60;
+    let t = CSR::from_diagonals(&[&[0.5], &[-1.], &[4.], &[-2.], &[0.3]], &[-7, -1, 0, 1, 5], (n, n));
+    let dense = t.to_dense();
+    let t_csc = CSC::from_csr(&t);
+    let b = rhs(n);
+
+    for triangle in [Triangle::Lower, Triangle::Upper] {
+        for transposed in [false, true] {
+            for diagonal in [Diagonal::NonUnit, Diagonal::Unit] {
+                let expected = dense_solve(&dense, triangle, transposed, diagonal, &b);
+                let x = t.solve_triangular(triangle, transposed, diagonal, &b).unwrap();
+                assert!(x.iter().zip(&expected).all(|(x, e)| (x - e).abs() < 1e-10), "{:?} {} {:?}", triangle, transposed, diagonal);
+                assert_eq!(t.solve_triangular_par(triangle, transposed, diagonal, &b).unwrap(), x);
+
+                // The same triangle stored in CSC
+                let x_csc = t_csc.solve_triangular(triangle, transposed, diagonal, &b).unwrap();
+                assert!(x_csc.iter().zip(&expected).all(|(x, e)| (x - e).abs() < 1e-10), "{:?} {} {:?}", triangle, transposed, diagonal);
+                assert_eq!(t_csc.solve_triangular_par(triangle, transposed, diagonal, &b).unwrap(), x_csc);
+            }
+        }
+    }
+
+    // Errors on the diagonal
+    let no_diag = CSR::from_diagonals(&[&[1.], &[1.]], &[-1, 1], (n, n));
+    assert!(no_diag.solve_triangular(Triangle::Lower, false, Diagonal::NonUnit, &b).is_err());
+    assert!(no_diag.solve_triangular(Triangle::Lower, false, Diagonal::Unit, &b).is_ok());
+    let zero_diag = CSR::from_diagonals(&[&[1.], &[0.]], &[-1, 0], (n, n));
+    assert!(zero_diag.solve_triangular(Triangle::Upper, true, Diagonal::NonUnit, &b).is_err());
+}
+
+
+
+#[test]
+fn test_triangular_analysis() {
+    // Few long levels, so that the levels are solved in parallel:
+    // row i depends on i - 400 and i - 700
+    let n = 3000;
+    let l = CSR::from_diagonals(&[&[0.3], &[-0.6], &[2.]], &[-700, -400, 0], (n, n));
+    let b = rhs(n);
+
+    for transposed in [false, true] {
+        let analysis = TriangularAnalysis::new(&l, Triangle::Lower, transposed, Diagonal::NonUnit).unwrap();
+        assert_eq!(analysis.n_levels(), (n - 1) / 400 + 1);
+
+        let mut x = b.clone();
+        analysis.solve(&l, &mut x);
+        let mut x_par = b.clone();
+        analysis.solve_par(&l, &mut x_par);
+        assert_eq!(x, x_par);
+        let expected = dense_solve(&l.to_dense(), Triangle::Lower, transposed, Diagonal::NonUnit, &b);
+        assert!(x.iter().zip(&expected).all(|(x, e)| (x - e).abs() < 1e-12));
+
+        // Reused for new values on the same pattern
+        let l2 = CSR{row_pos: l.row_pos.clone(), col_pos: l.col_pos.clone(), values: l.values.iter().map(|x| 2. * x).collect(), shape: l.shape};
+        let mut x2 = b.clone();
+        analysis.solve_par(&l2, &mut x2);
+        assert!(x2.iter().zip(&x).all(|(a, b)| (2. * a - b).abs() < 1e-12));
+    }
+}
+
+
+
+#[test]
+#[should_panic]
+fn test_triangular_analysis_nnz_changed() {
+    let n = 10;
+    let l = CSR::from_diagonals(&[&[1.], &[2.]], &[-1, 0], (n, n));
+    let analysis = TriangularAnalysis::new(&l, Triangle::Lower, false, Diagonal::NonUnit).unwrap();
+
+    let diag = CSR::from_diagonals(&[&[2.]], &[0], (n, n));
+    let mut x = rhs(n);
+    analysis.solve(&diag, &mut x);
+}
+
+
+
+// The pattern itself is only compared in debug builds
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn test_triangular_analysis_pattern_changed() {
+    let n = 10;
+    let l = CSR::from_diagonals(&[&[1.], &[2.]], &[-1, 0], (n, n));
+    let analysis = TriangularAnalysis::new(&l, Triangle::Lower, false, Diagonal::NonUnit).unwrap();
+
+    // Same nnz, l_54 moved to l_53
+    let mut moved = CSR{row_pos: l.row_pos.clone(), col_pos: l.col_pos.clone(), values: l.values.clone(), shape: l.shape};
+    moved.col_pos[moved.row_pos[5]] = 3;
+    let mut x = rhs(n);
+    analysis.solve(&moved, &mut x);
+}
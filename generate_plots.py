@@ -18,13 +18,13 @@ print(df)
 
 # Select only numeric columns for averaging
 numeric_cols = ['cuBlas (µs)', 'cuSparse (µs)', 'gpuDense (µs)', 'gpuSparse (µs)', 
-                'Blas (µs)', 'cpuSparseParallel (µs)', 'cpuDenseParallel (µs)']
+                'Blas (µs)', 'cpuSparseParallel (µs)', 'cpuDenseParallel (µs)', 'cpuStrassen (µs)', 'cpuWinograd (µs)']
 # Group by matrix size (assuming they are paired)
 df_grouped = df.groupby('Size')[numeric_cols].mean().sort_index(ascending=False)
 
 # Extract x and y values for plotting
 sizes = df_grouped.index
-methods = ['cuBlas (µs)', 'cuSparse (µs)', 'gpuDense (µs)', 'gpuSparse (µs)', 'Blas (µs)', 'cpuSparseParallel (µs)', 'cpuDenseParallel (µs)']
+methods = ['cuBlas (µs)', 'cuSparse (µs)', 'gpuDense (µs)', 'gpuSparse (µs)', 'Blas (µs)', 'cpuSparseParallel (µs)', 'cpuDenseParallel (µs)', 'cpuStrassen (µs)', 'cpuWinograd (µs)']
 
 colors = {
     'cuBlas (µs)': '#1f77b4',  # Blau
@@ -33,7 +33,9 @@ colors = {
     'gpuSparse (µs)': '#d62728',  # Rot
     'Blas (µs)': '#999999',  # grau
     'cpuSparseParallel (µs)': '#8c564b',  # Braun
-    'cpuDenseParallel (µs)': '#000000'  # schwarz
+    'cpuDenseParallel (µs)': '#000000',  # schwarz
+    'cpuStrassen (µs)': '#9467bd',  # lila
+    'cpuWinograd (µs)': '#e377c2'  # rosa
 }
 
 plt.figure(figsize=(10, 10))
@@ -16,6 +16,11 @@ diag_pos = [-1, 0, 1]
 
 pfad = "matrix_instances/generated/toeplitz"
 
+# 2D Laplacian (5-Punkt-Stern) auf einem nx x nx Gitter, T (x) I + I (x) T mit T = tridiag(-1, 2, -1)
+sizes_laplacian = [20, 30]
+
+pfad_laplacian = "matrix_instances/generated/laplacian"
+
 
 
 
@@ -45,6 +50,17 @@ for n in sizes_toeplitz:
     fname = join(pfad, "toeplit_" + str(n) + "_A.mtx")
     
     write_mtx_coo(fname, A)
+
+
+Path(pfad_laplacian).mkdir(parents=True, exist_ok=True)
+
+for nx in sizes_laplacian:
+    T = sp.sparse.diags([-1, 2, -1], [-1, 0, 1], shape=(nx,nx))
+    A = sp.sparse.kronsum(T, T).tocoo()
+
+    fname = join(pfad_laplacian, "laplacian_" + str(nx) + "_A.mtx")
+
+    write_mtx_coo(fname, A)
     
 
 
@@ -18,6 +18,9 @@ struct DataEntry {
 @group(2) @binding(0) var<storage, read_write> idx: atomic<u32>;
 @group(2) @binding(1) var<storage, read_write> glob_data: array<DataEntry>;
 
+// Rows [rows.x, rows.y) of A handled by this dispatch, one chunk of the RowPartition
+@group(3) @binding(0) var<uniform> rows: vec2<u32>;
+
 
 // For algorithm see CPU sparse implementation
 
@@ -35,7 +38,10 @@ fn main(@builtin(local_invocation_id) id: vec3<u32>, @builtin(workgroup_id) wid:
     var nz_row_marker: array<u32, HIERDIESPALTEN> = array<u32, HIERDIESPALTEN>();   
 
 
-    let i = id.x + wid.x * HIERWGANZu;
+    let i = rows.x + id.x + wid.x * HIERWGANZu;
+    if i >= rows.y {
+        return;
+    }
 
     
         for (var col_pos_pos = a_row_pos[i]; col_pos_pos < a_row_pos[i+1]; col_pos_pos++) {
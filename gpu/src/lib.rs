@@ -29,19 +29,7 @@ pub struct WgpuTask {
 pub fn size_prediction(A: &CSR, B: &CSR) -> usize {
     let m = A.shape.0;
 
-    let mut nnzs = vec![];
-
-    // for (i, col_pos_pos) in A.row_pos.iter().enumerate() {
-    for i in 0..m {
-        let i0 = A.row_pos[i];
-        let i1 = A.row_pos[i+1];
-
-        let mut nnz_i = 0;
-        for k in A.col_pos[i0..i1].iter() {
-            nnz_i += B.get_row_nnz(*k);
-        }
-        nnzs.push(nnz_i);
-    }
+    let nnzs = matrix_base::product_row_flops(A, B);
 
 
     min(nnzs.iter().sum(), m * B.shape.1) 
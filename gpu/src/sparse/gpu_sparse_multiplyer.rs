@@ -2,10 +2,18 @@
 
 use std::cmp::min;
 
-use wgpu::{BindGroup, BindGroupLayout, ShaderModel, ShaderModule};
+use wgpu::{BindGroup, BindGroupLayout, Buffer, ShaderModel, ShaderModule};
+
+use matrix_base::{product_row_flops, RowPartition};
 
 use crate::*;
 
+
+// Target number of products per dispatch. The rows of A are cut into
+// chunks of about this many flops (same partition as the CPU kernels,
+// see matrix_base::partition), every chunk is one dispatch.
+const GPU_DISPATCH_FLOPS: usize = 1 << 24;
+
 // pub struct ShaderParam {
 //     pub wg_disp_n: u32 // How many workgroups are dispatched?
 // }
@@ -22,7 +30,9 @@ pub struct GPUSparseMultiplyer {
     pub buffer_res: Option<ResultBuffer>,
     pub buffer_res_staging: Option<ResultBuffer>,
     pub nnz_pred: usize,
-    pub n_disps: usize,
+    pub partition: RowPartition,
+    // Uniform buffer with the row range of the current dispatch
+    pub row_range: Option<(Buffer, BindGroupLayout, BindGroup)>,
     pub result: Option<(Vec<GlobDataEntry>, usize)>
 
 }
@@ -35,17 +45,18 @@ impl<'a> GPUSparseMultiplyer{
 
         let device = &wgpu_task.device;
 
-        let nnz_pred = size_prediction(&a, &b);
+        // Same as size_prediction, the flops per row are needed for the partition
+        let flops = product_row_flops(a, b);
+        let total_flops: usize = flops.iter().sum();
+        let nnz_pred = min(total_flops, a.shape.0 * b.shape.1);
+
+        // Work of a row is flops + 1, see RowPartition::for_product
+        let weights: Vec<usize> = flops.iter().map(|f| f + 1).collect();
+        let partition = RowPartition::balanced(&weights, total_flops.div_ceil(GPU_DISPATCH_FLOPS).max(1));
 
         // let nnz_pred =0;
 
         // Load Shader
-        let n_disps = (a.shape.0 as f64 / batch_size as f64).ceil() as usize;
-
-        // let n_disps = 1;
-
-
-        //println!("n_disps {}" , n_disps);
 
         let mut shader_code = match std::fs::read_to_string("gpu/shader/sparse_mul.wgsl") {
             Ok(s) => s,
@@ -72,7 +83,7 @@ impl<'a> GPUSparseMultiplyer{
         let a = GPUCSR::new(&a);
         let b = GPUCSR::new(&b);
         
-        GPUSparseMultiplyer{wgpu_task, a, b, shader, batch_size, bind_groups: None, bind_group_layouts: None, buffer_res: None, buffer_res_staging: None, nnz_pred, n_disps, result: None}
+        GPUSparseMultiplyer{wgpu_task, a, b, shader, batch_size, bind_groups: None, bind_group_layouts: None, buffer_res: None, buffer_res_staging: None, nnz_pred, partition, row_range: None, result: None}
     }
 
 
@@ -118,6 +129,27 @@ impl<'a> GPUSparseMultiplyer{
             entries: &bg_res_entries
         });
 
+        let row_range_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Row range"),
+            size: 2 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        let bg_rows_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind Group Layout: Row range"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                count: None,
+            }]
+        });
+        let bg_rows = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind Group Row range"),
+            layout: &bg_rows_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: row_range_buffer.as_entire_binding() }],
+        });
+
         let bg_a = buffer_a.create_bind_group(&device, &bg_a_layout);
         let bg_b = buffer_b.create_bind_group(&device, &bg_b_layout);
         let bg_res = buffer_res.create_bind_group(&device, &bg_res_layout);
@@ -127,6 +159,7 @@ impl<'a> GPUSparseMultiplyer{
         self.bind_group_layouts = Some((bg_a_layout, bg_b_layout, bg_res_layout));
         self.buffer_res_staging = Some(buffer_res_staging);
         self.buffer_res = Some(buffer_res);
+        self.row_range = Some((row_range_buffer, bg_rows_layout, bg_rows));
 
 
 
@@ -148,7 +181,7 @@ impl<'a> GPUSparseMultiplyer{
 
         let pipeline_layout = self.wgpu_task.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Compute Pipeline Layout"),
-            bind_group_layouts: &[&self.bind_group_layouts.as_ref().expect(msg).0, &self.bind_group_layouts.as_ref().expect(msg).1, &self.bind_group_layouts.as_ref().expect(msg).2],
+            bind_group_layouts: &[&self.bind_group_layouts.as_ref().expect(msg).0, &self.bind_group_layouts.as_ref().expect(msg).1, &self.bind_group_layouts.as_ref().expect(msg).2, &self.row_range.as_ref().expect(msg).1],
             push_constant_ranges: &[],
         });
 
@@ -163,23 +196,36 @@ impl<'a> GPUSparseMultiplyer{
 
 
         // Encode / Queue / Compute pass
+        // One dispatch per chunk of the partition. write_buffer takes effect
+        // at the next submit, so every dispatch sees its own row range.
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Compute Encoder") });
-
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Compute Pass"),
-                timestamp_writes: None,
-            });
+        let (row_range_buffer, _, bg_rows) = self.row_range.as_ref().expect(msg);
 
-            compute_pass.set_pipeline(&pipeline);
-            compute_pass.set_bind_group(0, &self.bind_groups.as_ref().expect(msg).0, &[]);
-            compute_pass.set_bind_group(1, &self.bind_groups.as_ref().expect(msg).1, &[]);
-            compute_pass.set_bind_group(2, &self.bind_groups.as_ref().expect(msg).2, &[]);
-            compute_pass.dispatch_workgroups(self.n_disps as u32,1,1); // Angepasste Dispatch-Parameter
-            // compute_pass.dispatch_workgroups(1,1,1); // Angepasste Dispatch-Parameter
+        for rows in self.partition.chunks() {
+            if rows.is_empty() {
+                continue;
+            }
+            self.wgpu_task.queue.write_buffer(row_range_buffer, 0, bytemuck::cast_slice(&[rows.start as u32, rows.end as u32]));
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Compute Encoder") });
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Compute Pass"),
+                    timestamp_writes: None,
+                });
+
+                compute_pass.set_pipeline(&pipeline);
+                compute_pass.set_bind_group(0, &self.bind_groups.as_ref().expect(msg).0, &[]);
+                compute_pass.set_bind_group(1, &self.bind_groups.as_ref().expect(msg).1, &[]);
+                compute_pass.set_bind_group(2, &self.bind_groups.as_ref().expect(msg).2, &[]);
+                compute_pass.set_bind_group(3, bg_rows, &[]);
+                compute_pass.dispatch_workgroups(rows.len().div_ceil(self.batch_size) as u32, 1, 1);
+            }
+            self.wgpu_task.queue.submit(Some(encoder.finish()));
         }
 
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Copy Encoder") });
+
         // let nnz_pred = size_prediction(&self.a, &self.b);
         let nnz_pred = self.nnz_pred;
 
@@ -0,0 +1,127 @@
+use crate::CSR;
+
+
+// Assembly of CSR matrices from other CSR matrices.
+// All functions keep the column indices within a row sorted,
+// as long as the inputs are sorted.
+
+
+
+// Kronecker product A ⊗ B, a (m_a*m_b, n_a*n_b)-matrix with blocks a_ij * B.
+// E.g. the 2D Laplacian on an n x n grid from the 1D one, T = tridiag(-1, 2, -1),
+// is kron(&I, &T) + kron(&T, &I). matrix_base has no CSR addition, the sum is
+// fakscpu's SparseElementwise: kron(&I, &T).sparse_add(1., &kron(&T, &I), 1.)
+pub fn kron(a: &CSR, b: &CSR) -> CSR {
+    let (ma, na) = a.shape;
+    let (mb, nb) = b.shape;
+    let shape = (ma*mb, na*nb);
+
+    let nnz = a.values.len() * b.values.len();
+    let mut row_pos: Vec<usize> = Vec::with_capacity(shape.0+1);
+    let mut col_pos: Vec<usize> = Vec::with_capacity(nnz);
+    let mut values: Vec<f64> = Vec::with_capacity(nnz);
+    row_pos.push(0);
+
+    // Row i_a*m_b + i_b of the result is row i_a of A where every
+    // entry a_{i_a j_a} is replaced by a_{i_a j_a} * B_{i_b *}
+    for ia in 0..ma {
+        for ib in 0..mb {
+            for (ja, xa) in a.row(ia) {
+                for (jb, xb) in b.row(ib) {
+                    col_pos.push(ja*nb + jb);
+                    values.push(xa * xb);
+                }
+            }
+            row_pos.push(values.len());
+        }
+    }
+
+    CSR{row_pos, col_pos, values, shape}
+}
+
+
+
+// Block matrix from a grid of blocks, like scipy.sparse.bmat.
+// None is a zero block. Every block row and block column needs
+// at least one Some(..) block, so that its size is known.
+// E.g. block(&[&[Some(&a), Some(&b)], &[None, Some(&d)]])
+pub fn block(blocks: &[&[Option<&CSR>]]) -> CSR {
+    let n_block_rows = blocks.len();
+    assert!(n_block_rows > 0, "Need at least one block row");
+    let n_block_cols = blocks[0].len();
+    assert!(blocks.iter().all(|r| r.len() == n_block_cols), "All block rows must have the same number of blocks");
+
+    // Heights of the block rows and widths of the block cols
+    let mut heights: Vec<Option<usize>> = vec![None; n_block_rows];
+    let mut widths: Vec<Option<usize>> = vec![None; n_block_cols];
+    for (bi, block_row) in blocks.iter().enumerate() {
+        for (bj, blk) in block_row.iter().enumerate() {
+            if let Some(blk) = blk {
+                check_block_size(&mut heights[bi], blk.shape.0, "row", bi);
+                check_block_size(&mut widths[bj], blk.shape.1, "column", bj);
+            }
+        }
+    }
+    let heights: Vec<usize> = heights.iter().enumerate()
+        .map(|(bi, h)| h.unwrap_or_else(|| panic!("Block row {} has only zero blocks, size unknown", bi))).collect();
+    let widths: Vec<usize> = widths.iter().enumerate()
+        .map(|(bj, w)| w.unwrap_or_else(|| panic!("Block column {} has only zero blocks, size unknown", bj))).collect();
+
+    // Column offset of each block column
+    let mut col_offsets = vec![0; n_block_cols];
+    for bj in 1..n_block_cols {
+        col_offsets[bj] = col_offsets[bj-1] + widths[bj-1];
+    }
+
+    let shape = (heights.iter().sum(), widths.iter().sum());
+    let nnz: usize = blocks.iter().flat_map(|r| r.iter()).flatten().map(|blk| blk.values.len()).sum();
+
+    let mut row_pos: Vec<usize> = Vec::with_capacity(shape.0+1);
+    let mut col_pos: Vec<usize> = Vec::with_capacity(nnz);
+    let mut values: Vec<f64> = Vec::with_capacity(nnz);
+    row_pos.push(0);
+
+    for (bi, block_row) in blocks.iter().enumerate() {
+        for i in 0..heights[bi] {
+            // Row i of all blocks in this block row, left to right
+            for (bj, blk) in block_row.iter().enumerate() {
+                if let Some(blk) = blk {
+                    for (j, x) in blk.row(i) {
+                        col_pos.push(col_offsets[bj] + j);
+                        values.push(*x);
+                    }
+                }
+            }
+            row_pos.push(values.len());
+        }
+    }
+
+    CSR{row_pos, col_pos, values, shape}
+}
+
+
+
+// Set the size of a block row/column on first sight, compare afterwards
+fn check_block_size(size: &mut Option<usize>, dim: usize, what: &str, idx: usize) {
+    match size {
+        Some(s) => assert_eq!(*s, dim, "Blocks in block {} {} have incompatible sizes", what, idx),
+        None => *size = Some(dim)
+    }
+}
+
+
+
+// [A_1 A_2 ... A_k], all with the same number of rows
+pub fn hstack(mats: &[&CSR]) -> CSR {
+    let row: Vec<Option<&CSR>> = mats.iter().map(|a| Some(*a)).collect();
+    block(&[&row])
+}
+
+
+
+// [A_1; A_2; ...; A_k], all with the same number of columns
+pub fn vstack(mats: &[&CSR]) -> CSR {
+    let rows: Vec<[Option<&CSR>; 1]> = mats.iter().map(|a| [Some(*a)]).collect();
+    let rows: Vec<&[Option<&CSR>]> = rows.iter().map(|r| &r[..]).collect();
+    block(&rows)
+}
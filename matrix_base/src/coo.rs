@@ -2,8 +2,46 @@ use std::io::{BufRead, BufReader};
 use std::fs::File;
 use std::path::Path;
 
+use rayon::prelude::*;
+
 use crate::Dense;
 
+// Symmetry type from the MatrixMarket banner line,
+// e.g. '%%MatrixMarket matrix coordinate real symmetric'
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MtxSymmetry {
+    General,
+    Symmetric,
+    SkewSymmetric,
+    Hermitian
+}
+
+
+// Only reads the banner. Files without one (e.g. the ones from
+// the generate_*.py scripts) are general.
+pub fn read_mtx_symmetry(fname: &Path) -> Result<MtxSymmetry, &str> {
+    let err_msg = "Error parsing file.";
+
+    let f = File::open(fname).map_err(|_| err_msg)?;
+    let mut f = BufReader::new(f);
+    let mut line = String::new();
+    f.read_line(&mut line).map_err(|_| err_msg)?;
+
+    let line = line.trim().to_lowercase();
+    if !line.starts_with("%%matrixmarket") {
+        return Ok(MtxSymmetry::General);
+    }
+
+    match line.split_whitespace().last() {
+        Some("symmetric") => Ok(MtxSymmetry::Symmetric),
+        Some("skew-symmetric") => Ok(MtxSymmetry::SkewSymmetric),
+        Some("hermitian") => Ok(MtxSymmetry::Hermitian),
+        _ => Ok(MtxSymmetry::General)
+    }
+}
+
+
+
 pub struct COO {
     pub data: Vec<(usize, usize, f64)>,
     pub shape: (usize, usize)
@@ -79,12 +117,29 @@ impl COO {
 
     pub fn to_dense(&self) -> Dense {
         let mut mat = Dense::new_zeros((self.shape.0, self.shape.1));
-        for (i,j,x) in &self.data {
-            mat.set(*i, *j, *x);
+        for (i,j,x) in self.iter() {
+            mat.set(i, j, *x);
         }
         mat
     }
 
+
+    // (i, j, a_ij) for all stored entries, in storage order.
+    // Same item type as CSR::iter, so algorithms can take either.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &f64)> {
+        self.data.iter().map(|(i, j, x)| (*i, *j, x))
+    }
+
+    // (i, j, a_ij) for all stored entries, mutable values
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut f64)> {
+        self.data.iter_mut().map(|(i, j, x)| (*i, *j, x))
+    }
+
+    // Parallel version of iter()
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = (usize, usize, &f64)> {
+        self.data.par_iter().map(|(i, j, x)| (*i, *j, x))
+    }
+
     
 
 
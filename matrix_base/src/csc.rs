@@ -0,0 +1,71 @@
+use crate::{CSR, Dense};
+use crate::csr::hash_pattern;
+
+
+// Compressed sparse column format, the transposed counterpart of CSR:
+// column j holds the rows row_pos[col_pos[j]..col_pos[j+1]]
+// with the values values[col_pos[j]..col_pos[j+1]].
+// Same fields as CSR with the roles of rows and columns swapped.
+// Column access is what left-looking factorizations (see fakscpu::direct) need.
+pub struct CSC {
+    pub col_pos: Vec<usize>,
+    pub row_pos: Vec<usize>,
+    pub values: Vec<f64>,
+    pub shape: (usize, usize)
+}
+
+
+impl CSC {
+    // The arrays of A in CSC are the ones of A^T in CSR,
+    // rows within a column are sorted (see CSR::transpose)
+    pub fn from_csr(csr: &CSR) -> Self {
+        let t = csr.transpose();
+        CSC{col_pos: t.row_pos, row_pos: t.col_pos, values: t.values, shape: csr.shape}
+    }
+
+
+    pub fn to_csr(&self) -> CSR {
+        let t = CSR{row_pos: self.col_pos.clone(), col_pos: self.row_pos.clone(), values: self.values.clone(), shape: (self.shape.1, self.shape.0)};
+        t.transpose()
+    }
+
+
+    pub fn to_dense(&self) -> Dense {
+        let mut mat = Dense::new_zeros(self.shape);
+        for (i, j, x) in self.iter() {
+            mat.set(i, j, *x);
+        }
+        mat
+    }
+
+
+    // See CSR::pattern_hash, the same as for the CSR of A^T with the shape of A
+    pub fn pattern_hash(&self) -> u64 {
+        hash_pattern(self.shape, &self.col_pos, &self.row_pos)
+    }
+
+
+    pub fn get_col_nnz(&self, j: usize) -> usize {
+        self.col_pos[j+1] - self.col_pos[j]
+    }
+
+
+    // Row indices and values of the j-th column as slices
+    pub fn col_slices(&self, j: usize) -> (&[usize], &[f64]) {
+        let range = self.col_pos[j]..self.col_pos[j+1];
+        (&self.row_pos[range.clone()], &self.values[range])
+    }
+
+
+    // (i, a_ij) for the non-zero entries of the j-th column
+    pub fn col(&self, j: usize) -> impl Iterator<Item = (usize, &f64)> {
+        let (rows, vals) = self.col_slices(j);
+        rows.iter().copied().zip(vals)
+    }
+
+
+    // (i, j, a_ij) for all non-zero entries, column by column
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &f64)> {
+        (0..self.shape.1).flat_map(move |j| self.col(j).map(move |(i, x)| (i, j, x)))
+    }
+}
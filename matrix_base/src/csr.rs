@@ -1,5 +1,7 @@
 
-use crate::{COO, Dense};
+use rayon::prelude::*;
+
+use crate::{COO, Dense, RowPartition, SparseVecView};
 
 
 // CSR format from "Two Fast Algorithms for Sparse Matrices: Multiplication and Permuted Transposition", Rice, Gustavson
@@ -65,6 +67,72 @@ impl CSR {
 
 
 
+    // n x n identity matrix
+    pub fn identity(n: usize) -> Self {
+        CSR{row_pos: (0..=n).collect(), col_pos: (0..n).collect(), values: vec![1.; n], shape: (n,n)}
+    }
+
+
+
+    // Sparse matrix from diagonals, like scipy.sparse.diags (see generate_toeplitz.py).
+    // diagonals[d] is placed on the diagonal with offset offsets[d]:
+    // 0 is the main diagonal, k > 0 the k-th upper and k < 0 the k-th lower one.
+    // A diagonal with a single value is broadcast over the whole diagonal,
+    // otherwise it must have exactly the length of the diagonal.
+    // E.g. the tridiagonal (1,-2,1) Toeplitz matrix:
+    // CSR::from_diagonals(&[&[1.], &[-2.], &[1.]], &[-1, 0, 1], (n,n))
+    pub fn from_diagonals(diagonals: &[&[f64]], offsets: &[isize], shape: (usize, usize)) -> Self {
+        assert_eq!(diagonals.len(), offsets.len(), "Number of diagonals and offsets do not match");
+
+        let (m, n) = shape;
+
+        // Sort the diagonals by offset, so that every row is
+        // filled from left to right
+        let mut order: Vec<usize> = (0..offsets.len()).collect();
+        order.sort_by_key(|&d| offsets[d]);
+        for w in order.windows(2) {
+            assert!(offsets[w[0]] != offsets[w[1]], "Offset {} appears more than once", offsets[w[0]]);
+        }
+
+        for &d in &order {
+            let k = offsets[d];
+            // The diagonal k starts at (max(0,-k), max(0,k))
+            let len = if k >= 0 {
+                n.saturating_sub(k as usize).min(m)
+            } else {
+                m.saturating_sub((-k) as usize).min(n)
+            };
+            assert!(diagonals[d].len() == 1 || diagonals[d].len() == len,
+                "Diagonal with offset {} has length {}, expected 1 or {}", k, diagonals[d].len(), len);
+        }
+
+        let mut row_pos: Vec<usize> = Vec::with_capacity(m+1);
+        let mut col_pos: Vec<usize> = vec![];
+        let mut values: Vec<f64> = vec![];
+        row_pos.push(0);
+
+        for i in 0..m {
+            for &d in &order {
+                let j = i as isize + offsets[d];
+                if j < 0 || j >= n as isize {
+                    continue;
+                }
+                let diag = diagonals[d];
+                // Position on the diagonal is the smaller of the two indices
+                let pos = i.min(j as usize);
+                let x = if diag.len() == 1 { diag[0] } else { diag[pos] };
+
+                col_pos.push(j as usize);
+                values.push(x);
+            }
+            row_pos.push(values.len());
+        }
+
+        CSR{row_pos, col_pos, values, shape}
+    }
+
+
+
     pub fn print(&self) {        
         println!("Sparse ({},{})-matrix in CSR format with {} entries", self.shape.0, self.shape.1, self.values.len());
         println!("Row Pos {:?}", self.row_pos);
@@ -79,20 +147,305 @@ impl CSR {
     }
 
     pub fn to_dense(&self) -> Dense {
-        let m = self.shape.0;
-        let n = self.shape.1;
-        // let mut mat = vec![vec![0.;n];m];
-        let mut mat = Dense::new_zeros((m,n));
+        let mut mat = Dense::new_zeros(self.shape);
 
-        for i in 0..m {
-            for col_pos_pos in self.row_pos[i]..self.row_pos[i+1] {
-                let k = self.col_pos[col_pos_pos];
-                mat.set(i,k, self.values[col_pos_pos]);
-            }
+        for (i, k, x) in self.iter() {
+            mat.set(i, k, *x);
         }
 
         mat
     }
 
+
+
+    // A^T, by counting the entries per column. The rows of A^T are
+    // filled in increasing row index of A, so they are always sorted.
+    pub fn transpose(&self) -> CSR {
+        let (m, n) = self.shape;
+        let mut row_pos = vec![0; n+1];
+        for j in &self.col_pos {
+            row_pos[j+1] += 1;
+        }
+        for j in 0..n {
+            row_pos[j+1] += row_pos[j];
+        }
+
+        let mut next = row_pos.clone();
+        let mut col_pos = vec![0; self.values.len()];
+        let mut values = vec![0.; self.values.len()];
+        for (i, j, x) in self.iter() {
+            col_pos[next[j]] = i;
+            values[next[j]] = *x;
+            next[j] += 1;
+        }
+
+        CSR{row_pos, col_pos, values, shape: (n, m)}
+    }
+
+
+
+    // Main diagonal a_ii, i < min(m, n). Missing entries are 0,
+    // duplicates are summed up.
+    pub fn diagonal(&self) -> Vec<f64> {
+        let mut diag = vec![0.; self.shape.0.min(self.shape.1)];
+        for (i, j, x) in self.iter() {
+            if i == j {
+                diag[i] += x;
+            }
+        }
+        diag
+    }
+
+
+
+    // B = P A Q^T with b_ij = a_{row_perm[i], col_perm[j]}, i.e. row i of B
+    // is row row_perm[i] of A. Symmetric reorderings (see ordering) use
+    // the same permutation for both. Rows of B are sorted.
+    pub fn permute(&self, row_perm: &[usize], col_perm: &[usize]) -> CSR {
+        let (m, n) = self.shape;
+        assert_eq!(row_perm.len(), m, "Row permutation does not match the matrix");
+        assert_eq!(col_perm.len(), n, "Column permutation does not match the matrix");
+
+        let mut col_inv = vec![usize::MAX; n];
+        for (j, &c) in col_perm.iter().enumerate() {
+            assert!(c < n && col_inv[c] == usize::MAX, "Not a permutation");
+            col_inv[c] = j;
+        }
+
+        let mut row_pos = Vec::with_capacity(m+1);
+        let mut col_pos = Vec::with_capacity(self.values.len());
+        let mut values = Vec::with_capacity(self.values.len());
+        let mut row = vec![];
+        let mut seen = vec![false; m];
+        row_pos.push(0);
+        for &r in row_perm {
+            assert!(r < m && !seen[r], "Not a permutation");
+            seen[r] = true;
+            row.clear();
+            row.extend(self.row(r).map(|(j, x)| (col_inv[j], *x)));
+            row.sort_by_key(|(j, _)| *j);
+            for (j, x) in &row {
+                col_pos.push(*j);
+                values.push(*x);
+            }
+            row_pos.push(col_pos.len());
+        }
+
+        CSR{row_pos, col_pos, values, shape: self.shape}
+    }
+
+
+
+    // max |i - j| over the stored entries
+    pub fn bandwidth(&self) -> usize {
+        self.iter().map(|(i, j, _)| i.abs_diff(j)).max().unwrap_or(0)
+    }
+
+
+    // Hash of the shape and the pattern (row_pos, col_pos), to check cheaply that a
+    // matrix still has the pattern an analysis or a plan was computed for
+    pub fn pattern_hash(&self) -> u64 {
+        hash_pattern(self.shape, &self.row_pos, &self.col_pos)
+    }
+
+
+    // Size of the (lower) envelope: \sum_i i - min{j <= i : a_ij != 0},
+    // the fill of a profile/skyline Cholesky factor. Rows without an
+    // entry left of the diagonal add nothing.
+    pub fn profile(&self) -> usize {
+        self.rows()
+            .map(|(i, cols, _)| cols.iter().filter(|&&j| j <= i).map(|&j| i - j).max().unwrap_or(0))
+            .sum()
+    }
+
+
+
+    // ** Iterators over the non-zero entries **
+    // All of them only walk over row_pos[0..=shape.0], so the index
+    // arithmetic row_pos[i]..row_pos[i+1] lives here and nowhere else.
+
+    // Column indices and values of the i-th row as slices
+    pub fn row_slices(&self, i: usize) -> (&[usize], &[f64]) {
+        let range = self.row_pos[i]..self.row_pos[i+1];
+        (&self.col_pos[range.clone()], &self.values[range])
+    }
+
+    // Same as row_slices, but the values can be modified
+    pub fn row_slices_mut(&mut self, i: usize) -> (&[usize], &mut [f64]) {
+        let range = self.row_pos[i]..self.row_pos[i+1];
+        (&self.col_pos[range.clone()], &mut self.values[range])
+    }
+
+    // Borrow the i-th row as sparse vector of length shape.1.
+    // Requires sorted column indices within the row, which holds for
+    // matrices built by from_coo from sorted COO data and for all products.
+    pub fn row_vec(&self, i: usize) -> SparseVecView<'_> {
+        let (indices, values) = self.row_slices(i);
+        SparseVecView{indices, values, len: self.shape.1}
+    }
+
+    // (j, a_ij) for all non-zero entries of the i-th row
+    pub fn row(&self, i: usize) -> impl Iterator<Item = (usize, &f64)> {
+        let (cols, vals) = self.row_slices(i);
+        cols.iter().copied().zip(vals.iter())
+    }
+
+    // (j, a_ij) for all non-zero entries of the i-th row, mutable values
+    pub fn row_mut(&mut self, i: usize) -> impl Iterator<Item = (usize, &mut f64)> {
+        let (cols, vals) = self.row_slices_mut(i);
+        cols.iter().copied().zip(vals.iter_mut())
+    }
+
+    // (i, j, a_ij) for all non-zero entries, row by row
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &f64)> {
+        (0..self.shape.0).flat_map(move |i| self.row(i).map(move |(j, x)| (i, j, x)))
+    }
+
+    // (i, j, a_ij) for all non-zero entries, row by row, mutable values
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut f64)> {
+        self.rows_mut().flat_map(|(i, cols, vals)| cols.iter().copied().zip(vals.iter_mut()).map(move |(j, x)| (i, j, x)))
+    }
+
+    // (i, col indices, values) for every row, including empty rows
+    pub fn rows(&self) -> impl Iterator<Item = (usize, &[usize], &[f64])> {
+        (0..self.shape.0).map(move |i| {
+            let (cols, vals) = self.row_slices(i);
+            (i, cols, vals)
+        })
+    }
+
+    // (i, col indices, values) for every row, mutable values.
+    // The value slices are disjoint, so they can be handed out all at once.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = (usize, &[usize], &mut [f64])> {
+        RowsMut::new(self).map(|(i, cols, vals)| (i, &*cols, vals))
+    }
+
+    // Parallel version of rows()
+    pub fn par_rows(&self) -> impl IndexedParallelIterator<Item = (usize, &[usize], &[f64])> {
+        (0..self.shape.0).into_par_iter().map(move |i| {
+            let (cols, vals) = self.row_slices(i);
+            (i, cols, vals)
+        })
+    }
+
+    // Parallel version of rows_mut()
+    pub fn par_rows_mut(&mut self) -> impl ParallelIterator<Item = (usize, &[usize], &mut [f64])> {
+        self.par_rows_pattern_mut().map(|(i, cols, vals)| (i, &*cols, vals))
+    }
+
+    // Like par_rows_mut, but the column indices can be written as well.
+    // For kernels that first compute row_pos and then fill a preallocated
+    // col_pos / values row by row. The rows are split recursively into halves
+    // with disjoint slices, so there is no allocation per row.
+    pub fn par_rows_pattern_mut(&mut self) -> impl ParallelIterator<Item = (usize, &mut [usize], &mut [f64])> {
+        rayon::iter::split(RowsMut::new(self), RowsMut::split).flat_map_iter(|rows| rows)
+    }
+
+
+    // Same as par_rows, but rayon splits along the chunks of the partition
+    // (e.g. chunks of equal flops, see partition.rs) instead of the row count.
+    // The rows of one chunk are processed by one task, in order.
+    pub fn par_rows_by<'a>(&'a self, partition: &'a RowPartition) -> impl ParallelIterator<Item = (usize, &'a [usize], &'a [f64])> {
+        assert_eq!(partition.n_rows(), self.shape.0, "Partition does not match the number of rows");
+        (0..partition.n_chunks()).into_par_iter().flat_map_iter(move |c| partition.chunk(c).map(move |i| {
+            let (cols, vals) = self.row_slices(i);
+            (i, cols, vals)
+        }))
+    }
+
+
+    // Same as par_rows_pattern_mut, split along the chunks of the partition
+    pub fn par_rows_pattern_mut_by<'a>(&'a mut self, partition: &'a RowPartition) -> impl ParallelIterator<Item = (usize, &'a mut [usize], &'a mut [f64])> {
+        assert_eq!(partition.n_rows(), self.shape.0, "Partition does not match the number of rows");
+        let chunks = ChunkedRowsMut{rows: RowsMut::new(self), bounds: &partition.bounds};
+        rayon::iter::split(chunks, ChunkedRowsMut::split).flat_map_iter(|chunks| chunks.rows)
+    }
+
+}
+
+
+
+// A contiguous block of rows with the corresponding parts of col_pos and values
+struct RowsMut<'a> {
+    rows: std::ops::Range<usize>,
+    row_pos: &'a [usize],
+    cols: &'a mut [usize],
+    vals: &'a mut [f64]
+}
+
+
+impl<'a> RowsMut<'a> {
+    fn new(csr: &'a mut CSR) -> Self {
+        let m = csr.shape.0;
+        let nnz = csr.row_pos[m];
+        RowsMut{rows: 0..m, row_pos: &csr.row_pos, cols: &mut csr.col_pos[..nnz], vals: &mut csr.values[..nnz]}
+    }
+
+    // Split in the middle row, for rayon::iter::split
+    fn split(self) -> (Self, Option<Self>) {
+        if self.rows.len() < 2 {
+            return (self, None);
+        }
+        let mid = self.rows.start + self.rows.len() / 2;
+        let (left, right) = self.split_at_row(mid);
+        (left, Some(right))
+    }
+
+    // Rows start..row and row..end
+    fn split_at_row(self, row: usize) -> (Self, Self) {
+        let at = self.row_pos[row] - self.row_pos[self.rows.start];
+        let (cols_l, cols_r) = self.cols.split_at_mut(at);
+        let (vals_l, vals_r) = self.vals.split_at_mut(at);
+        (RowsMut{rows: self.rows.start..row, row_pos: self.row_pos, cols: cols_l, vals: vals_l},
+         RowsMut{rows: row..self.rows.end, row_pos: self.row_pos, cols: cols_r, vals: vals_r})
+    }
 }
 
+
+
+// Rows of the chunks bounds[0]..bounds[1], ..., of a RowPartition.
+// Splits at the middle chunk boundary, never inside a chunk.
+struct ChunkedRowsMut<'a, 'b> {
+    rows: RowsMut<'a>,
+    bounds: &'b [usize]
+}
+
+
+impl<'a, 'b> ChunkedRowsMut<'a, 'b> {
+    fn split(self) -> (Self, Option<Self>) {
+        if self.bounds.len() <= 2 {
+            return (self, None);
+        }
+        let mid = self.bounds.len() / 2;
+        let (left, right) = self.rows.split_at_row(self.bounds[mid]);
+        (ChunkedRowsMut{rows: left, bounds: &self.bounds[..=mid]},
+         Some(ChunkedRowsMut{rows: right, bounds: &self.bounds[mid..]}))
+    }
+}
+
+
+impl<'a> Iterator for RowsMut<'a> {
+    type Item = (usize, &'a mut [usize], &'a mut [f64]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = self.rows.next()?;
+        let nnz = self.row_pos[i+1] - self.row_pos[i];
+        let (cols, cols_rest) = std::mem::take(&mut self.cols).split_at_mut(nnz);
+        let (vals, vals_rest) = std::mem::take(&mut self.vals).split_at_mut(nnz);
+        self.cols = cols_rest;
+        self.vals = vals_rest;
+        Some((i, cols, vals))
+    }
+}
+
+
+
+// FxHash-style mixing (rotate, xor, multiply) of all indices. Not resistant
+// to deliberate collisions, but any change of a single index changes the hash.
+pub(crate) fn hash_pattern(shape: (usize, usize), pos: &[usize], idx: &[usize]) -> u64 {
+    const K: u64 = 0x517c_c1b7_2722_0a95;
+    let mix = |h: u64, x: usize| (h.rotate_left(5) ^ x as u64).wrapping_mul(K);
+    let h = mix(mix(0, shape.0), shape.1);
+    let h = pos.iter().fold(mix(h, pos.len()), |h, x| mix(h, *x));
+    idx.iter().fold(mix(h, idx.len()), |h, x| mix(h, *x))
+}
@@ -1,3 +1,4 @@
+use rayon::prelude::*;
 
 pub struct Dense {
     pub data: Vec<f64>,
@@ -18,6 +19,45 @@ impl Dense {
         self.data[self.shape.1 *i + j] = x;
     }
 
+    // Row-major storage, so a row is a contiguous slice
+    pub fn row(&self, i: usize) -> &[f64] {
+        &self.data[self.shape.1 * i..self.shape.1 * (i+1)]
+    }
+
+    pub fn row_mut(&mut self, i: usize) -> &mut [f64] {
+        &mut self.data[self.shape.1 * i..self.shape.1 * (i+1)]
+    }
+
+    // (i, row_i) for all rows
+    // max(1) only guards chunks() against 0 columns, the row count is taken from shape
+    pub fn rows(&self) -> impl Iterator<Item = (usize, &[f64])> {
+        self.data.chunks(self.shape.1.max(1)).take(self.shape.0).enumerate()
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = (usize, &mut [f64])> {
+        self.data.chunks_mut(self.shape.1.max(1)).take(self.shape.0).enumerate()
+    }
+
+    pub fn par_rows(&self) -> impl IndexedParallelIterator<Item = (usize, &[f64])> {
+        self.data.par_chunks(self.shape.1.max(1)).take(self.shape.0).enumerate()
+    }
+
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = (usize, &mut [f64])> {
+        self.data.par_chunks_mut(self.shape.1.max(1)).take(self.shape.0).enumerate()
+    }
+
+    // (i, j, a_ij) for all entries, including zeros.
+    // Same item type as CSR::iter and COO::iter.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &f64)> {
+        let n = self.shape.1.max(1);
+        self.data.iter().enumerate().map(move |(idx, x)| (idx / n, idx % n, x))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut f64)> {
+        let n = self.shape.1.max(1);
+        self.data.iter_mut().enumerate().map(move |(idx, x)| (idx / n, idx % n, x))
+    }
+
     pub fn print(&self) {
         println!("Dense ({},{})-matrix", self.shape.0, self.shape.1);
         for i in 0..self.shape.0 {
@@ -2,7 +2,28 @@ pub mod dense;
 pub use dense::Dense;
 
 pub mod coo;
-pub use coo::COO;
+pub use coo::{COO, MtxSymmetry};
 
 pub mod csr;
-pub use csr::CSR;
\ No newline at end of file
+pub use csr::CSR;
+
+pub mod csc;
+pub use csc::CSC;
+
+pub mod sym_csr;
+pub use sym_csr::SymCSR;
+
+pub mod sparse_vec;
+pub use sparse_vec::{SparseVec, SparseVecView};
+
+pub mod construct;
+pub use construct::{kron, block, hstack, vstack};
+
+pub mod partition;
+pub use partition::{RowPartition, Imbalance, product_row_flops};
+
+pub mod ordering;
+pub use ordering::{rcm, amd, nested_dissection, OrderingReport};
+
+pub mod operator;
+pub use operator::{LinearOperator, MatrixFree, Sum, Product, Scaled, Transposed};
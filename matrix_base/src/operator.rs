@@ -0,0 +1,376 @@
+use rayon::prelude::*;
+
+use crate::{CSC, CSR, Dense, SymCSR};
+
+
+// Linear operators A: R^n -> R^m for matrix-free algorithms (Krylov
+// solvers, eigensolvers), which only need products with A and A^T.
+//
+// Implemented for the matrix types (Dense, CSR, CSC, SymCSR), closures
+// (MatrixFree) and the compositions Sum, Product, Scaled and Transposed.
+// References to operators are operators as well, so compositions can own
+// their parts or borrow them: Sum::new(&a, Scaled::new(2., &b)).
+//
+// Dimension mismatches panic, like for the matrix products.
+pub trait LinearOperator {
+    // (m, n) for A: R^n -> R^m
+    fn shape(&self) -> (usize, usize);
+
+    // y = A x, y is overwritten
+    fn apply(&self, x: &[f64], y: &mut [f64]);
+
+    // y = A^T x, y is overwritten
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]);
+
+    // Y = A X for a block of vectors (the columns of X).
+    // Column by column by default, CSR multiplies all columns per row.
+    fn apply_block(&self, x: &Dense, y: &mut Dense) {
+        let (m, n) = self.shape();
+        assert_eq!(x.shape.0, n, "Operator and block dimensions do not match");
+        assert_eq!(y.shape, (m, x.shape.1), "Operator and block dimensions do not match");
+        let mut x_c = vec![0.; n];
+        let mut y_c = vec![0.; m];
+        for c in 0..x.shape.1 {
+            for (i, row) in x.rows() {
+                x_c[i] = row[c];
+            }
+            self.apply(&x_c, &mut y_c);
+            for (i, row) in y.rows_mut() {
+                row[c] = y_c[i];
+            }
+        }
+    }
+}
+
+
+
+// Minimal number of rows per rayon task of the products, a row is only a few flops
+const APPLY_MIN_ROWS: usize = 1024;
+
+
+
+fn check_apply(shape: (usize, usize), x: &[f64], y: &[f64]) {
+    assert_eq!(x.len(), shape.1, "Operator and vector dimensions do not match");
+    assert_eq!(y.len(), shape.0, "Operator and vector dimensions do not match");
+}
+
+
+fn check_apply_transpose(shape: (usize, usize), x: &[f64], y: &[f64]) {
+    assert_eq!(x.len(), shape.0, "Operator and vector dimensions do not match");
+    assert_eq!(y.len(), shape.1, "Operator and vector dimensions do not match");
+}
+
+
+
+impl<T: LinearOperator + ?Sized> LinearOperator for &T {
+    fn shape(&self) -> (usize, usize) {
+        (**self).shape()
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        (**self).apply(x, y)
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        (**self).apply_transpose(x, y)
+    }
+
+    fn apply_block(&self, x: &Dense, y: &mut Dense) {
+        (**self).apply_block(x, y)
+    }
+}
+
+
+
+impl LinearOperator for Dense {
+    fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        check_apply(self.shape, x, y);
+        y.par_iter_mut().zip(self.par_rows()).for_each(|(y_i, (_, row))| {
+            *y_i = row.iter().zip(x).map(|(a, b)| a * b).sum();
+        });
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        check_apply_transpose(self.shape, x, y);
+        y.fill(0.);
+        for ((_, row), x_i) in self.rows().zip(x) {
+            y.iter_mut().zip(row).for_each(|(y_j, a_ij)| *y_j += a_ij * x_i);
+        }
+    }
+}
+
+
+
+// Rows in parallel. A^T x scatters the rows of A, serially.
+impl LinearOperator for CSR {
+    fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        check_apply(self.shape, x, y);
+        y.par_iter_mut().zip(self.par_rows()).with_min_len(APPLY_MIN_ROWS).for_each(|(y_i, (_, cols, vals))| {
+            *y_i = cols.iter().zip(vals).map(|(j, a_ij)| a_ij * x[*j]).sum();
+        });
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        check_apply_transpose(self.shape, x, y);
+        y.fill(0.);
+        for ((_, cols, vals), x_i) in self.rows().zip(x) {
+            for (j, a_ij) in cols.iter().zip(vals) {
+                y[*j] += a_ij * x_i;
+            }
+        }
+    }
+
+    fn apply_block(&self, x: &Dense, y: &mut Dense) {
+        assert_eq!(x.shape.0, self.shape.1, "Operator and block dimensions do not match");
+        assert_eq!(y.shape, (self.shape.0, x.shape.1), "Operator and block dimensions do not match");
+        y.par_rows_mut().zip(self.par_rows()).for_each(|((_, y_row), (_, cols, vals))| {
+            y_row.fill(0.);
+            for (k, a_ik) in cols.iter().zip(vals) {
+                y_row.iter_mut().zip(x.row(*k)).for_each(|(y_ic, x_kc)| *y_ic += a_ik * x_kc);
+            }
+        });
+    }
+}
+
+
+
+// The transposed roles of CSR: A^T x in parallel over the columns
+impl LinearOperator for CSC {
+    fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        check_apply(self.shape, x, y);
+        y.fill(0.);
+        for (j, x_j) in x.iter().enumerate() {
+            for (i, a_ij) in self.col(j) {
+                y[i] += a_ij * x_j;
+            }
+        }
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        check_apply_transpose(self.shape, x, y);
+        y.par_iter_mut().enumerate().with_min_len(APPLY_MIN_ROWS).for_each(|(j, y_j)| {
+            *y_j = self.col(j).map(|(i, a_ij)| a_ij * x[i]).sum();
+        });
+    }
+}
+
+
+
+// Serial, every stored a_ij (i < j) is used for y_i and y_j
+impl LinearOperator for SymCSR {
+    fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        check_apply(self.shape, x, y);
+        y.fill(0.);
+        for (i, j, a_ij) in self.iter() {
+            y[i] += a_ij * x[j];
+            if i != j {
+                y[j] += a_ij * x[i];
+            }
+        }
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        self.apply(x, y)
+    }
+}
+
+
+
+// Operator given by closures y = A x and y = A^T x,
+// the closures get x and y with the checked lengths
+pub struct MatrixFree<F, G> {
+    shape: (usize, usize),
+    apply: F,
+    apply_transpose: G
+}
+
+
+impl<F, G> MatrixFree<F, G>
+where
+    F: Fn(&[f64], &mut [f64]),
+    G: Fn(&[f64], &mut [f64])
+{
+    pub fn new(shape: (usize, usize), apply: F, apply_transpose: G) -> Self {
+        MatrixFree{shape, apply, apply_transpose}
+    }
+}
+
+
+impl<F: Fn(&[f64], &mut [f64]) + Clone> MatrixFree<F, F> {
+    // A = A^T, the same closure for both products
+    pub fn symmetric(n: usize, apply: F) -> Self {
+        MatrixFree{shape: (n, n), apply: apply.clone(), apply_transpose: apply}
+    }
+}
+
+
+impl<F, G> LinearOperator for MatrixFree<F, G>
+where
+    F: Fn(&[f64], &mut [f64]),
+    G: Fn(&[f64], &mut [f64])
+{
+    fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        check_apply(self.shape, x, y);
+        (self.apply)(x, y)
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        check_apply_transpose(self.shape, x, y);
+        (self.apply_transpose)(x, y)
+    }
+}
+
+
+
+// A + B
+pub struct Sum<A, B> {
+    a: A,
+    b: B
+}
+
+
+impl<A: LinearOperator, B: LinearOperator> Sum<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        assert_eq!(a.shape(), b.shape(), "Operator dimensions do not match for addition");
+        Sum{a, b}
+    }
+}
+
+
+impl<A: LinearOperator, B: LinearOperator> LinearOperator for Sum<A, B> {
+    fn shape(&self) -> (usize, usize) {
+        self.a.shape()
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        let mut tmp = vec![0.; y.len()];
+        self.a.apply(x, y);
+        self.b.apply(x, &mut tmp);
+        y.iter_mut().zip(&tmp).for_each(|(y_i, t_i)| *y_i += t_i);
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        let mut tmp = vec![0.; y.len()];
+        self.a.apply_transpose(x, y);
+        self.b.apply_transpose(x, &mut tmp);
+        y.iter_mut().zip(&tmp).for_each(|(y_i, t_i)| *y_i += t_i);
+    }
+}
+
+
+
+// A B, applied as A (B x)
+pub struct Product<A, B> {
+    a: A,
+    b: B
+}
+
+
+impl<A: LinearOperator, B: LinearOperator> Product<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        assert_eq!(a.shape().1, b.shape().0, "Operator dimensions do not match for multiplication");
+        Product{a, b}
+    }
+}
+
+
+impl<A: LinearOperator, B: LinearOperator> LinearOperator for Product<A, B> {
+    fn shape(&self) -> (usize, usize) {
+        (self.a.shape().0, self.b.shape().1)
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        let mut tmp = vec![0.; self.b.shape().0];
+        self.b.apply(x, &mut tmp);
+        self.a.apply(&tmp, y);
+    }
+
+    // (A B)^T x = B^T (A^T x)
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        let mut tmp = vec![0.; self.a.shape().1];
+        self.a.apply_transpose(x, &mut tmp);
+        self.b.apply_transpose(&tmp, y);
+    }
+}
+
+
+
+// alpha A
+pub struct Scaled<A> {
+    alpha: f64,
+    a: A
+}
+
+
+impl<A: LinearOperator> Scaled<A> {
+    pub fn new(alpha: f64, a: A) -> Self {
+        Scaled{alpha, a}
+    }
+}
+
+
+impl<A: LinearOperator> LinearOperator for Scaled<A> {
+    fn shape(&self) -> (usize, usize) {
+        self.a.shape()
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        self.a.apply(x, y);
+        y.iter_mut().for_each(|y_i| *y_i *= self.alpha);
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        self.a.apply_transpose(x, y);
+        y.iter_mut().for_each(|y_i| *y_i *= self.alpha);
+    }
+}
+
+
+
+// A^T, without forming it
+pub struct Transposed<A> {
+    a: A
+}
+
+
+impl<A: LinearOperator> Transposed<A> {
+    pub fn new(a: A) -> Self {
+        Transposed{a}
+    }
+}
+
+
+impl<A: LinearOperator> LinearOperator for Transposed<A> {
+    fn shape(&self) -> (usize, usize) {
+        let (m, n) = self.a.shape();
+        (n, m)
+    }
+
+    fn apply(&self, x: &[f64], y: &mut [f64]) {
+        self.a.apply_transpose(x, y)
+    }
+
+    fn apply_transpose(&self, x: &[f64], y: &mut [f64]) {
+        self.a.apply(x, y)
+    }
+}
@@ -0,0 +1,396 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use crate::CSR;
+
+
+// Symmetric orderings over the graph of a square matrix: the vertices are
+// the rows, with an edge i - j for every stored a_ij or a_ji, i != j.
+// All orderings return perm with perm[k] = the row/column of A that is
+// placed at position k, so the reordered matrix is a.permute(&perm, &perm)
+// and perm can be used as column ordering of fakscpu::direct::LuSymbolic.
+//
+// - rcm: small bandwidth and profile (locality of SpMV/SpGEMM, banded solvers)
+// - amd, nested_dissection: small fill of a Cholesky/LU factorization
+
+
+
+// Adjacency lists of the symmetrized pattern, without the diagonal
+struct Graph {
+    adj_pos: Vec<usize>,
+    adj: Vec<usize>
+}
+
+
+impl Graph {
+    fn new(a: &CSR) -> Self {
+        assert_eq!(a.shape.0, a.shape.1, "Matrix is not square");
+        let n = a.shape.0;
+
+        let mut adj_pos = vec![0; n+1];
+        for (i, j, _) in a.iter() {
+            if i != j {
+                adj_pos[i+1] += 1;
+                adj_pos[j+1] += 1;
+            }
+        }
+        for i in 0..n {
+            adj_pos[i+1] += adj_pos[i];
+        }
+        let mut next = adj_pos.clone();
+        let mut adj = vec![0; adj_pos[n]];
+        for (i, j, _) in a.iter() {
+            if i != j {
+                adj[next[i]] = j;
+                next[i] += 1;
+                adj[next[j]] = i;
+                next[j] += 1;
+            }
+        }
+
+        // Sort and remove the duplicates of symmetric entries
+        let mut compact = Vec::with_capacity(adj.len());
+        let mut compact_pos = Vec::with_capacity(n+1);
+        compact_pos.push(0);
+        for i in 0..n {
+            let list = &mut adj[adj_pos[i]..adj_pos[i+1]];
+            list.sort_unstable();
+            let start = compact.len();
+            for &j in list.iter() {
+                if compact.len() == start || *compact.last().unwrap() != j {
+                    compact.push(j);
+                }
+            }
+            compact_pos.push(compact.len());
+        }
+
+        Graph{adj_pos: compact_pos, adj: compact}
+    }
+
+
+    fn n(&self) -> usize {
+        self.adj_pos.len() - 1
+    }
+
+
+    fn neighbors(&self, i: usize) -> &[usize] {
+        &self.adj[self.adj_pos[i]..self.adj_pos[i+1]]
+    }
+
+
+    fn degree(&self, i: usize) -> usize {
+        self.adj_pos[i+1] - self.adj_pos[i]
+    }
+
+
+    // Breadth-first level structure rooted in root, restricted to the
+    // vertices v with part[v] == label. Returns the vertices in BFS order and
+    // the level bounds (level l holds order[level_pos[l]..level_pos[l+1]]).
+    // Neighbors are visited by increasing degree, as Cuthill-McKee does.
+    fn levels(&self, root: usize, part: &[usize], label: usize, visited: &mut [bool]) -> (Vec<usize>, Vec<usize>) {
+        let mut order = vec![root];
+        let mut level_pos = vec![0, 1];
+        visited[root] = true;
+        let mut next = vec![];
+
+        while *level_pos.last().unwrap() > level_pos[level_pos.len()-2] {
+            let (start, end) = (level_pos[level_pos.len()-2], level_pos[level_pos.len()-1]);
+            for q in start..end {
+                let v = order[q];
+                next.clear();
+                next.extend(self.neighbors(v).iter().copied().filter(|&w| part[w] == label && !visited[w]));
+                next.sort_by_key(|&w| self.degree(w));
+                for &w in &next {
+                    visited[w] = true;
+                    order.push(w);
+                }
+            }
+            level_pos.push(order.len());
+        }
+        level_pos.pop();
+
+        for &v in &order {
+            visited[v] = false;
+        }
+        (order, level_pos)
+    }
+
+
+    // Pseudo-peripheral vertex in the component of start (George and Liu):
+    // restart from a vertex of minimal degree in the last level as long as
+    // the number of levels grows.
+    // "An Implementation of a Pseudoperipheral Node Finder", George, Liu
+    // https://doi.org/10.1145/355841.355845
+    fn pseudo_peripheral(&self, start: usize, part: &[usize], label: usize, visited: &mut [bool]) -> usize {
+        let mut root = start;
+        let (mut order, mut level_pos) = self.levels(root, part, label, visited);
+        loop {
+            let last = &order[level_pos[level_pos.len()-2]..];
+            let candidate = *last.iter().min_by_key(|&&v| self.degree(v)).unwrap();
+            let (c_order, c_level_pos) = self.levels(candidate, part, label, visited);
+            if c_level_pos.len() <= level_pos.len() {
+                return root;
+            }
+            root = candidate;
+            order = c_order;
+            level_pos = c_level_pos;
+        }
+    }
+}
+
+
+
+// Reverse Cuthill-McKee: BFS from a pseudo-peripheral vertex of every
+// connected component, neighbors by increasing degree, then reversed.
+// "Reducing the bandwidth of sparse symmetric matrices", Cuthill, McKee
+// https://doi.org/10.1145/800195.805928
+pub fn rcm(a: &CSR) -> Vec<usize> {
+    let g = Graph::new(a);
+    let n = g.n();
+    let part = vec![0; n];
+    let mut visited = vec![false; n];
+    let mut done = vec![false; n];
+    let mut perm = Vec::with_capacity(n);
+
+    // Components are started from their vertex of minimal degree
+    let mut by_degree: Vec<usize> = (0..n).collect();
+    by_degree.sort_by_key(|&v| g.degree(v));
+    for &start in &by_degree {
+        if done[start] {
+            continue;
+        }
+        let root = g.pseudo_peripheral(start, &part, 0, &mut visited);
+        let (order, _) = g.levels(root, &part, 0, &mut visited);
+        for &v in &order {
+            done[v] = true;
+        }
+        perm.extend(order);
+    }
+
+    perm.reverse();
+    perm
+}
+
+
+
+// Approximate minimum degree on the quotient graph, see
+// "An Approximate Minimum Degree Ordering Algorithm", Amestoy, Davis, Duff
+// https://doi.org/10.1137/S0895479894278952
+//
+// Eliminating the variable p turns it into the element p with the variables
+// L_p = A_p + \bigcup_{e \in E_p} L_e - p (the pattern of column p of L),
+// the elements E_p are absorbed into p. Instead of the exact external degree
+// every i in L_p gets the bound of the paper
+// d_i = min(n_left, d_i + |L_p - i|, |A_i - i| + |L_p - i| + \sum_{e \in E_i - p} |L_e - L_p|).
+// Supervariables, mass elimination and aggressive absorption are left out.
+pub fn amd(a: &CSR) -> Vec<usize> {
+    let g = Graph::new(a);
+    let n = g.n();
+
+    // Variable neighbors A_i and adjacent elements E_i of every variable,
+    // variables L_e of every element
+    let mut var_adj: Vec<Vec<usize>> = (0..n).map(|i| g.neighbors(i).to_vec()).collect();
+    let mut elem_adj: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut elem_vars: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut absorbed = vec![false; n];
+    let mut eliminated = vec![false; n];
+
+    let mut degree: Vec<usize> = (0..n).map(|i| g.degree(i)).collect();
+    let mut heap: BinaryHeap<Reverse<(usize, usize)>> = (0..n).map(|i| Reverse((degree[i], i))).collect();
+
+    // mark[i] == p: i is in L_p. w[e]: |L_e - L_p| while eliminating p
+    let mut mark = vec![usize::MAX; n];
+    let mut w: Vec<isize> = vec![-1; n];
+    let mut perm = Vec::with_capacity(n);
+
+    while let Some(Reverse((d, p))) = heap.pop() {
+        // Entries of the heap become stale when the degree is updated
+        if eliminated[p] || d != degree[p] {
+            continue;
+        }
+        eliminated[p] = true;
+        perm.push(p);
+        let n_left = n - perm.len();
+
+        // L_p
+        mark[p] = p;
+        let mut lp = vec![];
+        for &i in &var_adj[p] {
+            if mark[i] != p {
+                mark[i] = p;
+                lp.push(i);
+            }
+        }
+        for &e in &elem_adj[p] {
+            for &i in &elem_vars[e] {
+                if mark[i] != p && !eliminated[i] {
+                    mark[i] = p;
+                    lp.push(i);
+                }
+            }
+            absorbed[e] = true;
+            elem_vars[e] = vec![];
+        }
+        var_adj[p] = vec![];
+        elem_adj[p] = vec![];
+
+        // |L_e - L_p| for the elements adjacent to L_p
+        for &i in &lp {
+            for &e in &elem_adj[i] {
+                if absorbed[e] {
+                    continue;
+                }
+                if w[e] < 0 {
+                    w[e] = elem_vars[e].len() as isize;
+                }
+                w[e] -= 1;
+            }
+        }
+
+        for &i in &lp {
+            // Absorbed elements are replaced by p, variables in L_p are covered by p
+            elem_adj[i].retain(|&e| !absorbed[e]);
+            var_adj[i].retain(|&j| mark[j] != p);
+
+            let external: usize = elem_adj[i].iter().map(|&e| w[e] as usize).sum();
+            let bound = var_adj[i].len() + lp.len() - 1 + external;
+            degree[i] = n_left.min(degree[i] + lp.len() - 1).min(bound);
+            elem_adj[i].push(p);
+            heap.push(Reverse((degree[i], i)));
+        }
+        for &i in &lp {
+            for &e in &elem_adj[i] {
+                w[e] = -1;
+            }
+        }
+        elem_vars[p] = lp;
+    }
+
+    perm
+}
+
+
+
+// Parts of the graph with at most this many vertices are not dissected further
+pub const ND_LEAF_SIZE: usize = 64;
+
+
+
+// Nested dissection with level-structure separators: the middle level of a
+// BFS from a pseudo-peripheral vertex splits a part into two halves, which are
+// ordered first (recursively), the separator last.
+// "Nested Dissection of a Regular Finite Element Mesh", George
+// https://doi.org/10.1137/0710032
+// Parts with at most leaf_size vertices are ordered by rcm-like BFS order.
+pub fn nested_dissection(a: &CSR, leaf_size: usize) -> Vec<usize> {
+    assert!(leaf_size > 0, "Leaf size must be positive");
+    let g = Graph::new(a);
+    let n = g.n();
+
+    // part[v] is the label of the part v currently belongs to,
+    // usize::MAX marks vertices that are already ordered
+    let mut part = vec![0; n];
+    let mut visited = vec![false; n];
+    let mut next_label = 1;
+    let mut perm = vec![usize::MAX; n];
+
+    // (label, vertices, first position in perm), ordered depth first
+    let mut stack = vec![(0, (0..n).collect::<Vec<usize>>(), 0)];
+    while let Some((label, vertices, first)) = stack.pop() {
+        if vertices.is_empty() {
+            continue;
+        }
+
+        // Level structure of the component of the first vertex of the part,
+        // the rest of the part (other components) forms the second half
+        let root = g.pseudo_peripheral(vertices[0], &part, label, &mut visited);
+        let (order, level_pos) = g.levels(root, &part, label, &mut visited);
+
+        if vertices.len() <= leaf_size {
+            // All components of the leaf in BFS order
+            let mut pos = first;
+            let mut rest = vertices;
+            let mut order = order;
+            loop {
+                for &v in &order {
+                    perm[pos] = v;
+                    part[v] = usize::MAX;
+                    pos += 1;
+                }
+                rest.retain(|&v| part[v] == label);
+                match rest.first() {
+                    Some(&v) => order = g.levels(v, &part, label, &mut visited).0,
+                    None => break
+                }
+            }
+            continue;
+        }
+
+        let (first_half, separator): (Vec<usize>, Vec<usize>) = if order.len() < vertices.len() {
+            (order, vec![])
+        } else {
+            // Middle level: the first level where half of the vertices are reached
+            let n_levels = level_pos.len() - 1;
+            let mid = (1..n_levels).find(|&l| level_pos[l+1] > order.len() / 2).unwrap_or(n_levels - 1);
+            let sep = order[level_pos[mid]..level_pos[mid+1]].to_vec();
+            (order[..level_pos[mid]].to_vec(), sep)
+        };
+
+        let (label_1, label_2) = (next_label, next_label + 1);
+        next_label += 2;
+        for &v in &first_half {
+            part[v] = label_1;
+        }
+        for &v in &separator {
+            part[v] = usize::MAX;
+        }
+        let second_half: Vec<usize> = vertices.iter().copied().filter(|&v| part[v] == label).collect();
+        for &v in &second_half {
+            part[v] = label_2;
+        }
+
+        let sep_first = first + first_half.len() + second_half.len();
+        for (k, &v) in separator.iter().enumerate() {
+            perm[sep_first + k] = v;
+        }
+        let second_first = first + first_half.len();
+        stack.push((label_2, second_half, second_first));
+        stack.push((label_1, first_half, first));
+    }
+
+    perm
+}
+
+
+
+// Bandwidth and profile (see CSR::bandwidth, CSR::profile) of a matrix
+// before and after a symmetric reordering
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderingReport {
+    pub bandwidth_before: usize,
+    pub bandwidth_after: usize,
+    pub profile_before: usize,
+    pub profile_after: usize
+}
+
+
+impl OrderingReport {
+    pub fn new(a: &CSR, perm: &[usize]) -> Self {
+        let b = a.permute(perm, perm);
+        OrderingReport {
+            bandwidth_before: a.bandwidth(),
+            bandwidth_after: b.bandwidth(),
+            profile_before: a.profile(),
+            profile_after: b.profile()
+        }
+    }
+}
+
+
+impl fmt::Display for OrderingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bandwidth {} -> {}, profile {} -> {}",
+            self.bandwidth_before, self.bandwidth_after, self.profile_before, self.profile_after)
+    }
+}
@@ -0,0 +1,127 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::CSR;
+
+
+// Scheduling of row-wise kernels (SpGEMM on CPU and GPU).
+// Splitting by row count leaves threads idle on power-law matrices,
+// where a few hub rows carry most of the work. A RowPartition cuts the
+// rows into contiguous chunks of (about) equal work instead.
+//
+// A single row is never split, so a row with more work than
+// total / n_chunks still ends up in a chunk of its own (see imbalance).
+
+
+
+// flops(i) = \sum_{k \in I_i(A)} nnz(B_{k*}), the number of products of
+// row i of A*B. The sum over all rows is the upper bound of nnz(A*B)
+// used by gpu::size_prediction.
+pub fn product_row_flops(a: &CSR, b: &CSR) -> Vec<usize> {
+    assert_eq!(a.shape.1, b.shape.0, "Matrix dimensions do not match for multiplication");
+    a.par_rows()
+        .map(|(_, cols, _)| cols.iter().map(|k| b.get_row_nnz(*k)).sum())
+        .collect()
+}
+
+
+
+// Chunk c covers the rows bounds[c]..bounds[c+1], with work[c] units of work
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowPartition {
+    pub bounds: Vec<usize>,
+    pub work: Vec<usize>
+}
+
+
+// Work per chunk, ratio = max / mean is 1 for a perfect partition
+// and the speedup lost to the slowest chunk otherwise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Imbalance {
+    pub max_work: usize,
+    pub mean_work: f64,
+    pub ratio: f64
+}
+
+
+
+impl RowPartition {
+    // At most n_chunks contiguous chunks of about equal total weight.
+    // Every chunk boundary is placed at the first row where the prefix sum
+    // of the weights reaches c * total / n_chunks.
+    // Chunks can be empty if single rows are heavier than the target.
+    pub fn balanced(weights: &[usize], n_chunks: usize) -> Self {
+        assert!(n_chunks > 0, "Need at least one chunk");
+        let n = weights.len();
+
+        let mut prefix = Vec::with_capacity(n+1);
+        prefix.push(0);
+        for w in weights {
+            prefix.push(prefix.last().unwrap() + w);
+        }
+        let total = prefix[n];
+
+        let mut bounds = Vec::with_capacity(n_chunks+1);
+        bounds.push(0);
+        for c in 1..n_chunks {
+            let target = (total as u128 * c as u128 / n_chunks as u128) as usize;
+            let row = prefix.partition_point(|&p| p < target).min(n);
+            bounds.push(row.max(*bounds.last().unwrap()));
+        }
+        bounds.push(n);
+
+        let work = bounds.windows(2).map(|w| prefix[w[1]] - prefix[w[0]]).collect();
+        RowPartition{bounds, work}
+    }
+
+
+    // n_chunks chunks of the same number of rows, i.e. what splitting by
+    // row count does. The work is still taken from the weights.
+    pub fn uniform(weights: &[usize], n_chunks: usize) -> Self {
+        assert!(n_chunks > 0, "Need at least one chunk");
+        let n = weights.len();
+
+        let bounds: Vec<usize> = (0..=n_chunks).map(|c| n * c / n_chunks).collect();
+        let work = bounds.windows(2).map(|w| weights[w[0]..w[1]].iter().sum()).collect();
+        RowPartition{bounds, work}
+    }
+
+
+    // Balanced partition of the rows of A for C = A*B.
+    // The work of row i is flops(i) + 1, so that long runs of empty rows are split too.
+    pub fn for_product(a: &CSR, b: &CSR, n_chunks: usize) -> Self {
+        let weights: Vec<usize> = product_row_flops(a, b).iter().map(|f| f + 1).collect();
+        RowPartition::balanced(&weights, n_chunks)
+    }
+
+
+    pub fn n_chunks(&self) -> usize {
+        self.work.len()
+    }
+
+
+    pub fn n_rows(&self) -> usize {
+        *self.bounds.last().unwrap()
+    }
+
+
+    pub fn chunk(&self, c: usize) -> Range<usize> {
+        self.bounds[c]..self.bounds[c+1]
+    }
+
+
+    // Row ranges of all chunks, in order
+    pub fn chunks(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.bounds.windows(2).map(|w| w[0]..w[1])
+    }
+
+
+    pub fn imbalance(&self) -> Imbalance {
+        let max_work = self.work.iter().copied().max().unwrap_or(0);
+        let total: usize = self.work.iter().sum();
+        let mean_work = total as f64 / self.n_chunks() as f64;
+        let ratio = if total == 0 { 1. } else { max_work as f64 / mean_work };
+        Imbalance{max_work, mean_work, ratio}
+    }
+}
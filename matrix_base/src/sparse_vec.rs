@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+
+
+// Sparse vector of length len, stored as sorted indices and the
+// corresponding values. Dense vectors are plain Vec<f64> / &[f64].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseVec {
+    pub indices: Vec<usize>,
+    pub values: Vec<f64>,
+    pub len: usize
+}
+
+
+// Borrowed version of SparseVec, e.g. a row of a CSR matrix.
+// All operations are implemented here, SparseVec forwards to view().
+#[derive(Debug, Clone, Copy)]
+pub struct SparseVecView<'a> {
+    pub indices: &'a [usize],
+    pub values: &'a [f64],
+    pub len: usize
+}
+
+
+
+impl SparseVec {
+    pub fn new(len: usize) -> Self {
+        SparseVec{indices: vec![], values: vec![], len}
+    }
+
+    // Entries may come in any order, duplicates are summed up
+    pub fn from_pairs(len: usize, mut pairs: Vec<(usize, f64)>) -> Self {
+        pairs.sort_by_key(|&(i, _)| i);
+
+        let mut indices: Vec<usize> = Vec::with_capacity(pairs.len());
+        let mut values: Vec<f64> = Vec::with_capacity(pairs.len());
+        for (i, x) in pairs {
+            assert!(i < len, "Index {} out of bounds for sparse vector of length {}", i, len);
+            if indices.last() == Some(&i) {
+                *values.last_mut().unwrap() += x;
+            } else {
+                indices.push(i);
+                values.push(x);
+            }
+        }
+
+        SparseVec{indices, values, len}
+    }
+
+    // Keeps all non-zero entries of a dense vector
+    pub fn from_dense(x: &[f64]) -> Self {
+        let (indices, values) = x.iter().enumerate().filter(|(_, x)| **x != 0.).map(|(i, x)| (i, *x)).unzip();
+        SparseVec{indices, values, len: x.len()}
+    }
+
+    // Gather: pick the entries of a dense vector at the given (sorted) indices
+    pub fn gather(x: &[f64], indices: &[usize]) -> Self {
+        debug_assert!(indices.windows(2).all(|w| w[0] < w[1]), "Indices must be sorted and unique");
+        let values = indices.iter().map(|i| x[*i]).collect();
+        SparseVec{indices: indices.to_vec(), values, len: x.len()}
+    }
+
+    pub fn view(&self) -> SparseVecView<'_> {
+        SparseVecView{indices: &self.indices, values: &self.values, len: self.len}
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &f64)> {
+        self.indices.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn to_dense(&self) -> Vec<f64> {
+        self.view().to_dense()
+    }
+
+    pub fn scatter(&self, y: &mut [f64]) {
+        self.view().scatter(y)
+    }
+
+    pub fn dot(&self, other: &SparseVec) -> f64 {
+        self.view().dot(other.view())
+    }
+
+    pub fn dot_dense(&self, y: &[f64]) -> f64 {
+        self.view().dot_dense(y)
+    }
+
+    pub fn axpy(&self, alpha: f64, x: &SparseVec) -> SparseVec {
+        self.view().axpy(alpha, x.view())
+    }
+
+    pub fn axpy_dense(&self, alpha: f64, y: &mut [f64]) {
+        self.view().axpy_dense(alpha, y)
+    }
+
+    pub fn union_with(&self, other: &SparseVec, f: impl Fn(f64, f64) -> f64) -> SparseVec {
+        self.view().union_with(other.view(), f)
+    }
+
+    pub fn intersect_with(&self, other: &SparseVec, f: impl Fn(f64, f64) -> f64) -> SparseVec {
+        self.view().intersect_with(other.view(), f)
+    }
+
+    pub fn difference(&self, other: &SparseVec) -> SparseVec {
+        self.view().difference(other.view())
+    }
+}
+
+
+
+impl<'a> SparseVecView<'a> {
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &'a f64)> {
+        self.indices.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn to_owned(&self) -> SparseVec {
+        SparseVec{indices: self.indices.to_vec(), values: self.values.to_vec(), len: self.len}
+    }
+
+    pub fn to_dense(&self) -> Vec<f64> {
+        let mut y = vec![0.; self.len];
+        self.scatter(&mut y);
+        y
+    }
+
+    // Scatter: y_i = x_i for all non-zero entries, other entries of y are untouched
+    pub fn scatter(&self, y: &mut [f64]) {
+        for (i, x) in self.iter() {
+            y[i] = *x;
+        }
+    }
+
+    // Sparse-sparse dot product, merge of the two sorted index lists
+    pub fn dot(&self, other: SparseVecView) -> f64 {
+        assert_eq!(self.len, other.len, "Vector lengths do not match");
+        let mut res = 0.;
+        let (mut p, mut q) = (0, 0);
+        while p < self.nnz() && q < other.nnz() {
+            match self.indices[p].cmp(&other.indices[q]) {
+                Ordering::Less => p += 1,
+                Ordering::Greater => q += 1,
+                Ordering::Equal => {
+                    res += self.values[p] * other.values[q];
+                    p += 1;
+                    q += 1;
+                }
+            }
+        }
+        res
+    }
+
+    pub fn dot_dense(&self, y: &[f64]) -> f64 {
+        assert_eq!(self.len, y.len(), "Vector lengths do not match");
+        self.iter().map(|(i, x)| x * y[i]).sum()
+    }
+
+    // self + alpha * x, both sparse
+    pub fn axpy(&self, alpha: f64, x: SparseVecView) -> SparseVec {
+        self.merge(x, |a| a, |b| alpha * b, |a, b| a + alpha * b)
+    }
+
+    // y += alpha * self, y dense
+    pub fn axpy_dense(&self, alpha: f64, y: &mut [f64]) {
+        assert_eq!(self.len, y.len(), "Vector lengths do not match");
+        for (i, x) in self.iter() {
+            y[i] += alpha * x;
+        }
+    }
+
+    // Entries on the union of both patterns, f is applied where both are present.
+    // E.g. f = |a, b| a + b is the sparse vector sum.
+    pub fn union_with(&self, other: SparseVecView, f: impl Fn(f64, f64) -> f64) -> SparseVec {
+        self.merge(other, |a| a, |b| b, f)
+    }
+
+    // Entries on the intersection of both patterns.
+    // E.g. f = |a, b| a * b is the element-wise product.
+    pub fn intersect_with(&self, other: SparseVecView, f: impl Fn(f64, f64) -> f64) -> SparseVec {
+        assert_eq!(self.len, other.len, "Vector lengths do not match");
+        let mut indices = vec![];
+        let mut values = vec![];
+        let (mut p, mut q) = (0, 0);
+        while p < self.nnz() && q < other.nnz() {
+            match self.indices[p].cmp(&other.indices[q]) {
+                Ordering::Less => p += 1,
+                Ordering::Greater => q += 1,
+                Ordering::Equal => {
+                    indices.push(self.indices[p]);
+                    values.push(f(self.values[p], other.values[q]));
+                    p += 1;
+                    q += 1;
+                }
+            }
+        }
+        SparseVec{indices, values, len: self.len}
+    }
+
+    // Entries of self whose index is not in other, e.g. frontier minus visited set
+    pub fn difference(&self, other: SparseVecView) -> SparseVec {
+        assert_eq!(self.len, other.len, "Vector lengths do not match");
+        let mut indices = vec![];
+        let mut values = vec![];
+        let mut q = 0;
+        for (i, x) in self.iter() {
+            while q < other.nnz() && other.indices[q] < i {
+                q += 1;
+            }
+            if q == other.nnz() || other.indices[q] != i {
+                indices.push(i);
+                values.push(*x);
+            }
+        }
+        SparseVec{indices, values, len: self.len}
+    }
+
+    // General two-way merge of the sorted index lists.
+    // only_a / only_b are applied to entries present in only one operand,
+    // both to entries present in both.
+    fn merge(&self, other: SparseVecView,
+        only_a: impl Fn(f64) -> f64, only_b: impl Fn(f64) -> f64, both: impl Fn(f64, f64) -> f64) -> SparseVec {
+        assert_eq!(self.len, other.len, "Vector lengths do not match");
+        let mut indices = Vec::with_capacity(self.nnz() + other.nnz());
+        let mut values = Vec::with_capacity(self.nnz() + other.nnz());
+        let (mut p, mut q) = (0, 0);
+        while p < self.nnz() || q < other.nnz() {
+            let ord = if p == self.nnz() {
+                Ordering::Greater
+            } else if q == other.nnz() {
+                Ordering::Less
+            } else {
+                self.indices[p].cmp(&other.indices[q])
+            };
+
+            match ord {
+                Ordering::Less => {
+                    indices.push(self.indices[p]);
+                    values.push(only_a(self.values[p]));
+                    p += 1;
+                }
+                Ordering::Greater => {
+                    indices.push(other.indices[q]);
+                    values.push(only_b(other.values[q]));
+                    q += 1;
+                }
+                Ordering::Equal => {
+                    indices.push(self.indices[p]);
+                    values.push(both(self.values[p], other.values[q]));
+                    p += 1;
+                    q += 1;
+                }
+            }
+        }
+        SparseVec{indices, values, len: self.len}
+    }
+}
+
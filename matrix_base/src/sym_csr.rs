@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use crate::{COO, CSR, Dense};
+use crate::coo::{read_mtx_symmetry, MtxSymmetry};
+
+
+// Symmetric (n,n)-matrix in CSR format, only the upper triangle
+// (including the diagonal) is stored, i.e. col_pos[..] >= row for every row.
+// The lower triangle is implicit, a_ji = a_ij.
+// Same fields and notation as CSR.
+pub struct SymCSR {
+    pub row_pos: Vec<usize>,
+    pub col_pos: Vec<usize>,
+    pub values: Vec<f64>,
+    pub shape: (usize, usize)
+}
+
+
+impl SymCSR {
+    // From a COO matrix that holds one triangle of a symmetric matrix,
+    // either the lower (as in MatrixMarket 'symmetric' files) or the upper one.
+    // Entries (i,j) are mirrored to (min(i,j), max(i,j)).
+    pub fn from_triangle_coo(coo: &COO) -> Self {
+        assert_eq!(coo.shape.0, coo.shape.1, "Symmetric matrix must be square");
+
+        let mut data: Vec<(usize, usize, f64)> = coo.data.iter().map(|&(i, j, x)| (i.min(j), i.max(j), x)).collect();
+        data.sort_by_key(|&(i, j, _)| (i, j));
+
+        let upper = CSR::from_coo(&COO{data, shape: coo.shape});
+        SymCSR{row_pos: upper.row_pos, col_pos: upper.col_pos, values: upper.values, shape: upper.shape}
+    }
+
+
+    // From a full CSR matrix, which is assumed to be symmetric.
+    // Only the upper triangle is kept, the lower one is not checked.
+    pub fn from_csr(csr: &CSR) -> Self {
+        assert_eq!(csr.shape.0, csr.shape.1, "Symmetric matrix must be square");
+
+        let mut row_pos: Vec<usize> = Vec::with_capacity(csr.shape.0+1);
+        let mut col_pos: Vec<usize> = vec![];
+        let mut values: Vec<f64> = vec![];
+        row_pos.push(0);
+
+        for (i, cols, vals) in csr.rows() {
+            for (j, x) in cols.iter().zip(vals) {
+                if *j >= i {
+                    col_pos.push(*j);
+                    values.push(*x);
+                }
+            }
+            row_pos.push(values.len());
+        }
+
+        SymCSR{row_pos, col_pos, values, shape: csr.shape}
+    }
+
+
+    // Read a MatrixMarket file with 'symmetric' in its banner
+    pub fn read_mtx(fname: &Path) -> Result<Self, &str> {
+        if read_mtx_symmetry(fname)? != MtxSymmetry::Symmetric {
+            return Err("File is not a symmetric MatrixMarket file.");
+        }
+        let coo = COO::read_mtx(fname, false)?;
+        Ok(SymCSR::from_triangle_coo(&coo))
+    }
+
+
+    pub fn print(&self) {
+        println!("Symmetric ({},{})-matrix in CSR format (upper triangle) with {} stored entries", self.shape.0, self.shape.1, self.values.len());
+        println!("Row Pos {:?}", self.row_pos);
+        println!("Col Pos {:?}", self.col_pos);
+        println!("Values {:?}", self.values);
+    }
+
+
+    // Number of non-zero entries of the full matrix
+    pub fn nnz_full(&self) -> usize {
+        let diag = self.iter().filter(|(i, j, _)| i == j).count();
+        2*self.values.len() - diag
+    }
+
+
+    // (j, a_ij) for the stored entries of the i-th row, i.e. j >= i
+    pub fn row_upper(&self, i: usize) -> impl Iterator<Item = (usize, &f64)> {
+        let range = self.row_pos[i]..self.row_pos[i+1];
+        self.col_pos[range.clone()].iter().copied().zip(self.values[range].iter())
+    }
+
+
+    // (i, j, a_ij) for all stored entries, i.e. the upper triangle
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &f64)> {
+        (0..self.shape.0).flat_map(move |i| self.row_upper(i).map(move |(j, x)| (i, j, x)))
+    }
+
+
+    // Expand to a full CSR matrix with sorted rows
+    pub fn to_csr(&self) -> CSR {
+        let n = self.shape.0;
+
+        // Count entries per row of the full matrix: the stored
+        // upper row plus the mirrored strictly upper entries (k,i), k<i
+        let mut counts = vec![0; n];
+        for (i, j, _) in self.iter() {
+            counts[i] += 1;
+            if i != j {
+                counts[j] += 1;
+            }
+        }
+
+        let mut row_pos = vec![0; n+1];
+        for i in 0..n {
+            row_pos[i+1] = row_pos[i] + counts[i];
+        }
+
+        // Rows are processed in order, so the mirrored entries (j, i)
+        // arrive in increasing column i and before the stored upper
+        // row j starts, which keeps every row sorted.
+        let nnz = row_pos[n];
+        let mut col_pos = vec![0; nnz];
+        let mut values = vec![0.; nnz];
+        let mut next = row_pos.clone();
+        for i in 0..n {
+            for (j, x) in self.row_upper(i) {
+                if i != j {
+                    col_pos[next[j]] = i;
+                    values[next[j]] = *x;
+                    next[j] += 1;
+                }
+            }
+            for (j, x) in self.row_upper(i) {
+                col_pos[next[i]] = j;
+                values[next[i]] = *x;
+                next[i] += 1;
+            }
+        }
+
+        CSR{row_pos, col_pos, values, shape: self.shape}
+    }
+
+
+    pub fn to_dense(&self) -> Dense {
+        let mut mat = Dense::new_zeros(self.shape);
+        for (i, j, x) in self.iter() {
+            mat.set(i, j, *x);
+            mat.set(j, i, *x);
+        }
+        mat
+    }
+}
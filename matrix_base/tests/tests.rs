@@ -2,7 +2,11 @@
 use std::path::Path;
 
 
-use matrix_base::{Dense, COO, CSR};
+use rayon::prelude::*;
+
+use matrix_base::{Dense, COO, CSR, SymCSR, SparseVec, RowPartition, kron, block, hstack, vstack, product_row_flops};
+use matrix_base::{rcm, amd, nested_dissection, OrderingReport};
+use matrix_base::{CSC, LinearOperator, MatrixFree, Sum, Product, Scaled, Transposed};
 
 // Im Endeffekt etwas umständlich über Path joinen.
 // Kann man auch mit String-Concat machen, aber
@@ -77,4 +81,515 @@ fn test_read_csr() {
 
     // TODO
 
-}
\ No newline at end of file
+}
+
+
+#[test]
+fn test_iterators() {
+    let fname = Path::new(DATA_PATH).join(Path::new("a001.mtx"));
+    let coo = COO::read_mtx(&fname, true).expect("Failed reading matrix during test");
+    let mut csr = CSR::from_coo(&coo);
+    let dense = coo.to_dense();
+
+    let data_a001 = [(0,0,25.), (1,0,15.), (1,1,18.), (2,0,5.), (2,2,11.)];
+
+    // All formats yield the same (i, j, a_ij) for the non-zero entries
+    let csr_entries: Vec<(usize, usize, f64)> = csr.iter().map(|(i, j, x)| (i, j, *x)).collect();
+    let coo_entries: Vec<(usize, usize, f64)> = coo.iter().map(|(i, j, x)| (i, j, *x)).collect();
+    let dense_entries: Vec<(usize, usize, f64)> = dense.iter().filter(|(_, _, x)| **x != 0.).map(|(i, j, x)| (i, j, *x)).collect();
+    assert_eq!(csr_entries, data_a001);
+    assert_eq!(coo_entries, data_a001);
+    assert_eq!(dense_entries, data_a001);
+
+    let row1: Vec<(usize, f64)> = csr.row(1).map(|(j, x)| (j, *x)).collect();
+    assert_eq!(row1, [(0, 15.), (1, 18.)]);
+    assert_eq!(dense.row(1), &[15., 18., 0.]);
+
+    // Row sums, sequential and parallel
+    let sums: Vec<f64> = csr.rows().map(|(_, _, vals)| vals.iter().sum()).collect();
+    let par_sums: Vec<f64> = csr.par_rows().map(|(_, _, vals)| vals.iter().sum()).collect();
+    let dense_sums: Vec<f64> = dense.par_rows().map(|(_, row)| row.iter().sum()).collect();
+    assert_eq!(sums, [25., 33., 16.]);
+    assert_eq!(par_sums, sums);
+    assert_eq!(dense_sums, sums);
+
+    // Mutable iteration
+    for (i, j, x) in csr.iter_mut() {
+        *x += (10 * i + j) as f64;
+    }
+    assert_eq!(csr.values, [25., 25., 29., 25., 33.]);
+
+    csr.par_rows_mut().for_each(|(i, _, vals)| {
+        for x in vals.iter_mut() {
+            *x = i as f64;
+        }
+    });
+    assert_eq!(csr.values, [0., 1., 1., 2., 2.]);
+}
+
+
+
+#[test]
+fn test_sparse_vec() {
+    let eps = 1e-10;
+
+    let x = SparseVec::from_pairs(6, vec![(4, 1.), (0, 2.), (2, 3.), (4, 1.)]);
+    assert_eq!(x.indices, [0, 2, 4]);
+    assert_eq!(x.values, [2., 3., 2.]);
+    assert_eq!(x.to_dense(), [2., 0., 3., 0., 2., 0.]);
+
+    let y = SparseVec::from_dense(&[0., 1., 1., 0., 4., 0.]);
+    assert_eq!(y.indices, [1, 2, 4]);
+
+    assert!(cmp_float(x.dot(&y), 3. + 8., eps));
+    assert!(cmp_float(x.dot_dense(&y.to_dense()), 3. + 8., eps));
+
+    // x + 2y
+    let z = x.axpy(2., &y);
+    assert_eq!(z.indices, [0, 1, 2, 4]);
+    assert_eq!(z.values, [2., 2., 5., 10.]);
+
+    let mut w = vec![1.; 6];
+    x.axpy_dense(-1., &mut w);
+    assert_eq!(w, [-1., 1., -2., 1., -1., 1.]);
+
+    let prod = x.intersect_with(&y, |a, b| a * b);
+    assert_eq!(prod.indices, [2, 4]);
+    assert_eq!(prod.values, [3., 8.]);
+
+    let maxes = x.union_with(&y, f64::max);
+    assert_eq!(maxes.to_dense(), [2., 1., 3., 0., 4., 0.]);
+
+    let diff = y.difference(&x);
+    assert_eq!(diff.indices, [1]);
+
+    // Gather / scatter round trip
+    let g = SparseVec::gather(&w, &[1, 3, 5]);
+    assert_eq!(g.values, [1., 1., 1.]);
+    let mut s = vec![0.; 6];
+    g.scatter(&mut s);
+    assert_eq!(s, [0., 1., 0., 1., 0., 1.]);
+
+    // CSR rows as views
+    let fname = Path::new(DATA_PATH).join(Path::new("a001.mtx"));
+    let coo = COO::read_mtx(&fname, true).expect("Failed reading matrix during test");
+    let csr = CSR::from_coo(&coo);
+    let r1 = csr.row_vec(1);
+    let r2 = csr.row_vec(2);
+    assert_eq!(r1.len, 3);
+    assert!(cmp_float(r1.dot(r2), 15. * 5., eps));
+    assert_eq!(r1.union_with(r2, |a, b| a + b).to_dense(), [20., 18., 11.]);
+}
+
+
+
+#[test]
+fn test_constructors() {
+    let eps = 1e-10;
+
+    let id = CSR::identity(3);
+    assert_eq!(id.row_pos, [0, 1, 2, 3]);
+    assert_eq!(id.to_dense().data, [1., 0., 0., 0., 1., 0., 0., 0., 1.]);
+
+    // Tridiagonal Toeplitz matrix as in generate_toeplitz.py
+    let t = CSR::from_diagonals(&[&[1.], &[-2.], &[1.]], &[-1, 0, 1], (4, 4));
+    assert_eq!(t.to_dense().data, [
+        -2., 1., 0., 0.,
+        1., -2., 1., 0.,
+        0., 1., -2., 1.,
+        0., 0., 1., -2.]);
+
+    // Non-square with full diagonals
+    let d = CSR::from_diagonals(&[&[1., 2.], &[3., 4., 5.]], &[1, -1], (4, 3));
+    assert_eq!(d.to_dense().data, [
+        0., 1., 0.,
+        3., 0., 2.,
+        0., 4., 0.,
+        0., 0., 5.]);
+
+    // Kronecker product against the definition
+    let fname = Path::new(DATA_PATH).join(Path::new("a001.mtx"));
+    let a = CSR::from_coo(&COO::read_mtx(&fname, true).expect("Failed reading matrix during test"));
+    let fname = Path::new(DATA_PATH).join(Path::new("a002.mtx"));
+    let b = CSR::from_coo(&COO::read_mtx(&fname, true).expect("Failed reading matrix during test"));
+
+    let k = kron(&a, &b);
+    assert_eq!(k.shape, (6, 9));
+    let (ad, bd, kd) = (a.to_dense(), b.to_dense(), k.to_dense());
+    for (i, j, x) in kd.iter() {
+        let expected = ad.get(i / 2, j / 3) * bd.get(i % 2, j % 3);
+        assert!(cmp_float(*x, expected, eps));
+    }
+    for i in 0..k.shape.0 {
+        let (cols, _) = k.row_slices(i);
+        assert!(cols.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    // 2D Laplacian stencil: kron(I, T) and kron(T, I) have the same pattern size
+    let lap_x = kron(&CSR::identity(4), &t);
+    let lap_y = kron(&t, &CSR::identity(4));
+    assert_eq!(lap_x.shape, (16, 16));
+    assert_eq!(lap_x.values.len(), lap_y.values.len());
+
+    // [A 0; B I]
+    let id2 = CSR::identity(2);
+    let blk = block(&[&[Some(&a), None], &[Some(&b), Some(&id2)]]);
+    assert_eq!(blk.shape, (5, 5));
+    let blkd = blk.to_dense();
+    assert_eq!(blkd.row(0), &[25., 0., 0., 0., 0.]);
+    assert_eq!(blkd.row(3), &[25., 0., 0., 1., 0.]);
+    assert_eq!(blkd.row(4), &[15., 18., 0., 0., 1.]);
+
+    let h = hstack(&[&a, &id]);
+    assert_eq!(h.shape, (3, 6));
+    assert_eq!(h.to_dense().row(1), &[15., 18., 0., 0., 1., 0.]);
+
+    let v = vstack(&[&b, &a]);
+    assert_eq!(v.shape, (5, 3));
+    assert_eq!(v.to_dense().row(2), &[25., 0., 0.]);
+}
+
+
+
+#[test]
+fn test_read_symmetric() {
+    let fname = Path::new(DATA_PATH).join(Path::new("symmetric/s001.mtx"));
+    let sym = SymCSR::read_mtx(&fname).expect("Failed reading matrix during test");
+
+    assert_eq!(sym.shape, (4, 4));
+    // Only the upper triangle is stored
+    assert_eq!(sym.values.len(), 8);
+    assert!(sym.iter().all(|(i, j, _)| j >= i));
+    assert_eq!(sym.nnz_full(), 12);
+
+    let expected = [
+        4., -1., 0., 1.,
+        -1., 4., -1., 0.,
+        0., -1., 4., -1.,
+        1., 0., -1., 4.];
+    assert_eq!(sym.to_dense().data, expected);
+
+    // Full CSR and back
+    let full = sym.to_csr();
+    assert_eq!(full.values.len(), 12);
+    assert_eq!(full.to_dense().data, expected);
+    for i in 0..4 {
+        let (cols, _) = full.row_slices(i);
+        assert!(cols.windows(2).all(|w| w[0] < w[1]));
+    }
+    let sym2 = SymCSR::from_csr(&full);
+    assert_eq!(sym2.row_pos, sym.row_pos);
+    assert_eq!(sym2.col_pos, sym.col_pos);
+    assert_eq!(sym2.values, sym.values);
+
+    // General files are rejected
+    let fname = Path::new(DATA_PATH).join(Path::new("a001.mtx"));
+    assert!(SymCSR::read_mtx(&fname).is_err());
+}
+
+
+
+#[test]
+fn test_row_partition() {
+    // Power-law like, rows sorted by degree: the first 20 rows are hubs
+    // with a full row, the other rows have a single entry
+    let n = 1000;
+    let mut data = Vec::new();
+    for i in 0..n {
+        if i < 20 {
+            data.extend((0..n).map(|j| (i, j, 1.)));
+        } else {
+            data.push((i, i, 2.));
+        }
+    }
+    let mut a = CSR::from_coo(&COO{data, shape: (n, n)});
+
+    // Row i of A*A: nnz of the rows of A hit by row i
+    let flops = product_row_flops(&a, &a);
+    assert_eq!(flops.len(), n);
+    assert_eq!(flops[20], 1);
+    assert_eq!(flops[0], 20 * n + (n - 20));
+    assert_eq!(flops[19], flops[0]);
+
+    let weights: Vec<usize> = flops.iter().map(|f| f + 1).collect();
+    let total: usize = weights.iter().sum();
+
+    for n_chunks in [1, 3, 8, 40] {
+        let balanced = RowPartition::balanced(&weights, n_chunks);
+        let uniform = RowPartition::uniform(&weights, n_chunks);
+        assert_eq!(RowPartition::for_product(&a, &a, n_chunks), balanced);
+
+        for p in [&balanced, &uniform] {
+            assert_eq!(p.n_chunks(), n_chunks);
+            assert_eq!(p.n_rows(), n);
+            assert_eq!(p.bounds[0], 0);
+            assert!(p.bounds.windows(2).all(|w| w[0] <= w[1]));
+            assert_eq!(p.work.iter().sum::<usize>(), total);
+            assert_eq!(p.chunks().map(|r| r.len()).sum::<usize>(), n);
+        }
+
+        // A chunk holds at most one hub more than the average, so the
+        // max is bounded by mean + max row weight
+        let imb = balanced.imbalance();
+        assert!(imb.ratio >= 1.);
+        assert!(imb.max_work <= total / n_chunks + weights[0] + 1);
+    }
+
+    // Splitting by rows puts all hubs into the first chunk
+    assert_eq!(RowPartition::balanced(&weights, 1).imbalance().ratio, 1.);
+    let skewed = RowPartition::uniform(&weights, 3).imbalance();
+    let balanced = RowPartition::balanced(&weights, 3).imbalance();
+    assert!(skewed.ratio > 2.5);
+    assert!(balanced.ratio < 1.1);
+
+    // Zero weights are not split: every boundary is at the first row,
+    // so all rows end up in the last chunk
+    let empty = RowPartition::balanced(&[0; 10], 2);
+    assert_eq!(empty.bounds, [0, 0, 10]);
+    assert_eq!(empty.imbalance().ratio, 1.);
+
+    // for_product weighs a row with flops + 1, so empty rows are split
+    let zero = CSR{row_pos: vec![0; 11], col_pos: vec![], values: vec![], shape: (10, 10)};
+    assert_eq!(RowPartition::for_product(&zero, &CSR::identity(10), 2).bounds, [0, 5, 10]);
+
+    // Partitioned iterators visit every row once, in order
+    let p = RowPartition::balanced(&weights, 7);
+    let rows: Vec<usize> = a.par_rows_by(&p).map(|(i, _, _)| i).collect();
+    assert_eq!(rows, (0..n).collect::<Vec<usize>>());
+    let nnz: usize = a.par_rows_by(&p).map(|(_, cols, _)| cols.len()).sum();
+    assert_eq!(nnz, a.values.len());
+
+    a.par_rows_pattern_mut_by(&p).for_each(|(i, cols, vals)| {
+        for (j, x) in cols.iter().zip(vals.iter_mut()) {
+            *x = (i + j) as f64;
+        }
+    });
+    assert!(a.iter().all(|(i, j, x)| *x == (i + j) as f64));
+}
+
+
+
+#[test]
+fn test_transpose_diagonal() {
+    let a = CSR::from_diagonals(&[&[1.], &[2., 3., 4.], &[5.]], &[-1, 0, 2], (3, 4));
+    let at = a.transpose();
+    assert_eq!(at.shape, (4, 3));
+    let (d, dt) = (a.to_dense(), at.to_dense());
+    for (i, j, x) in d.iter() {
+        assert_eq!(dt.get(j, i), *x);
+    }
+    for i in 0..at.shape.0 {
+        assert!(at.row_slices(i).0.is_sorted());
+    }
+    assert_eq!(at.transpose().col_pos, a.col_pos);
+
+    assert_eq!(a.diagonal(), [2., 3., 4.]);
+    assert_eq!(at.diagonal(), [2., 3., 4.]);
+}
+
+
+
+#[test]
+fn test_pattern_hash() {
+    let a = CSR::from_diagonals(&[&[1.], &[2.], &[3.]], &[-1, 0, 2], (8, 8));
+    let mut b = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: a.shape};
+    b.values.iter_mut().for_each(|x| *x *= -2.);
+    assert_eq!(a.pattern_hash(), b.pattern_hash());
+
+    // Same nnz, one entry moved
+    b.col_pos[b.row_pos[3]] = 1;
+    assert_ne!(a.pattern_hash(), b.pattern_hash());
+    // Same arrays, other shape
+    let wide = CSR{row_pos: a.row_pos.clone(), col_pos: a.col_pos.clone(), values: a.values.clone(), shape: (8, 9)};
+    assert_ne!(a.pattern_hash(), wide.pattern_hash());
+
+    assert_eq!(CSC::from_csr(&a).pattern_hash(), CSC::from_csr(&a).pattern_hash());
+    assert_ne!(CSC::from_csr(&a).pattern_hash(), CSC::from_csr(&b).pattern_hash());
+}
+
+
+
+#[cfg(test)]
+fn grid_laplacian(nx: usize) -> CSR {
+    let mut data = vec![];
+    for i in 0..nx*nx {
+        let (r, c) = (i / nx, i % nx);
+        if r > 0 { data.push((i, i - nx, -1.)); }
+        if c > 0 { data.push((i, i - 1, -1.)); }
+        data.push((i, i, 4.));
+        if c + 1 < nx { data.push((i, i + 1, -1.)); }
+        if r + 1 < nx { data.push((i, i + nx, -1.)); }
+    }
+    CSR::from_coo(&COO{data, shape: (nx*nx, nx*nx)})
+}
+
+
+#[cfg(test)]
+fn is_permutation(perm: &[usize], n: usize) -> bool {
+    let mut sorted = perm.to_vec();
+    sorted.sort();
+    sorted == (0..n).collect::<Vec<usize>>()
+}
+
+
+
+#[test]
+fn test_permute_bandwidth() {
+    let a = CSR::from_diagonals(&[&[1., 2.], &[3., 4., 5.], &[6., 7.]], &[-1, 0, 2], (3, 4));
+    let (p, q) = ([2, 0, 1], [3, 1, 0, 2]);
+    let b = a.permute(&p, &q);
+    let (da, db) = (a.to_dense(), b.to_dense());
+    for (i, &p_i) in p.iter().enumerate() {
+        for (j, &q_j) in q.iter().enumerate() {
+            assert_eq!(db.get(i, j), da.get(p_i, q_j));
+        }
+        assert!(b.row_slices(i).0.is_sorted());
+    }
+    assert_eq!(a.bandwidth(), 2);
+    assert_eq!(b.bandwidth(), 3);
+
+    let n = 10;
+    let t = CSR::from_diagonals(&[&[1.], &[-2.], &[1.]], &[-1, 0, 1], (n, n));
+    assert_eq!((t.bandwidth(), t.profile()), (1, n - 1));
+
+    // Arrow matrix: the hub first gives the full lower triangle as envelope, last only one row
+    let mut data: Vec<(usize, usize, f64)> = (0..n).map(|j| (0, j, 1.)).collect();
+    data.extend((1..n).flat_map(|i| [(i, 0, 1.), (i, i, 1.)]));
+    let arrow = CSR::from_coo(&COO{data, shape: (n, n)});
+    assert_eq!(arrow.profile(), n * (n - 1) / 2);
+    let hub_last: Vec<usize> = (1..n).chain([0]).collect();
+    let report = OrderingReport::new(&arrow, &hub_last);
+    assert_eq!(report, OrderingReport{bandwidth_before: n - 1, bandwidth_after: n - 1, profile_before: n * (n - 1) / 2, profile_after: n - 1});
+    assert_eq!(format!("{}", report), "bandwidth 9 -> 9, profile 45 -> 9");
+}
+
+
+
+#[test]
+fn test_orderings() {
+    let nx = 30;
+    let n = nx * nx;
+    let a = grid_laplacian(nx);
+    assert_eq!(a.bandwidth(), nx);
+
+    // Scrambled grid: i -> 7 i mod n
+    let scramble: Vec<usize> = (0..n).map(|i| 7 * i % n).collect();
+    let s = a.permute(&scramble, &scramble);
+    assert!(s.bandwidth() > n / 2);
+
+    let perm = rcm(&s);
+    assert!(is_permutation(&perm, n));
+    let report = OrderingReport::new(&s, &perm);
+    println!("rcm: {}", report);
+    assert!(report.bandwidth_after <= 2 * nx);
+    assert!(report.profile_after < report.profile_before / 10);
+
+    for perm in [amd(&s), nested_dissection(&s, 16), nested_dissection(&s, 1)] {
+        assert!(is_permutation(&perm, n));
+    }
+
+    // Minimum degree eliminates the leaves of the arrow before the hub,
+    // the hub and the last leaf tie
+    let mut data: Vec<(usize, usize, f64)> = (0..n).map(|j| (0, j, 1.)).collect();
+    data.extend((1..n).flat_map(|i| [(i, 0, 1.), (i, i, 1.)]));
+    let arrow = CSR::from_coo(&COO{data, shape: (n, n)});
+    assert!(amd(&arrow)[n - 2..].contains(&0));
+
+    // Nested dissection orders the first separator last: the BFS from a corner of
+    // the grid has the anti-diagonals as levels, the middle one splits the grid
+    let perm = nested_dissection(&a, 16);
+    let sep = &perm[n - nx..];
+    assert!(sep.iter().all(|v| v / nx + v % nx == nx - 1));
+
+    // Two disconnected paths
+    let path = CSR::from_diagonals(&[&[1.], &[2.], &[1.]], &[-1, 0, 1], (5, 5));
+    let two = block(&[&[Some(&path), None], &[None, Some(&path)]]);
+    let perm = rcm(&two);
+    assert!(is_permutation(&perm, 10));
+    assert_eq!(two.permute(&perm, &perm).bandwidth(), 1);
+    assert!(is_permutation(&nested_dissection(&two, 2), 10));
+}
+
+
+
+// y = A x and y = A^T x with the dense A as reference
+#[cfg(test)]
+fn check_operator(op: &dyn LinearOperator, a: &Dense) {
+    assert_eq!(op.shape(), a.shape);
+    let (m, n) = a.shape;
+    let x: Vec<f64> = (0..n).map(|j| j as f64 - 1.5).collect();
+    let mut y = vec![f64::NAN; m];
+    op.apply(&x, &mut y);
+    for (i, y_i) in y.iter().enumerate() {
+        let expected: f64 = (0..n).map(|j| a.get(i, j) * x[j]).sum();
+        assert!(cmp_float(*y_i, expected, 1e-12));
+    }
+
+    let x: Vec<f64> = (0..m).map(|i| 2. - i as f64).collect();
+    let mut y = vec![f64::NAN; n];
+    op.apply_transpose(&x, &mut y);
+    for (j, y_j) in y.iter().enumerate() {
+        let expected: f64 = (0..m).map(|i| a.get(i, j) * x[i]).sum();
+        assert!(cmp_float(*y_j, expected, 1e-12));
+    }
+}
+
+
+
+#[test]
+fn test_linear_operator() {
+    let a = CSR::from_diagonals(&[&[1.], &[2., 3., 4.], &[5., -1.]], &[-1, 0, 2], (3, 4));
+    let b = CSR::from_diagonals(&[&[-2.], &[1.], &[3.]], &[-1, 0, 1], (4, 4));
+    let (a_d, b_d) = (a.to_dense(), b.to_dense());
+
+    check_operator(&a, &a_d);
+    check_operator(&a_d, &a_d);
+    check_operator(&CSC::from_csr(&a), &a_d);
+    check_operator(&Transposed::new(&a), &a.transpose().to_dense());
+
+    // Compositions against the dense results
+    let mut ab = Dense::new_zeros((3, 4));
+    let mut sum = Dense::new_zeros((3, 4));
+    for (i, j, x) in ab.iter_mut() {
+        *x = (0..4).map(|k| a_d.get(i, k) * b_d.get(k, j)).sum();
+    }
+    for (i, j, x) in sum.iter_mut() {
+        *x = a_d.get(i, j) - 0.5 * b_d.get(j, i + 1);
+    }
+    // S B^T with the shift s_{i,i+1} = 1
+    let shift = CSR::from_diagonals(&[&[1.]], &[1], (3, 4));
+    check_operator(&Product::new(&a, &b), &ab);
+    check_operator(&Sum::new(&a, Scaled::new(-0.5, Product::new(&shift, Transposed::new(&b)))), &sum);
+
+    // Symmetric storage and matrix-free operators
+    let s = grid_laplacian(4);
+    let s_d = s.to_dense();
+    check_operator(&SymCSR::from_csr(&s), &s_d);
+    let stencil = MatrixFree::symmetric(16, |x: &[f64], y: &mut [f64]| {
+        for (i, y_i) in y.iter_mut().enumerate() {
+            let (r, c) = (i / 4, i % 4);
+            *y_i = 4. * x[i];
+            if r > 0 { *y_i -= x[i - 4]; }
+            if c > 0 { *y_i -= x[i - 1]; }
+            if c < 3 { *y_i -= x[i + 1]; }
+            if r < 3 { *y_i -= x[i + 4]; }
+        }
+    });
+    check_operator(&stencil, &s_d);
+    let a_free = MatrixFree::new((3, 4), |x: &[f64], y: &mut [f64]| a.apply(x, y), |x: &[f64], y: &mut [f64]| a.apply_transpose(x, y));
+    check_operator(&a_free, &a_d);
+
+    // Blocks, CSR and the column by column default
+    let mut x = Dense::new_zeros((4, 3));
+    for (i, j, x_ij) in x.iter_mut() {
+        *x_ij = (i * 3 + j) as f64 - 4.;
+    }
+    let (mut y, mut y_free) = (Dense::new_zeros((3, 3)), Dense::new_zeros((3, 3)));
+    a.apply_block(&x, &mut y);
+    a_free.apply_block(&x, &mut y_free);
+    for (i, j, y_ij) in y.iter() {
+        let expected: f64 = (0..4).map(|k| a_d.get(i, k) * x.get(k, j)).sum();
+        assert!(cmp_float(*y_ij, expected, 1e-12));
+        assert!(cmp_float(y_free.get(i, j), expected, 1e-12));
+    }
+}
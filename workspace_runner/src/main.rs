@@ -1,6 +1,6 @@
 use std::{cmp::{max, min}, env, fs::{self, File}, io::{stdout, BufRead, Write}, path::{Path, PathBuf}};
-use matrix_base::{COO, CSR, Dense};
-use fakscpu::{dense::DenseProd, sparse::SparseProd};
+use matrix_base::{COO, CSR, Dense, kron};
+use fakscpu::{amg::{Amg, AmgOptions}, context::{ExecContext, ExecOptions}, dense::{product_error, DenseProd, STRASSEN_CUTOFF}, elementwise::SparseElementwise, solvers::SolverOptions, sparse::SparseProd};
 use gpu::WgpuTask;
 
 /// Benchmark matrix multiplication using different libraries
@@ -19,17 +19,17 @@ fn main() {
         folder_path = &args[2];
     }
     let mut results = vec![Vec::new(), Vec::new(), Vec::new()];
-    results[0].push("Matrix1,Matrix2,cuBlas (µs),cuSparse (µs),gpuDense (µs),gpuSparse (µs),Blas (µs),cpuSparseParallel (µs),cpuDenseParallel (µs)".to_string());
-    results[1].push("Matrix1,Matrix2,cuBlas (µs),cuSparse (µs),gpuDense (µs),gpuSparse (µs),Blas (µs),cpuSparseParallel (µs),cpuDenseParallel (µs)".to_string());
-    results[2].push("Matrix1,Matrix2,cuBlas (µs),cuSparse (µs),gpuDense (µs),gpuSparse (µs),Blas (µs),cpuSparseParallel (µs),cpuDenseParallel (µs)".to_string());
+    results[0].push("Matrix1,Matrix2,cuBlas (µs),cuSparse (µs),gpuDense (µs),gpuSparse (µs),Blas (µs),cpuSparseParallel (µs),cpuDenseParallel (µs),cpuStrassen (µs),cpuWinograd (µs)".to_string());
+    results[1].push("Matrix1,Matrix2,cuBlas (µs),cuSparse (µs),gpuDense (µs),gpuSparse (µs),Blas (µs),cpuSparseParallel (µs),cpuDenseParallel (µs),cpuStrassen (µs),cpuWinograd (µs)".to_string());
+    results[2].push("Matrix1,Matrix2,cuBlas (µs),cuSparse (µs),gpuDense (µs),gpuSparse (µs),Blas (µs),cpuSparseParallel (µs),cpuDenseParallel (µs),cpuStrassen (µs),cpuWinograd (µs)".to_string());
 
 
     // search matrices in the folder
     let matrix_paths = get_matrix_paths(folder_path);
 
     // Generate table headers
-    let table_head = &format!("{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}",
-    "Matrix 1", "Matrix 2", "cuBlas (µs)", "cuSparse (µs)", "gpuDense (µs)", "gpuSparse (µs)", "Blas (µs)", "cpuSparseParallel (µs)", "cpuDenseParallel (µs)");
+    let table_head = &format!("{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}{:<20}{:<20}",
+    "Matrix 1", "Matrix 2", "cuBlas (µs)", "cuSparse (µs)", "gpuDense (µs)", "gpuSparse (µs)", "Blas (µs)", "cpuSparseParallel (µs)", "cpuDenseParallel (µs)", "cpuStrassen (µs)", "cpuWinograd (µs)");
 
     println!("\nTotal Times:");
     println!("{}", table_head);
@@ -63,12 +63,12 @@ fn main() {
                 let avg_times = benchmark_matrix(matrix1_path, matrix2_path, repeat_count);
 
                 // generate table rows
-                multiplication_table += &format!("\n{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}",
-                matrix1_name, matrix2_name, avg_times[0].0, avg_times[1].0, avg_times[2].0, avg_times[3].0, avg_times[4].0, avg_times[5].0, avg_times[6].0);
-                overhead_table += &format!("\n{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}",
-                matrix1_name, matrix2_name, avg_times[0].1, avg_times[1].1, avg_times[2].1, avg_times[3].1, avg_times[4].1, avg_times[5].1, avg_times[6].1);
-                total_table += &format!("\n{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}",
-                matrix1_name, matrix2_name, avg_times[0].2, avg_times[1].2, avg_times[2].2, avg_times[3].2, avg_times[4].2, avg_times[5].2, avg_times[6].2);
+                multiplication_table += &format!("\n{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}{:<20}{:<20}",
+                matrix1_name, matrix2_name, avg_times[0].0, avg_times[1].0, avg_times[2].0, avg_times[3].0, avg_times[4].0, avg_times[5].0, avg_times[6].0, avg_times[7].0, avg_times[8].0);
+                overhead_table += &format!("\n{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}{:<20}{:<20}",
+                matrix1_name, matrix2_name, avg_times[0].1, avg_times[1].1, avg_times[2].1, avg_times[3].1, avg_times[4].1, avg_times[5].1, avg_times[6].1, avg_times[7].1, avg_times[8].1);
+                total_table += &format!("\n{:<20}{:<20}{:<15}{:<15}{:<15}{:<15}{:<15}{:<25}{:<25}{:<20}{:<20}",
+                matrix1_name, matrix2_name, avg_times[0].2, avg_times[1].2, avg_times[2].2, avg_times[3].2, avg_times[4].2, avg_times[5].2, avg_times[6].2, avg_times[7].2, avg_times[8].2);
 
                 // generate output rows
                 results[0].push(format!("{},{},{}", matrix1_name, matrix2_name, avg_times.iter().map(|&(x, _, _)| x.to_string()).collect::<Vec<String>>().join(",")));
@@ -93,6 +93,228 @@ fn main() {
         writeln!(file_total, "{}", results[2][index]).expect("Failed to write to total output file");
     }
     println!("exported tables to {}, {}, {}", output_filename_overhead, output_filename_raw_multiplication, output_filename_total);
+
+    benchmark_gemm(&matrix_paths, repeat_count);
+    benchmark_fast_dense(&matrix_paths, repeat_count);
+    benchmark_amg(repeat_count);
+    benchmark_threads(&matrix_paths, repeat_count);
+}
+
+/// Benchmark the blocked dense GEMM of fakscpu (product_dense_par) against OpenBLAS
+/// (blas_dense) on every compatible pair of matrices. Only the multiplication is timed,
+/// the ratio cpuDenseParallel / Blas is the slowdown of the GEMM relative to OpenBLAS.
+fn benchmark_gemm(matrix_paths: &[PathBuf], repeat_count: usize) {
+    let table_head = format!("{:<20}{:<20}{:<25}{:<15}{:<10}{:<15}", "Matrix 1", "Matrix 2", "cpuDenseParallel (µs)", "Blas (µs)", "Ratio", "GFLOP/s");
+    let mut results = vec!["Matrix1,Matrix2,cpuDenseParallel (µs),Blas (µs),Ratio,GFLOP/s".to_string()];
+    println!("\nDense GEMM vs OpenBLAS (ratio cpuDenseParallel / Blas):");
+    println!("{}", table_head);
+
+    for matrix1_path in matrix_paths {
+        for matrix2_path in matrix_paths {
+            if matrix1_path == matrix2_path || get_matrix_shape(matrix1_path).1 != get_matrix_shape(matrix2_path).0 {
+                continue;
+            }
+            let matrix1_name = matrix1_path.file_name().unwrap().to_str().unwrap();
+            let matrix2_name = matrix2_path.file_name().unwrap().to_str().unwrap();
+            let (a, _, a_coo) = import_matrix(matrix1_path);
+            let (b, _, b_coo) = import_matrix(matrix2_path);
+            let a_blas = blas_dense::BlasDense::from_coo(&a_coo);
+            let b_blas = blas_dense::BlasDense::from_coo(&b_coo);
+
+            let mut time_gemm = u128::MAX;
+            let mut time_blas = u128::MAX;
+            for _ in 1..=repeat_count.max(1) {
+                let start = std::time::Instant::now();
+                a.product_dense_par(&b);
+                time_gemm = min(time_gemm, max(1, start.elapsed().as_micros()));
+
+                let start = std::time::Instant::now();
+                a_blas.prod(&b_blas);
+                time_blas = min(time_blas, max(1, start.elapsed().as_micros()));
+            }
+            let ratio = time_gemm as f64 / time_blas as f64;
+            let gflops = 2. * (a.shape.0 * a.shape.1 * b.shape.1) as f64 / time_gemm as f64 / 1e3;
+
+            println!("{:<20}{:<20}{:<25}{:<15}{:<10.2}{:<15.1}", matrix1_name, matrix2_name, time_gemm, time_blas, ratio, gflops);
+            results.push(format!("{},{},{},{},{:.3},{:.2}", matrix1_name, matrix2_name, time_gemm, time_blas, ratio, gflops));
+        }
+    }
+
+    let output_filename = format!("./output/data/{}_result_times_gemm_repeat_count_{}.csv", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"), repeat_count);
+    let mut file = File::create(&output_filename).expect("Failed to create output file");
+    for line in &results {
+        writeln!(file, "{}", line).expect("Failed to write to GEMM output file");
+    }
+    println!("exported GEMM table to {}", output_filename);
+}
+
+// Cutoffs of the Strassen / Strassen-Winograd benchmark
+const FAST_DENSE_CUTOFFS: [usize; 4] = [32, 64, 128, STRASSEN_CUTOFF];
+
+/// Benchmark Strassen and Strassen-Winograd for several cutoffs on every compatible
+/// pair of matrices, with the relative error ||C - A*B||_F / ||A*B||_F against the
+/// classic blocked product.
+fn benchmark_fast_dense(matrix_paths: &[PathBuf], repeat_count: usize) {
+    let table_head = format!("{:<20}{:<20}{:<10}{:<20}{:<20}{:<20}{:<20}", "Matrix 1", "Matrix 2", "Cutoff", "cpuStrassen (µs)", "strassenError", "cpuWinograd (µs)", "winogradError");
+    let mut results = vec!["Matrix1,Matrix2,Cutoff,cpuStrassen (µs),strassenError,cpuWinograd (µs),winogradError".to_string()];
+    println!("\nStrassen / Strassen-Winograd (error relative to the classic product):");
+    println!("{}", table_head);
+
+    for matrix1_path in matrix_paths {
+        for matrix2_path in matrix_paths {
+            if matrix1_path == matrix2_path || get_matrix_shape(matrix1_path).1 != get_matrix_shape(matrix2_path).0 {
+                continue;
+            }
+            let matrix1_name = matrix1_path.file_name().unwrap().to_str().unwrap();
+            let matrix2_name = matrix2_path.file_name().unwrap().to_str().unwrap();
+            let (a, _, _) = import_matrix(matrix1_path);
+            let (b, _, _) = import_matrix(matrix2_path);
+
+            for cutoff in FAST_DENSE_CUTOFFS {
+                let mut time_strassen = u128::MAX;
+                let mut time_winograd = u128::MAX;
+                for _ in 1..=repeat_count.max(1) {
+                    let start = std::time::Instant::now();
+                    a.product_strassen(&b, cutoff);
+                    time_strassen = min(time_strassen, max(1, start.elapsed().as_micros()));
+
+                    let start = std::time::Instant::now();
+                    a.product_winograd(&b, cutoff);
+                    time_winograd = min(time_winograd, max(1, start.elapsed().as_micros()));
+                }
+                let error_strassen = product_error(&a, &b, &a.product_strassen(&b, cutoff));
+                let error_winograd = product_error(&a, &b, &a.product_winograd(&b, cutoff));
+
+                println!("{:<20}{:<20}{:<10}{:<20}{:<20.3e}{:<20}{:<20.3e}", matrix1_name, matrix2_name, cutoff, time_strassen, error_strassen, time_winograd, error_winograd);
+                results.push(format!("{},{},{},{},{:e},{},{:e}", matrix1_name, matrix2_name, cutoff, time_strassen, error_strassen, time_winograd, error_winograd));
+            }
+        }
+    }
+
+    let output_filename = format!("./output/data/{}_result_times_strassen_repeat_count_{}.csv", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"), repeat_count);
+    let mut file = File::create(&output_filename).expect("Failed to create output file");
+    for line in &results {
+        writeln!(file, "{}", line).expect("Failed to write to Strassen output file");
+    }
+    println!("exported Strassen table to {}", output_filename);
+}
+
+// Grid sizes of the AMG benchmark, nx x nx unknowns
+const AMG_GRID_SIZES: [usize; 3] = [256, 512, 1024];
+
+/// Benchmark the AMG setup (strength, aggregation, prolongator smoothing and
+/// the Galerkin products P^T A P) and the V-cycle solve on 2D Poisson problems.
+/// The setup is dominated by SpGEMM on matrices of a real application.
+/// Records whether the V-cycles converged and the final relative residual,
+/// a time of a solve that stopped at the iteration limit is not comparable.
+fn benchmark_amg(repeat_count: usize) {
+    let table_head = format!("{:<15}{:<15}{:<10}{:<15}{:<20}{:<20}{:<10}{:<12}{:<15}", "Unknowns", "nnz", "Levels", "Complexity", "amgSetup (µs)", "amgSolve (µs)", "Cycles", "Converged", "Rel. residual");
+    let mut results = vec!["Unknowns,nnz,Levels,Complexity,amgSetup (µs),amgSolve (µs),Cycles,Converged,Relative residual".to_string()];
+    println!("\nAMG (smoothed aggregation, 2D Poisson):");
+    println!("{}", table_head);
+
+    for nx in AMG_GRID_SIZES {
+        // 5-point Laplacian, T (x) I + I (x) T
+        let t = CSR::from_diagonals(&[&[-1.], &[2.], &[-1.]], &[-1, 0, 1], (nx, nx));
+        let id = CSR::identity(nx);
+        let a = kron(&t, &id).sparse_add_par(1., &kron(&id, &t), 1.);
+        let b = vec![1.; nx * nx];
+
+        let mut times_setup = Vec::with_capacity(repeat_count);
+        let mut times_solve = Vec::with_capacity(repeat_count);
+        let mut levels = 0;
+        let mut complexity = 0.;
+        let mut cycles = 0;
+        let mut converged = true;
+        let mut relative_residual: f64 = 0.;
+        for _ in 1..=repeat_count {
+            let start = std::time::Instant::now();
+            let amg = Amg::new(&a, &AmgOptions::default()).expect("AMG setup failed");
+            times_setup.push(start.elapsed().as_micros());
+
+            let mut x = vec![0.; nx * nx];
+            let start = std::time::Instant::now();
+            let report = amg.solve(&b, &mut x, &SolverOptions{record_history: false, ..Default::default()});
+            times_solve.push(start.elapsed().as_micros());
+
+            levels = amg.n_levels();
+            complexity = amg.operator_complexity();
+            cycles = report.iterations;
+            converged &= report.converged();
+            relative_residual = relative_residual.max(report.relative_residual);
+        }
+        if !converged {
+            eprintln!("AMG did not converge for {} unknowns, relative residual {:e}", nx * nx, relative_residual);
+        }
+        let setup = times_setup.iter().min().copied().unwrap_or(0);
+        let solve = times_solve.iter().min().copied().unwrap_or(0);
+
+        println!("{:<15}{:<15}{:<10}{:<15.2}{:<20}{:<20}{:<10}{:<12}{:<15.3e}", nx * nx, a.values.len(), levels, complexity, setup, solve, cycles, converged, relative_residual);
+        results.push(format!("{},{},{},{:.3},{},{},{},{},{:e}", nx * nx, a.values.len(), levels, complexity, setup, solve, cycles, converged, relative_residual));
+    }
+
+    let output_filename = format!("./output/data/{}_result_times_amg_repeat_count_{}.csv", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"), repeat_count);
+    let mut file = File::create(&output_filename).expect("Failed to create output file");
+    for line in &results {
+        writeln!(file, "{}", line).expect("Failed to write to AMG output file");
+    }
+    println!("exported AMG table to {}", output_filename);
+}
+
+/// Sweep the number of threads 1..=N (N available cores) for the parallel sparse
+/// product of every compatible pair of matrices, in one run with a pinned pool per
+/// thread count. Records the time, the speedup t_1 / t_p and the parallel
+/// efficiency t_1 / (p t_p).
+fn benchmark_threads(matrix_paths: &[PathBuf], repeat_count: usize) {
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let table_head = format!("{:<20}{:<20}{:<10}{:<25}{:<10}{:<12}", "Matrix 1", "Matrix 2", "Threads", "cpuSparseParallel (µs)", "Speedup", "Efficiency");
+    let mut results = vec!["Matrix1,Matrix2,Threads,cpuSparseParallel (µs),Speedup,Efficiency".to_string()];
+    println!("\nThread sweep (cpuSparseParallel):");
+    println!("{}", table_head);
+
+    for matrix1_path in matrix_paths {
+        for matrix2_path in matrix_paths {
+            if matrix1_path == matrix2_path || get_matrix_shape(matrix1_path).1 != get_matrix_shape(matrix2_path).0 {
+                continue;
+            }
+            let matrix1_name = matrix1_path.file_name().unwrap().to_str().unwrap();
+            let matrix2_name = matrix2_path.file_name().unwrap().to_str().unwrap();
+            let (_, a, _) = import_matrix(matrix1_path);
+            let (_, b, _) = import_matrix(matrix2_path);
+
+            let mut time_serial = 0;
+            for threads in 1..=max_threads {
+                // Only the workers of this point are alive while it is timed,
+                // the pool is dropped before the next one is pinned
+                let ctx = ExecContext::new(ExecOptions{threads, pin: cfg!(target_os = "linux"), ..Default::default()}).expect("Failed to create execution context");
+                let time = ctx.install(|| {
+                    (0..repeat_count.max(1)).map(|_| {
+                        let start = std::time::Instant::now();
+                        let c = a.product_sparse_par(&b);
+                        let elapsed = start.elapsed().as_micros();
+                        drop(c);
+                        max(1, elapsed)
+                    }).min().unwrap()
+                });
+                if ctx.threads() == 1 {
+                    time_serial = time;
+                }
+                let speedup = time_serial as f64 / time as f64;
+                let efficiency = speedup / ctx.threads() as f64;
+
+                println!("{:<20}{:<20}{:<10}{:<25}{:<10.2}{:<12.2}", matrix1_name, matrix2_name, ctx.threads(), time, speedup, efficiency);
+                results.push(format!("{},{},{},{},{:.3},{:.3}", matrix1_name, matrix2_name, ctx.threads(), time, speedup, efficiency));
+            }
+        }
+    }
+
+    let output_filename = format!("./output/data/{}_result_times_threads_repeat_count_{}.csv", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"), repeat_count);
+    let mut file = File::create(&output_filename).expect("Failed to create output file");
+    for line in &results {
+        writeln!(file, "{}", line).expect("Failed to write to thread sweep output file");
+    }
+    println!("exported thread sweep table to {}", output_filename);
 }
 
 fn import_matrix(matrix_path: &Path) -> (Dense, CSR, COO) {
@@ -120,6 +342,8 @@ fn benchmark_matrix(matrix1_path: &Path, matrix2_path: &Path, repeat_count: usiz
     let mut times_gpu_dense = Vec::with_capacity(repeat_count);
     let mut times_gpu_sparse = Vec::with_capacity(repeat_count);
     let mut times_blas = Vec::with_capacity(repeat_count);
+    let mut times_cpu_strassen = Vec::with_capacity(repeat_count);
+    let mut times_cpu_winograd = Vec::with_capacity(repeat_count);
 
     // run benchmark for each library
     // cuBLAS (Dense)
@@ -206,10 +430,30 @@ fn benchmark_matrix(matrix1_path: &Path, matrix2_path: &Path, repeat_count: usiz
     }
     print!("{:<25}", times_cpu_dense_parallel.iter().map(|&(_, _, total)| total).min().unwrap_or(0));
     stdout().flush().unwrap();
+
+    //CPU Strassen
+    for _ in 1..=repeat_count {
+        let start = std::time::Instant::now();
+        matrix1_dense.product_strassen(&matrix2_dense, STRASSEN_CUTOFF);
+        let time_total = start.elapsed().as_micros();
+        times_cpu_strassen.push((time_total, 0, time_total));
+    }
+    print!("{:<20}", times_cpu_strassen.iter().map(|&(_, _, total)| total).min().unwrap_or(0));
+    stdout().flush().unwrap();
+
+    //CPU Strassen-Winograd
+    for _ in 1..=repeat_count {
+        let start = std::time::Instant::now();
+        matrix1_dense.product_winograd(&matrix2_dense, STRASSEN_CUTOFF);
+        let time_total = start.elapsed().as_micros();
+        times_cpu_winograd.push((time_total, 0, time_total));
+    }
+    print!("{:<20}", times_cpu_winograd.iter().map(|&(_, _, total)| total).min().unwrap_or(0));
+    stdout().flush().unwrap();
     println!();
 
     // Calculate average times
-    let times_vec: Vec<Vec<(u128, u128, u128)>> = vec![times_cublas, times_cusparse, times_gpu_dense, times_gpu_sparse, times_blas, times_cpu_sparse_parallel, times_cpu_dense_parallel];
+    let times_vec: Vec<Vec<(u128, u128, u128)>> = vec![times_cublas, times_cusparse, times_gpu_dense, times_gpu_sparse, times_blas, times_cpu_sparse_parallel, times_cpu_dense_parallel, times_cpu_strassen, times_cpu_winograd];
     let min_times: Vec<(u128, u128, u128)> = times_vec.into_iter().map(|times| times.iter().fold((u128::max_value(), u128::max_value(), u128::max_value()), |acc, time| (max(1, min(acc.0, time.0)), max(1,min(acc.1, time.1)), max(1,min(acc.2, time.2))))).collect();
     min_times
     // let sum_times: Vec<(u128, u128, u128)> = times_vec.into_iter().map(|times| times.iter().fold((0, 0, 0), |acc, time| (acc.0 + time.0, acc.1 + time.1, acc.2 + time.2))).collect();